@@ -4,4 +4,40 @@ use anchor_lang::prelude::*;
 // https://pyth.network/developers/price-feed-ids#solana-stable
 pub const SOL_USD_FEED_ID: &str = "0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
 pub const USDC_USD_FEED_ID: &str = "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
+// https://docs.switchboard.xyz, the program that owns every Switchboard V2 aggregator account
+pub const SWITCHBOARD_PROGRAM_ID: Pubkey = pubkey!("SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f");
+// https://docs.raydium.io, the Raydium CPMM (CP-Swap) program that owns the pools LP token banks are priced from
+pub const RAYDIUM_CPMM_PROGRAM_ID: Pubkey = pubkey!("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C");
+pub const MAX_PRICE_CONFIDENCE_BPS: u64 = 200; // widest Pyth confidence interval used before falling back, as a share of the price
 pub const MAXIMUM_AGE: u64 = 100; // allow price feed 100 sec old, to avoid stale price feed errors
+pub const USD_EXPONENT: i32 = -6; // all USD values are expressed with 6 decimals
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const USD_PEG_PRICE: u128 = 1_000_000; // $1.00 on the USD_EXPONENT scale, the peg of a USD stablecoin
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+// Mint addresses of the assets supported by the protocol
+pub const SOL_MINT_ADDRESS: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+pub const USDC_MINT_ADDRESS: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+pub const SOL_DECIMALS: u8 = 9;
+pub const USDC_DECIMALS: u8 = 6;
+pub const MAX_POSITIONS: usize = 4; // distinct assets a single user can hold positions in
+pub const USER_VERSION: u8 = 4; // current `User` layout, written by init_user and migrate_user
+pub const MIN_INITIAL_DEPOSIT: u64 = 100_000; // smallest first deposit into an empty bank, in native units
+pub const DEAD_SHARES: u64 = 1_000; // shares of the first deposit locked in the bank forever
+pub const BORROW_INDEX_SCALE: u128 = 1_000_000_000_000_000_000; // borrow index of 1.0, where every bank's index starts
+pub const HEALTH_FACTOR_SCALE: u128 = BPS_DENOMINATOR as u128; // health factor of 1.0, the liquidation boundary
+pub const DYNAMIC_BONUS_FLOOR_HEALTH_BPS: u64 = 8_000; // health factor at which a dynamic liquidation bonus reaches its max
+pub const LP_MIN_TWAP_WINDOW_SECONDS: i64 = 600; // shortest window an LP pool's price must be averaged over to be trusted
+pub const LP_MAX_TWAP_DEVIATION_BPS: u64 = 200; // furthest an LP pool's spot price may stray from its time-weighted price
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn mint_constants_match_the_on_chain_mints() {
+        assert_eq!(SOL_MINT_ADDRESS, anchor_spl::token::spl_token::native_mint::ID);
+        assert_eq!(SOL_MINT_ADDRESS, Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap());
+        assert_eq!(USDC_MINT_ADDRESS, Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap());
+    }
+}
@@ -1,3 +1,5 @@
+use anchor_lang::prelude::*;
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Amount cannot be zero.")]
@@ -6,4 +8,142 @@ pub enum ErrorCode {
     MathOverflow,
     #[msg("This asset is not supported by the protocol.")]
     UnsupportedAsset,
-}
\ No newline at end of file
+    #[msg("Insufficient collateral to borrow the requested amount.")]
+    InsufficientCollateral,
+    #[msg("User does not have enough deposit shares.")]
+    InsufficientShares,
+    #[msg("User does not have enough deposited funds.")]
+    InsufficientFunds,
+    #[msg("Action would leave the position unhealthy.")]
+    PositionUnhealthy,
+    #[msg("Position is healthy and cannot be liquidated.")]
+    PositionHealthy,
+    #[msg("User has no outstanding debt in this asset.")]
+    NothingToRepay,
+    #[msg("Signer is not authorized to perform this action.")]
+    Unauthorized,
+    #[msg("Liquidation threshold cannot exceed 100%.")]
+    InvalidLiquidationThreshold,
+    #[msg("Max LTV cannot exceed the liquidation threshold.")]
+    InvalidMaxLtv,
+    #[msg("Liquidation bonus cannot exceed 50%.")]
+    InvalidLiquidationBonus,
+    #[msg("Liquidation close factor cannot exceed 100%.")]
+    InvalidLiquidationCloseFactor,
+    #[msg("Protocol fee cannot exceed 100%.")]
+    InvalidProtocolFee,
+    #[msg("The protocol is paused.")]
+    ProtocolPaused,
+    #[msg("Deposit would exceed the bank's supply cap.")]
+    SupplyCapExceeded,
+    #[msg("Borrow would exceed the bank's borrow cap.")]
+    BorrowCapExceeded,
+    #[msg("Reserve factor cannot exceed 100%.")]
+    InvalidReserveFactor,
+    #[msg("Not enough accrued reserves.")]
+    InsufficientReserves,
+    #[msg("Borrowed and collateral mints must differ.")]
+    IdenticalMints,
+    #[msg("The user has no collateral in this mint to seize.")]
+    InsufficientCollateralToSeize,
+    #[msg("Flash loan must be repaid later in the same transaction.")]
+    FlashLoanNotRepaid,
+    #[msg("Flash loan fee cannot exceed 100%.")]
+    InvalidFlashFee,
+    #[msg("Not allowed while the position is in isolated-collateral mode.")]
+    IsolatedModeViolation,
+    #[msg("Repay amount exceeds the close factor for this asset's debt.")]
+    CloseFactorExceeded,
+    #[msg("The user has no position in this asset.")]
+    PositionNotFound,
+    #[msg("The user already holds the maximum number of positions.")]
+    TooManyPositions,
+    #[msg("A bank for one of the user's positions was not provided.")]
+    MissingBank,
+    #[msg("The mint uses a Token-2022 extension that is unsafe for a lending vault.")]
+    UnsafeMintExtension,
+    #[msg("A user cannot liquidate their own position.")]
+    SelfLiquidation,
+    #[msg("The protocol must be paused for this action.")]
+    ProtocolNotPaused,
+    #[msg("The bank does not have enough unborrowed liquidity for this amount.")]
+    InsufficientLiquidity,
+    #[msg("The deposit is too small to mint any shares.")]
+    DepositTooSmall,
+    #[msg("The first deposit into a bank is below the minimum initial deposit.")]
+    InitialDepositTooSmall,
+    #[msg("The price feed id is not a valid Pyth feed id.")]
+    InvalidPriceFeedId,
+    #[msg("The bank or vault account does not belong to the given mint.")]
+    InvalidVault,
+    #[msg("The latest deposit into this asset is still within the bank's withdrawal cooldown.")]
+    WithdrawCooldownActive,
+    #[msg("The price feed is missing from the price update or older than the bank allows.")]
+    StalePriceFeed,
+    #[msg("Origination fee cannot exceed 100%.")]
+    InvalidOriginationFee,
+    #[msg("No price update was passed for an asset that needs to be valued.")]
+    MissingPriceFeed,
+    #[msg("The user account still holds a deposit or a borrow.")]
+    AccountNotEmpty,
+    #[msg("The oracle reported a zero or negative price.")]
+    InvalidOraclePrice,
+    #[msg("The bank has no tokens or shares to convert against.")]
+    BankEmpty,
+    #[msg("The secondary price feed is not a Switchboard aggregator.")]
+    InvalidSecondaryFeed,
+    #[msg("The primary and secondary oracles disagree by more than the bank allows.")]
+    OracleDeviation,
+    #[msg("Oracle deviation bound cannot exceed 100%.")]
+    InvalidOracleDeviation,
+    #[msg("The price moved too far from the last accepted price. Try again once it settles.")]
+    PriceDeviationTooHigh,
+    #[msg("Pass exactly one deposit amount per asset.")]
+    DepositLengthMismatch,
+    #[msg("The mint or vault is owned by a different token program than the one passed.")]
+    TokenProgramMismatch,
+    #[msg("Borrowing is disabled for this asset.")]
+    BorrowingDisabled,
+    #[msg("The user account is already on the current layout.")]
+    UserAlreadyMigrated,
+    #[msg("The debt left in this asset would be below the bank's minimum borrow amount.")]
+    BorrowTooSmall,
+    #[msg("The price update is for a different feed than the bank's.")]
+    WrongPriceFeed,
+    #[msg("The borrow would leave the position within the bank's safety buffer of liquidation.")]
+    SafetyBufferBreached,
+    #[msg("Safety buffer cannot exceed 100%.")]
+    InvalidSafetyBuffer,
+    #[msg("The liquidator's token account doesn't hold enough to make the repayment.")]
+    InsufficientLiquidatorFunds,
+    #[msg("Repay the outstanding debt before withdrawing all of this collateral.")]
+    OutstandingDebt,
+    #[msg("Liquidation auctions are turned off.")]
+    AuctionModeDisabled,
+    #[msg("A liquidation auction must last at least one second.")]
+    InvalidAuctionDuration,
+    #[msg("The token account is not owned by the signer or has an active delegate.")]
+    UnexpectedTokenAccountState,
+    #[msg("The user already owes the maximum number of distinct assets.")]
+    TooManyBorrowedAssets,
+    #[msg("The liquidation would leave the position further underwater than before.")]
+    LiquidationWorsensHealth,
+    #[msg("Division by zero.")]
+    DivideByZero,
+    #[msg("Converting between a price and a token amount overflowed.")]
+    PriceConversionOverflow,
+    #[msg("Converting between tokens and shares overflowed.")]
+    ShareConversionOverflow,
+    #[msg("A value is too large to represent.")]
+    ValueOverflow,
+    #[msg("The LP pool account is malformed or is not the pool of this bank's LP token.")]
+    InvalidLpPool,
+    #[msg("The LP pool's price is averaged over too short a window to be trusted.")]
+    LpWindowTooShort,
+    #[msg("The mint's decimals do not match the decimals recorded in its bank.")]
+    DecimalsMismatch,
+    #[msg("The LP pool's spot price is too far from its time-weighted price.")]
+    LpPriceDeviation,
+    #[msg("A bank whose debt has to be written off was passed read-only.")]
+    BankNotWritable,
+}
@@ -0,0 +1,223 @@
+use anchor_lang::prelude::*;
+use crate::state::{Bank, CollateralPricer, Config};
+
+// Field types are part of the indexer-facing interface; add new fields at the end rather than changing existing ones.
+
+#[event]
+pub struct UserCreatedEvent {
+    pub user: Pubkey,
+    pub user_account: Pubkey,
+    /// The user's place in creation order, for indexers that can't enumerate `User` PDAs.
+    pub position_nonce: u64,
+    pub created_at: u64,
+}
+
+#[event]
+pub struct DepositEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    /// The bank's totals after the deposit.
+    pub total_deposits: u64,
+    pub total_deposit_shares: u64,
+}
+
+#[event]
+pub struct WithdrawEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    /// The bank's totals after the withdrawal.
+    pub total_deposits: u64,
+    pub total_deposit_shares: u64,
+}
+
+#[event]
+pub struct BorrowEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    /// The bank's totals after the borrow.
+    pub total_borrows: u64,
+    pub total_borrow_shares: u64,
+}
+
+#[event]
+pub struct RepayEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    /// The bank's totals after the repayment.
+    pub total_borrows: u64,
+    pub total_borrow_shares: u64,
+}
+
+#[event]
+pub struct LiquidateEvent {
+    pub liquidator: Pubkey,
+    pub user: Pubkey,
+    pub borrowed_mint: Pubkey,
+    pub collateral_mint: Pubkey,
+    /// Debt repaid by the liquidator, in native units of the borrowed mint.
+    pub repay_amount: u64,
+    pub repay_shares: u64,
+    /// Collateral seized by the liquidator, in native units of the collateral mint.
+    pub seize_amount: u64,
+    pub seize_shares: u64,
+    /// The borrowed bank's totals after the liquidation.
+    pub total_borrows: u64,
+    pub total_borrow_shares: u64,
+    /// The collateral bank's totals after the liquidation.
+    pub total_deposits: u64,
+    pub total_deposit_shares: u64,
+    /// Debt the user could no longer cover, written off against the borrowed bank's depositors.
+    pub bad_debt: u64,
+}
+
+#[event]
+pub struct LiquidateAllEvent {
+    pub liquidator: Pubkey,
+    pub user: Pubkey,
+    /// Asset A then asset B. Every array below is indexed the same way.
+    pub mints: [Pubkey; 2],
+    /// Debt repaid by the liquidator, in native units of each mint.
+    pub repay_amounts: [u64; 2],
+    pub repay_shares: [u64; 2],
+    /// Collateral seized by the liquidator, in native units of each mint.
+    pub seize_amounts: [u64; 2],
+    pub seize_shares: [u64; 2],
+    /// Debt the user could no longer cover, written off against each bank's depositors.
+    pub bad_debt: [u64; 2],
+}
+
+/// A bank's admin-set parameters, as carried by the config audit events. Percentages are whole
+/// numbers and rates basis points, exactly as stored on the `Bank`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BankSettings {
+    pub liquidation_threshold: u64,
+    pub liquidation_bonus: u64,
+    pub liquidation_close_factor: u64,
+    pub max_ltv: u64,
+    pub max_price_age_seconds: u64,
+    pub supply_cap: u64,
+    pub borrow_cap: u64,
+    pub min_borrow_amount: u64,
+    pub reserve_factor_bps: u16,
+    pub flash_fee_bps: u16,
+    pub deposit_cooldown_seconds: u64,
+    pub origination_fee_bps: u16,
+    pub secondary_feed: Pubkey,
+    pub max_oracle_deviation_bps: u16,
+    pub max_price_deviation_bps: u16,
+    pub price_deviation_window_seconds: u64,
+    pub dynamic_bonus: bool,
+    pub min_liquidation_bonus: u64,
+    pub max_liquidation_bonus: u64,
+    pub borrowing_enabled: bool,
+    pub usable_as_collateral: bool,
+    pub peg_deviation_bps: u16,
+    pub max_liquidation_value_usd: u128,
+    pub accrual_interval_seconds: u64,
+    pub safety_buffer_bps: u16,
+    pub collateral_pricer: CollateralPricer,
+}
+
+impl From<&Bank> for BankSettings {
+    fn from(bank: &Bank) -> Self {
+        Self {
+            liquidation_threshold: bank.liquidation_threshold,
+            liquidation_bonus: bank.liquidation_bonus,
+            liquidation_close_factor: bank.liquidation_close_factor,
+            max_ltv: bank.max_ltv,
+            max_price_age_seconds: bank.max_price_age_seconds,
+            supply_cap: bank.supply_cap,
+            borrow_cap: bank.borrow_cap,
+            min_borrow_amount: bank.min_borrow_amount,
+            reserve_factor_bps: bank.reserve_factor_bps,
+            flash_fee_bps: bank.flash_fee_bps,
+            deposit_cooldown_seconds: bank.deposit_cooldown_seconds,
+            origination_fee_bps: bank.origination_fee_bps,
+            secondary_feed: bank.secondary_feed,
+            max_oracle_deviation_bps: bank.max_oracle_deviation_bps,
+            max_price_deviation_bps: bank.max_price_deviation_bps,
+            price_deviation_window_seconds: bank.price_deviation_window_seconds,
+            dynamic_bonus: bank.dynamic_bonus,
+            min_liquidation_bonus: bank.min_liquidation_bonus,
+            max_liquidation_bonus: bank.max_liquidation_bonus,
+            borrowing_enabled: bank.borrowing_enabled,
+            usable_as_collateral: bank.usable_as_collateral,
+            peg_deviation_bps: bank.peg_deviation_bps,
+            max_liquidation_value_usd: bank.max_liquidation_value_usd,
+            accrual_interval_seconds: bank.accrual_interval_seconds,
+            safety_buffer_bps: bank.safety_buffer_bps,
+            collateral_pricer: bank.collateral_pricer,
+        }
+    }
+}
+
+#[event]
+pub struct BankConfigUpdatedEvent {
+    /// The bank authority that made the change.
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub before: BankSettings,
+    pub after: BankSettings,
+}
+
+/// The config's admin-set fields, as carried by `ConfigUpdatedEvent`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConfigSettings {
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>,
+    pub fee_recipient: Pubkey,
+    pub min_liquidation_incentive_usd: u128,
+    pub auction_mode: bool,
+    pub auction_duration_seconds: u64,
+    pub quote_feed_id: [u8; 32],
+    pub max_borrowed_assets: u8,
+}
+
+impl From<&Config> for ConfigSettings {
+    fn from(config: &Config) -> Self {
+        Self {
+            admin: config.admin,
+            pending_admin: config.pending_admin,
+            fee_recipient: config.fee_recipient,
+            min_liquidation_incentive_usd: config.min_liquidation_incentive_usd,
+            auction_mode: config.auction_mode,
+            auction_duration_seconds: config.auction_duration_seconds,
+            quote_feed_id: config.quote_feed_id,
+            max_borrowed_assets: config.max_borrowed_assets,
+        }
+    }
+}
+
+#[event]
+pub struct ConfigUpdatedEvent {
+    /// The signer that made the change: the admin, or the new admin accepting a transfer.
+    pub admin: Pubkey,
+    pub before: ConfigSettings,
+    pub after: ConfigSettings,
+}
+
+#[event]
+pub struct PausedChangedEvent {
+    pub admin: Pubkey,
+    pub old_paused: bool,
+    pub new_paused: bool,
+}
+
+#[event]
+pub struct AssetRegisteredEvent {
+    /// The signer that created the bank, who becomes its authority.
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub bank: Pubkey,
+    pub price_feed_id: [u8; 32],
+    /// The bank's parameters as created.
+    pub settings: BankSettings,
+}
@@ -0,0 +1,1101 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::{Price, PriceUpdateV2};
+use crate::state::{Bank, CollateralPricer, User};
+use crate::error::ErrorCode;
+use crate::constants::{BPS_DENOMINATOR, DYNAMIC_BONUS_FLOOR_HEALTH_BPS, HEALTH_FACTOR_SCALE, USD_EXPONENT, USD_PEG_PRICE};
+use crate::interest::accrue_interest;
+use crate::oracle::{check_price_deviation, fetch_lp_price, fetch_price_with_fallback};
+use crate::math::{amount_for_shares, amount_for_shares_ceil, debt_at_index, mul_div_floor, normalize_price, token_value_usd, weighted_sum};
+
+/// Prices of the supported assets by mint, normalized to `USD_EXPONENT`.
+#[derive(Clone, Debug, Default)]
+pub struct Prices {
+    entries: Vec<(Pubkey, u128)>,
+    /// USD price of the asset health is valued in, or `None` to value it in USD.
+    quote: Option<u128>,
+}
+
+impl Prices {
+    /// Reads the price of each of `banks`' assets from whichever of `price_updates` carries the feed id
+    /// stored on the bank, rejecting prices older than `max_age` seconds. A bank whose Switchboard
+    /// aggregator is among `accounts` falls back to it when its Pyth price is unusable. An LP token bank
+    /// is priced instead from the updates for its two reserve feeds and its pool's accounts among `accounts`.
+    ///
+    /// A bank with no matching update is left unpriced, so a feed only has to be passed when a position
+    /// in its asset is actually valued. `for_mint` reports `MissingPriceFeed` for it otherwise. An update
+    /// whose feed belongs to none of `banks` is rejected with `WrongPriceFeed`, rather than ignored.
+    ///
+    /// A non-zero `quote_feed_id` is the config's quote asset, whose update must always be passed. `in_quote`
+    /// then converts USD prices into it.
+    pub fn load(
+        price_updates: &[&PriceUpdateV2],
+        accounts: &[AccountInfo],
+        clock: &Clock,
+        max_age: u64,
+        banks: &[&Bank],
+        quote_feed_id: &[u8; 32],
+    ) -> Result<Self> {
+        let quoted = *quote_feed_id != [0; 32];
+        for update in price_updates {
+            let feed_id = &update.price_message.feed_id;
+            let is_quote = quoted && feed_id == quote_feed_id;
+            if !is_quote && !banks.iter().any(|bank| bank.is_priced_by(feed_id)) {
+                msg!("Price update for feed 0x{} does not belong to any bank in this instruction", feed_hex(feed_id));
+                return err!(ErrorCode::WrongPriceFeed);
+            }
+        }
+
+        let update_for = |feed_id: &[u8; 32]| price_updates.iter().find(|update| update.price_message.feed_id == *feed_id);
+        let mut prices = Self::default();
+        for bank in banks {
+            let price = match bank.collateral_pricer {
+                CollateralPricer::DirectFeed => {
+                    let Some(price_update) = update_for(&bank.price_feed_id) else {
+                        continue;
+                    };
+                    let secondary = accounts.iter().find(|info| *info.key == bank.secondary_feed);
+                    fetch_price_with_fallback(price_update, secondary, bank, max_age, clock)?.price
+                }
+                CollateralPricer::LpToken { reserve_a_feed, reserve_b_feed, pool } => {
+                    let (Some(reserve_a_update), Some(reserve_b_update), true) =
+                        (update_for(&reserve_a_feed), update_for(&reserve_b_feed), accounts.iter().any(|info| *info.key == pool))
+                    else {
+                        continue;
+                    };
+                    fetch_lp_price(bank, reserve_a_update, reserve_b_update, accounts, max_age, clock)?
+                }
+            };
+            prices.set(bank.mint_address, price);
+        }
+
+        if quoted {
+            let Some(quote_update) = price_updates.iter().find(|update| update.price_message.feed_id == *quote_feed_id) else {
+                msg!("No price update was passed for the quote feed 0x{}", feed_hex(quote_feed_id));
+                return err!(ErrorCode::MissingPriceFeed);
+            };
+            let quote = fetch_price(quote_update, quote_feed_id, max_age, clock)?;
+            prices.quote = Some(normalize_price(quote.price.into(), quote.exponent, USD_EXPONENT)?);
+        }
+        Ok(prices)
+    }
+
+    /// Converts a USD price into the quote asset, on the same `USD_EXPONENT` scale. Prices are returned
+    /// as they are when health is valued in USD.
+    pub fn in_quote(&self, usd_price: u128) -> Result<u128> {
+        match self.quote {
+            Some(quote) => Ok(usd_price
+                .checked_mul(USD_PEG_PRICE)
+                .ok_or(ErrorCode::PriceConversionOverflow)?
+                .checked_div(quote)
+                .ok_or(ErrorCode::DivideByZero)?),
+            None => Ok(usd_price),
+        }
+    }
+
+    /// Sets the price of the asset with the given mint, replacing any previous one.
+    pub fn set(&mut self, mint: Pubkey, price: u128) {
+        match self.entries.iter_mut().find(|(key, _)| *key == mint) {
+            Some(entry) => entry.1 = price,
+            None => self.entries.push((mint, price)),
+        }
+    }
+
+    /// Rejects the prices of any of `banks` that tripped the bank's price-deviation breaker.
+    pub fn check_deviation(&self, banks: &[&Bank], now: i64) -> Result<()> {
+        for bank in banks {
+            if let Some(price) = self.get(&bank.mint_address) {
+                check_price_deviation(bank, price, now)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records `bank`'s price, if it was loaded, as the reference for its price-deviation breaker.
+    /// This is why instructions take every bank they price as `mut`, even one like `other_bank`
+    /// whose settings they only read.
+    pub fn record(&self, bank: &mut Bank, now: i64) {
+        if let Some(price) = self.get(&bank.mint_address) {
+            bank.record_price(price, now);
+        }
+    }
+
+    fn get(&self, mint: &Pubkey) -> Option<u128> {
+        self.entries.iter().find(|(key, _)| key == mint).map(|(_, price)| *price)
+    }
+
+    /// Returns the price of the asset with the given mint.
+    pub fn for_mint(&self, mint: &Pubkey) -> Result<u128> {
+        self.get(mint).ok_or_else(|| {
+            msg!("No price update was passed for {}", mint);
+            error!(ErrorCode::MissingPriceFeed)
+        })
+    }
+}
+
+/// Reads the price for `feed_id` from `price_update`, rejecting it if it is older than `max_age` seconds.
+///
+/// An update for another feed is rejected with `WrongPriceFeed` before anything is read from it. Any
+/// other failure from Pyth, whether the price is too old or unverified, surfaces as `StalePriceFeed`,
+/// with the feed id and Pyth's own error logged alongside it. A fresh price of zero or below is rejected
+/// with `InvalidOraclePrice`.
+pub fn fetch_price(price_update: &PriceUpdateV2, feed_id: &[u8; 32], max_age: u64, clock: &Clock) -> Result<Price> {
+    if price_update.price_message.feed_id != *feed_id {
+        msg!(
+            "Price update is for feed 0x{}, expected 0x{}",
+            feed_hex(&price_update.price_message.feed_id),
+            feed_hex(feed_id)
+        );
+        return err!(ErrorCode::WrongPriceFeed);
+    }
+    let price = price_update.get_price_no_older_than(clock, max_age, feed_id).map_err(|err| {
+        msg!("No usable price for feed 0x{}: {}", feed_hex(feed_id), err);
+        error!(ErrorCode::StalePriceFeed)
+    })?;
+    if price.price <= 0 {
+        msg!("Feed 0x{} reported a price of {}", feed_hex(feed_id), price.price);
+        return err!(ErrorCode::InvalidOraclePrice);
+    }
+    Ok(price)
+}
+
+/// Formats a Pyth feed id as hex for logs.
+pub(crate) fn feed_hex(feed_id: &[u8; 32]) -> String {
+    feed_id.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// A user's whole portfolio valued on the `USD_EXPONENT` scale, in the prices' quote asset (USD unless the
+/// config sets another).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealthFactor {
+    /// Value of every deposit, unweighted.
+    pub total_collateral_value: u128,
+    /// Value of every deposit, each weighted by its own bank's liquidation threshold.
+    pub weighted_collateral_value: u128,
+    /// Value of every deposit, each weighted by its own bank's max LTV.
+    pub borrowing_power: u128,
+    /// Value of every outstanding borrow.
+    pub total_debt_value: u128,
+}
+
+/// The one comparison every health gate goes through: `collateral` covers `debt` exactly when
+/// `collateral * HEALTH_FACTOR_SCALE / debt >= HEALTH_FACTOR_SCALE`, so a position sitting exactly on the
+/// boundary is healthy. Comparing the values directly gives the same answer without the division's rounding.
+pub fn is_healthy(collateral: u128, debt: u128) -> bool {
+    collateral >= debt
+}
+
+impl HealthFactor {
+    /// A position is healthy while its weighted collateral still covers its debt.
+    pub fn is_healthy(&self) -> bool {
+        is_healthy(self.weighted_collateral_value, self.total_debt_value)
+    }
+
+    /// Borrowing power left after existing debt, i.e. how much more value may still be borrowed.
+    pub fn remaining_borrowing_power(&self) -> u128 {
+        self.borrowing_power.saturating_sub(self.total_debt_value)
+    }
+
+    /// Debt value the position can still take on while staying `buffer_bps` of its weighted collateral
+    /// clear of the liquidation point.
+    pub fn buffered_borrowing_power(&self, buffer_bps: u16) -> Result<u128> {
+        let buffered_collateral = self.weighted_collateral_value
+            .checked_mul(BPS_DENOMINATOR.saturating_sub(buffer_bps as u64) as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+        Ok(buffered_collateral.saturating_sub(self.total_debt_value))
+    }
+
+    /// How much the debt exceeds the weighted collateral, i.e. how far an unhealthy position is underwater.
+    pub fn shortfall(&self) -> u128 {
+        self.total_debt_value.saturating_sub(self.weighted_collateral_value)
+    }
+
+    /// Whether a position that went from `before` to `self` is no further underwater. Shortfall is the
+    /// measure rather than `health_factor_bps`: a sound partial liquidation of a position below
+    /// `(1 + bonus) * threshold` still lowers that ratio, but only a seize whose weighted value exceeds the
+    /// debt repaid grows the shortfall.
+    pub fn is_no_worse_than(&self, before: &HealthFactor) -> bool {
+        self.shortfall() <= before.shortfall()
+    }
+
+    /// Weighted collateral over debt scaled by `HEALTH_FACTOR_SCALE`, so 10_000 is the liquidation boundary.
+    /// A position with no debt reports `u128::MAX`.
+    pub fn health_factor_bps(&self) -> Result<u128> {
+        if self.total_debt_value == 0 {
+            return Ok(u128::MAX);
+        }
+        Ok(self.weighted_collateral_value
+            .checked_mul(HEALTH_FACTOR_SCALE)
+            .ok_or(ErrorCode::MathOverflow)?
+            / self.total_debt_value)
+    }
+}
+
+/// The bonus, in basis points of the repaid value, a liquidator earns seizing `bank`'s collateral from a
+/// position at `health`.
+///
+/// A fixed bonus over-rewards liquidating a barely unhealthy position and under-rewards a deeply
+/// underwater one. With `dynamic_bonus` set, the bonus instead grows linearly from
+/// `min_liquidation_bonus` just below a health factor of 1.0 to `max_liquidation_bonus` at
+/// `DYNAMIC_BONUS_FLOOR_HEALTH_BPS`, and stays there below it.
+pub fn liquidation_bonus_bps(bank: &Bank, health: &HealthFactor) -> Result<u64> {
+    if !bank.dynamic_bonus {
+        return bank.liquidation_bonus.checked_mul(100).ok_or(error!(ErrorCode::MathOverflow));
+    }
+
+    let full_range = BPS_DENOMINATOR - DYNAMIC_BONUS_FLOOR_HEALTH_BPS;
+    let shortfall_bps = HEALTH_FACTOR_SCALE.saturating_sub(health.health_factor_bps()?);
+    let shortfall_bps = shortfall_bps.min(full_range as u128) as u64;
+
+    let min_bps = bank.min_liquidation_bonus.checked_mul(100).ok_or(ErrorCode::MathOverflow)?;
+    let spread_bps = bank.max_liquidation_bonus
+        .checked_sub(bank.min_liquidation_bonus)
+        .ok_or(ErrorCode::InvalidLiquidationBonus)?
+        .checked_mul(100)
+        .ok_or(ErrorCode::MathOverflow)?;
+    min_bps.checked_add(mul_div_floor(spread_bps, shortfall_bps, full_range)?).ok_or(error!(ErrorCode::MathOverflow))
+}
+
+/// The bonus, in basis points of the repaid value, a liquidator earns seizing `bank`'s collateral
+/// `elapsed` seconds into a liquidation auction lasting `duration` seconds.
+///
+/// The bonus ramps linearly from `min_liquidation_bonus` as the auction starts to `max_liquidation_bonus`
+/// once `duration` has passed, so the borrower gets a window before the full bonus is on offer and
+/// liquidators compete on how little of it they accept.
+pub fn auction_bonus_bps(bank: &Bank, elapsed: u64, duration: u64) -> Result<u64> {
+    let min_bps = bank.min_liquidation_bonus.checked_mul(100).ok_or(ErrorCode::MathOverflow)?;
+    let spread_bps = bank.max_liquidation_bonus
+        .checked_sub(bank.min_liquidation_bonus)
+        .ok_or(ErrorCode::InvalidLiquidationBonus)?
+        .checked_mul(100)
+        .ok_or(ErrorCode::MathOverflow)?;
+    if elapsed >= duration {
+        return min_bps.checked_add(spread_bps).ok_or(error!(ErrorCode::MathOverflow));
+    }
+    min_bps.checked_add(mul_div_floor(spread_bps, elapsed, duration)?).ok_or(error!(ErrorCode::MathOverflow))
+}
+
+/// Values `user`'s deposits and borrows at `prices`, converted into their quote asset.
+///
+/// Every non-empty position needs its bank in `banks`, which supplies the asset's decimals, liquidation
+/// threshold and max LTV. An isolated position counts only its `isolated_mint` collateral, and deposits in
+/// a bank that isn't `usable_as_collateral` add to `total_collateral_value` but to neither weighted sum.
+/// A depegged stablecoin is valued conservatively, see `collateral_price` and `debt_price`.
+///
+/// Values are accumulated in u128 with checked math throughout, so a portfolio too large to value fails
+/// with `ValueOverflow` instead of wrapping into a small number that would look healthy.
+pub fn compute_account_health(user: &User, prices: &Prices, banks: &[&Bank]) -> Result<HealthFactor> {
+    let mut total_collateral_value: u128 = 0;
+    let mut total_debt_value: u128 = 0;
+    let mut thresholds = Vec::with_capacity(user.positions.len());
+    let mut ltvs = Vec::with_capacity(user.positions.len());
+
+    for position in user.positions.iter().filter(|position| position.deposited_shares > 0 || position.borrowed_shares > 0) {
+        let bank = banks
+            .iter()
+            .find(|bank| bank.mint_address == position.mint)
+            .ok_or(ErrorCode::MissingBank)?;
+        let price = prices.for_mint(&position.mint)?;
+
+        if !user.isolated || user.isolated_mint == position.mint {
+            let deposit = current_deposit(user, bank, &position.mint)?;
+            let collateral_value = token_value_usd(prices.in_quote(collateral_price(bank, price))?, deposit, bank.decimals)?;
+            total_collateral_value = total_collateral_value.checked_add(collateral_value).ok_or(ErrorCode::ValueOverflow)?;
+            // Deposits of an asset the operators stopped counting as collateral are still the user's,
+            // but back none of their debt.
+            if bank.usable_as_collateral {
+                thresholds.push((collateral_value, bank.liquidation_threshold));
+                ltvs.push((collateral_value, bank.max_ltv));
+            }
+        }
+
+        let debt_value = token_value_usd(prices.in_quote(debt_price(bank, price))?, current_debt(user, bank, &position.mint)?, bank.decimals)?;
+        total_debt_value = total_debt_value.checked_add(debt_value).ok_or(ErrorCode::ValueOverflow)?;
+    }
+
+    Ok(HealthFactor {
+        total_collateral_value,
+        weighted_collateral_value: weighted_sum(&thresholds)?,
+        borrowing_power: weighted_sum(&ltvs)?,
+        total_debt_value,
+    })
+}
+
+/// Whether `bank` is a pegged stablecoin whose oracle `price` has drifted further from $1 than its
+/// `peg_deviation_bps` allows.
+fn is_depegged(bank: &Bank, price: u128) -> bool {
+    bank.peg_deviation_bps > 0
+        && price.abs_diff(USD_PEG_PRICE).saturating_mul(BPS_DENOMINATOR as u128)
+            > (bank.peg_deviation_bps as u128).saturating_mul(USD_PEG_PRICE)
+}
+
+/// The price deposits of `bank`'s asset are valued at. A depegged stablecoin counts at the lower of its
+/// oracle price and $1, so collateral never gains value from a depeg.
+pub fn collateral_price(bank: &Bank, price: u128) -> u128 {
+    if is_depegged(bank, price) { price.min(USD_PEG_PRICE) } else { price }
+}
+
+/// The price debt in `bank`'s asset is valued at. A depegged stablecoin counts at the higher of its
+/// oracle price and $1, so debt never shrinks because of a depeg.
+pub fn debt_price(bank: &Bank, price: u128) -> u128 {
+    if is_depegged(bank, price) { price.max(USD_PEG_PRICE) } else { price }
+}
+
+/// Returns what `user`'s deposit in `mint` redeems for right now, interest earned included.
+///
+/// This is the position's share of `bank.total_deposits`, rounded down, not the principal recorded in
+/// `deposited`: interest raises it, and a bad-debt write-off lowers it.
+/// `bank` should already be accrued to now.
+pub fn current_deposit(user: &User, bank: &Bank, mint: &Pubkey) -> Result<u64> {
+    let Some(position) = user.position(mint).filter(|position| position.deposited_shares > 0) else {
+        return Ok(0);
+    };
+    amount_for_shares(position.deposited_shares, bank.total_deposits, bank.total_deposit_shares)
+}
+
+/// Returns what `user` owes in `mint` right now, interest included.
+///
+/// The position's `borrowed` debt grows by the bank's `borrow_index` since it was recorded:
+/// `borrowed * bank.borrow_index / position.borrow_index`, rounded up in the bank's favour. This
+/// depends only on the user's own snapshot, so writing off someone else's bad debt can't shift it.
+/// A position carried over by `migrate_user` from before the index existed falls back to the user's
+/// share of `bank.total_borrows`, until their next borrow or repayment records a snapshot.
+/// `bank` should already be accrued to now.
+pub fn current_debt(user: &User, bank: &Bank, mint: &Pubkey) -> Result<u64> {
+    let Some(position) = user.position(mint).filter(|position| position.borrowed_shares > 0) else {
+        return Ok(0);
+    };
+    if position.borrow_index > 0 {
+        return debt_at_index(position.borrowed, position.borrow_index, bank.borrow_index);
+    }
+    if bank.total_borrow_shares == 0 {
+        return Ok(0);
+    }
+    amount_for_shares_ceil(position.borrowed_shares, bank.total_borrows, bank.total_borrow_shares)
+}
+
+/// Reads the extra banks passed in `remaining_accounts`, for positions in assets other than the
+/// instruction's named banks. Only accounts this program owns are read as banks: price updates passed
+/// alongside them are left to `load_price_updates`, and Switchboard aggregators and LP pools to `Prices::load`.
+///
+/// Each copy is accrued to `now`, so debt in these banks is valued with the interest owed since they
+/// were last touched rather than at their stored borrow index.
+pub fn load_banks(accounts: &[AccountInfo], now: i64) -> Result<Vec<Bank>> {
+    accounts
+        .iter()
+        .filter(|info| *info.owner == crate::ID)
+        .map(|info| {
+            let mut bank = Bank::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            accrue_interest(&mut bank, now)?;
+            Ok(bank)
+        })
+        .collect()
+}
+
+/// Reads the extra Pyth price updates passed in `remaining_accounts`, for feeds other than the one in
+/// the instruction's named `price_update`.
+pub fn load_price_updates(accounts: &[AccountInfo]) -> Result<Vec<PriceUpdateV2>> {
+    accounts
+        .iter()
+        .filter(|info| *info.owner == pyth_solana_receiver_sdk::ID)
+        .map(|info| PriceUpdateV2::try_deserialize(&mut &info.try_borrow_data()?[..]))
+        .collect()
+}
+
+/// Applies the isolated-collateral rules to a new borrow of `borrow_mint`.
+///
+/// A user with no debt picks their mode: `Some(mint)` isolates the position on that collateral asset,
+/// `None` cross-collateralizes it. Once debt exists the mode is fixed, and an isolated position may
+/// only keep borrowing the asset it already owes.
+pub fn apply_isolation(user: &mut User, borrow_mint: Pubkey, isolated_mint: Option<Pubkey>) -> Result<()> {
+    if !user.has_debt() {
+        match isolated_mint {
+            Some(mint) => {
+                require_keys_neq!(mint, borrow_mint, ErrorCode::IsolatedModeViolation);
+                user.isolated = true;
+                user.isolated_mint = mint;
+            }
+            None => {
+                user.isolated = false;
+                user.isolated_mint = Pubkey::default();
+            }
+        }
+        return Ok(());
+    }
+
+    // Existing debt fixes the mode.
+    let current_mode = user.isolated.then_some(user.isolated_mint);
+    require!(current_mode == isolated_mint, ErrorCode::IsolatedModeViolation);
+
+    if user.isolated {
+        let owes_other_asset = user
+            .positions
+            .iter()
+            .any(|position| position.mint != borrow_mint && position.borrowed_shares > 0);
+        require!(!owes_other_asset, ErrorCode::IsolatedModeViolation);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::constants::{BORROW_INDEX_SCALE, SECONDS_PER_YEAR, SOL_DECIMALS, SOL_MINT_ADDRESS, USDC_DECIMALS, USDC_MINT_ADDRESS};
+    use crate::interest::accrue_interest;
+    use crate::state::Position;
+
+    fn bank(mint: Pubkey, decimals: u8, liquidation_threshold: u64) -> Bank {
+        Bank {
+            mint_address: mint,
+            decimals,
+            liquidation_threshold,
+            max_ltv: 75,
+            usable_as_collateral: true,
+            // One share per token until interest accrues.
+            total_deposits: 1_000_000_000_000,
+            total_deposit_shares: 1_000_000_000_000,
+            total_borrows: 1_000_000_000_000,
+            total_borrow_shares: 1_000_000_000_000,
+            ..Default::default()
+        }
+    }
+
+    fn position(mint: Pubkey, deposited: u64, borrowed: u64) -> Position {
+        Position { mint, deposited, deposited_shares: deposited, borrowed, borrowed_shares: borrowed, ..Default::default() }
+    }
+
+    /// A fully verified price update for `feed_id`, published at `publish_time`.
+    pub(crate) fn price_update(feed_id: [u8; 32], price: i64, exponent: i32, publish_time: i64) -> PriceUpdateV2 {
+        let mut data = Vec::new();
+        data.extend_from_slice(Pubkey::default().as_ref()); // write_authority
+        data.push(1); // VerificationLevel::Full
+        data.extend_from_slice(&feed_id);
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes()); // conf
+        data.extend_from_slice(&exponent.to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes()); // prev_publish_time
+        data.extend_from_slice(&price.to_le_bytes()); // ema_price
+        data.extend_from_slice(&0u64.to_le_bytes()); // ema_conf
+        data.extend_from_slice(&0u64.to_le_bytes()); // posted_slot
+        PriceUpdateV2::deserialize(&mut &data[..]).unwrap()
+    }
+
+    fn prices(sol: u128, usdc: u128) -> Prices {
+        let mut prices = Prices::default();
+        prices.set(SOL_MINT_ADDRESS, sol);
+        prices.set(USDC_MINT_ADDRESS, usdc);
+        prices
+    }
+
+    #[test]
+    fn values_a_known_portfolio() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(150_000_000, 1_000_000); // $150, $1
+        let user = User {
+            positions: vec![
+                position(SOL_MINT_ADDRESS, 2_000_000_000, 500_000_000), // 2 SOL = $300 deposited, 0.5 SOL = $75 borrowed
+                position(USDC_MINT_ADDRESS, 100_000_000, 50_000_000),   // 100 USDC = $100 deposited, 50 USDC = $50 borrowed
+            ],
+            ..Default::default()
+        };
+
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+
+        assert_eq!(health.total_collateral_value, 400_000_000);
+        assert_eq!(health.weighted_collateral_value, 240_000_000 + 90_000_000);
+        assert_eq!(health.total_debt_value, 125_000_000);
+        assert!(health.is_healthy());
+        assert_eq!(health.shortfall(), 0);
+    }
+
+    #[test]
+    fn assets_not_usable_as_collateral_back_no_debt() {
+        let mut sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(150_000_000, 1_000_000); // $150, $1
+        let user = User {
+            positions: vec![
+                position(SOL_MINT_ADDRESS, 2_000_000_000, 0),         // 2 SOL = $300 deposited
+                position(USDC_MINT_ADDRESS, 100_000_000, 50_000_000), // 100 USDC = $100 deposited, 50 USDC = $50 borrowed
+            ],
+            ..Default::default()
+        };
+
+        let before = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+        sol_bank.usable_as_collateral = false;
+        let after = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+
+        // Only the USDC still counts: $100 at 75% LTV and 90% threshold.
+        assert_eq!(before.borrowing_power, 300_000_000);
+        assert_eq!(after.borrowing_power, 75_000_000);
+        assert_eq!(after.weighted_collateral_value, 90_000_000);
+        assert_eq!(after.total_collateral_value, before.total_collateral_value);
+        assert_eq!(after.total_debt_value, 50_000_000);
+    }
+
+    #[test]
+    fn shortfall_is_the_debt_beyond_weighted_collateral() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(100_000_000, 1_000_000); // $100, $1
+        let user = User {
+            // 1 SOL = $100 weighted at 80%, against $100 of USDC debt.
+            positions: vec![position(SOL_MINT_ADDRESS, 1_000_000_000, 0), position(USDC_MINT_ADDRESS, 0, 100_000_000)],
+            ..Default::default()
+        };
+
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+
+        assert!(!health.is_healthy());
+        assert_eq!(health.shortfall(), 20_000_000);
+    }
+
+    #[test]
+    fn a_liquidation_is_worse_only_if_it_seizes_more_weighted_value_than_it_repays() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(90_000_000, 1_000_000); // $90, $1
+        let health = |deposited, borrowed| {
+            let user = User {
+                positions: vec![position(SOL_MINT_ADDRESS, deposited, 0), position(USDC_MINT_ADDRESS, 0, borrowed)],
+                ..Default::default()
+            };
+            compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap()
+        };
+        // 10 SOL = $900 weighted to $720, against $900 of debt: $180 underwater.
+        let before = health(10_000_000_000, 900_000_000);
+
+        // Repaying $450 for $472.50 of SOL at a 5% bonus lowers the ratio, but only takes $378 of weighted
+        // collateral, so the shortfall shrinks to $108.
+        let sound = health(4_750_000_000, 450_000_000);
+        assert!(sound.health_factor_bps().unwrap() < before.health_factor_bps().unwrap());
+        assert!(sound.is_no_worse_than(&before));
+
+        // At a 50% bonus the same repay seizes $675 of SOL, $540 weighted, and the shortfall grows to $270.
+        let adversarial = health(2_500_000_000, 450_000_000);
+        assert_eq!(adversarial.shortfall(), 270_000_000);
+        assert!(!adversarial.is_no_worse_than(&before));
+    }
+
+    #[test]
+    fn a_position_exactly_at_the_threshold_is_healthy_for_every_gate() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(100_000_000, 1_000_000); // $100, $1
+        let user = User {
+            // 1 SOL = $100 weighted at 80%, against exactly $80 of USDC debt.
+            positions: vec![position(SOL_MINT_ADDRESS, 1_000_000_000, 0), position(USDC_MINT_ADDRESS, 0, 80_000_000)],
+            ..Default::default()
+        };
+
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+
+        // Withdrawals and liquidations both gate on `is_healthy`: on the boundary the position can still
+        // withdraw and cannot be liquidated.
+        assert_eq!(health.weighted_collateral_value, health.total_debt_value);
+        assert_eq!(health.health_factor_bps().unwrap(), HEALTH_FACTOR_SCALE);
+        assert!(health.is_healthy());
+        assert_eq!(health.shortfall(), 0);
+        // Borrows gate on the same comparison, so borrowing exactly the remaining power is allowed and a
+        // unit more is not.
+        assert!(is_healthy(50_000_000, 50_000_000));
+        assert!(!is_healthy(50_000_000, 50_000_001));
+        assert!(!is_healthy(health.weighted_collateral_value, health.total_debt_value + 1));
+    }
+
+    #[test]
+    fn a_depegged_stablecoin_is_valued_conservatively() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let mut usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let user = User {
+            // 1 SOL and 100 USDC deposited, against 100 USDC of debt.
+            positions: vec![position(SOL_MINT_ADDRESS, 1_000_000_000, 0), position(USDC_MINT_ADDRESS, 100_000_000, 100_000_000)],
+            ..Default::default()
+        };
+        let depegged_down = prices(100_000_000, 900_000); // $100, $0.90
+        let depegged_up = prices(100_000_000, 1_100_000); // $100, $1.10
+
+        let naive_down = compute_account_health(&user, &depegged_down, &[&sol_bank, &usdc_bank]).unwrap();
+        let naive_up = compute_account_health(&user, &depegged_up, &[&sol_bank, &usdc_bank]).unwrap();
+        usdc_bank.peg_deviation_bps = 200;
+        let guarded_down = compute_account_health(&user, &depegged_down, &[&sol_bank, &usdc_bank]).unwrap();
+        let guarded_up = compute_account_health(&user, &depegged_up, &[&sol_bank, &usdc_bank]).unwrap();
+
+        // Below the peg the debt still counts at $1, above it the collateral counts at only $1.
+        assert_eq!(naive_down.total_debt_value, 90_000_000);
+        assert_eq!(guarded_down.total_debt_value, 100_000_000);
+        assert_eq!(guarded_down.total_collateral_value, naive_down.total_collateral_value);
+        assert_eq!(naive_up.total_collateral_value, 210_000_000);
+        assert_eq!(guarded_up.total_collateral_value, 200_000_000);
+        assert_eq!(guarded_up.total_debt_value, naive_up.total_debt_value);
+        assert!(guarded_down.remaining_borrowing_power() < naive_down.remaining_borrowing_power());
+        assert!(guarded_up.remaining_borrowing_power() < naive_up.remaining_borrowing_power());
+
+        // Within the bound the oracle price is used as is.
+        assert_eq!(collateral_price(&usdc_bank, 1_010_000), 1_010_000);
+        assert_eq!(debt_price(&usdc_bank, 990_000), 990_000);
+    }
+
+    fn health_at(health_factor_bps: u128) -> HealthFactor {
+        HealthFactor {
+            total_collateral_value: 0,
+            weighted_collateral_value: health_factor_bps * 100,
+            borrowing_power: 0,
+            total_debt_value: 1_000_000,
+        }
+    }
+
+    #[test]
+    fn dynamic_bonus_grows_as_health_falls() {
+        let sol_bank = Bank { dynamic_bonus: true, min_liquidation_bonus: 2, max_liquidation_bonus: 10, ..Default::default() };
+
+        // Just below 1.0 the bonus barely moves off the minimum; by 0.80 it has reached the maximum.
+        let barely = liquidation_bonus_bps(&sol_bank, &health_at(9_900)).unwrap();
+        let deeply = liquidation_bonus_bps(&sol_bank, &health_at(8_000)).unwrap();
+        assert_eq!(barely, 240);
+        assert_eq!(deeply, 1_000);
+        assert!(barely < deeply);
+
+        assert_eq!(liquidation_bonus_bps(&sol_bank, &health_at(9_000)).unwrap(), 600);
+        assert_eq!(liquidation_bonus_bps(&sol_bank, &health_at(5_000)).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn fixed_bonus_ignores_health() {
+        let sol_bank = Bank { liquidation_bonus: 5, min_liquidation_bonus: 2, max_liquidation_bonus: 10, ..Default::default() };
+
+        assert_eq!(liquidation_bonus_bps(&sol_bank, &health_at(9_900)).unwrap(), 500);
+        assert_eq!(liquidation_bonus_bps(&sol_bank, &health_at(8_000)).unwrap(), 500);
+    }
+
+    #[test]
+    fn auction_bonus_ramps_from_min_to_max_over_the_auction() {
+        let sol_bank = Bank { min_liquidation_bonus: 2, max_liquidation_bonus: 10, ..Default::default() };
+
+        let at_start = auction_bonus_bps(&sol_bank, 0, 120).unwrap();
+        let a_minute_in = auction_bonus_bps(&sol_bank, 60, 120).unwrap();
+        assert_eq!(at_start, 200);
+        assert_eq!(a_minute_in, 600);
+        assert!(at_start < a_minute_in);
+
+        // Once the auction has run its course the bonus stays at the maximum.
+        assert_eq!(auction_bonus_bps(&sol_bank, 120, 120).unwrap(), 1_000);
+        assert_eq!(auction_bonus_bps(&sol_bank, 10_000, 120).unwrap(), 1_000);
+        // An auction with no duration offers the full bonus straight away.
+        assert_eq!(auction_bonus_bps(&sol_bank, 0, 0).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn rejects_a_position_without_its_bank() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let prices = prices(150_000_000, 1_000_000);
+        let user = User {
+            positions: vec![
+                position(SOL_MINT_ADDRESS, 1_000_000_000, 0),
+                position(USDC_MINT_ADDRESS, 100_000_000, 0),
+            ],
+            ..Default::default()
+        };
+
+        assert!(compute_account_health(&user, &prices, &[&sol_bank]).is_err());
+    }
+
+    #[test]
+    fn isolated_position_ignores_other_collateral() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(150_000_000, 1_000_000);
+        let user = User {
+            positions: vec![
+                position(SOL_MINT_ADDRESS, 2_000_000_000, 0),         // $300
+                position(USDC_MINT_ADDRESS, 100_000_000, 50_000_000), // $100, not counted
+            ],
+            isolated: true,
+            isolated_mint: SOL_MINT_ADDRESS,
+            ..Default::default()
+        };
+
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+
+        assert_eq!(health.total_collateral_value, 300_000_000);
+        assert_eq!(health.weighted_collateral_value, 240_000_000);
+        assert_eq!(health.total_debt_value, 50_000_000);
+    }
+
+    #[test]
+    fn isolated_position_cannot_borrow_a_second_asset() {
+        let mut user = User::default();
+
+        apply_isolation(&mut user, USDC_MINT_ADDRESS, Some(SOL_MINT_ADDRESS)).unwrap();
+        assert!(user.isolated);
+        user.positions.push(position(USDC_MINT_ADDRESS, 0, 50_000_000));
+
+        // More of the same asset is fine...
+        apply_isolation(&mut user, USDC_MINT_ADDRESS, Some(SOL_MINT_ADDRESS)).unwrap();
+        // ...a second asset, or switching modes while in debt, is not.
+        assert!(apply_isolation(&mut user, SOL_MINT_ADDRESS, Some(SOL_MINT_ADDRESS)).is_err());
+        assert!(apply_isolation(&mut user, USDC_MINT_ADDRESS, None).is_err());
+    }
+
+    #[test]
+    fn borrowing_power_uses_each_collateral_assets_ltv() {
+        let sol_bank = Bank { max_ltv: 75, ..bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80) };
+        let usdc_bank = Bank { max_ltv: 85, ..bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90) };
+        let prices = prices(150_000_000, 1_000_000);
+        let user = User {
+            positions: vec![
+                position(SOL_MINT_ADDRESS, 2_000_000_000, 0), // $300
+                position(USDC_MINT_ADDRESS, 100_000_000, 0),  // $100
+            ],
+            ..Default::default()
+        };
+
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+
+        // 75% of $300 plus 85% of $100
+        assert_eq!(health.borrowing_power, 225_000_000 + 85_000_000);
+    }
+
+    #[test]
+    fn existing_debt_uses_up_borrowing_power() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(150_000_000, 1_000_000);
+        let user = User {
+            positions: vec![
+                position(SOL_MINT_ADDRESS, 2_000_000_000, 0), // $300, $225 at 75% LTV
+                position(USDC_MINT_ADDRESS, 0, 200_000_000),  // $200 owed
+            ],
+            ..Default::default()
+        };
+
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+        assert_eq!(health.remaining_borrowing_power(), 25_000_000);
+
+        let underwater = HealthFactor { total_debt_value: 300_000_000, ..health };
+        assert_eq!(underwater.remaining_borrowing_power(), 0);
+    }
+
+    #[test]
+    fn the_safety_buffer_holds_borrowing_below_the_liquidation_point() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(150_000_000, 1_000_000);
+        let user = User {
+            positions: vec![position(SOL_MINT_ADDRESS, 2_000_000_000, 0)], // $300, $240 at an 80% threshold
+            ..Default::default()
+        };
+
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+
+        // With no buffer the whole weighted collateral can back debt; max LTV still applies separately.
+        assert_eq!(health.buffered_borrowing_power(0).unwrap(), 240_000_000);
+        // A 10% buffer keeps debt at $216, which a $225 max-LTV borrow would overshoot.
+        assert_eq!(health.buffered_borrowing_power(1_000).unwrap(), 216_000_000);
+        assert!(!is_healthy(health.buffered_borrowing_power(1_000).unwrap(), health.remaining_borrowing_power()));
+
+        let indebted = HealthFactor { total_debt_value: 230_000_000, ..health };
+        assert_eq!(indebted.buffered_borrowing_power(1_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn values_a_third_asset_like_any_other() {
+        // A mint that isn't SOL or USDC, with 8 decimals, priced at $2.
+        let third_mint = Pubkey::new_unique();
+        let third_bank = Bank { max_ltv: 50, ..bank(third_mint, 8, 60) };
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let mut prices = prices(150_000_000, 1_000_000);
+        prices.set(third_mint, 2_000_000);
+
+        // 100 tokens ($200) deposited, borrowing 80 USDC against them.
+        let mut user = User::default();
+        *user.position_or_insert(&third_mint).unwrap() = position(third_mint, 10_000_000_000, 0);
+        *user.position_or_insert(&USDC_MINT_ADDRESS).unwrap() = position(USDC_MINT_ADDRESS, 0, 80_000_000);
+
+        let health = compute_account_health(&user, &prices, &[&third_bank, &usdc_bank]).unwrap();
+
+        assert_eq!(health.total_collateral_value, 200_000_000);
+        assert_eq!(health.borrowing_power, 100_000_000);
+        assert_eq!(health.weighted_collateral_value, 120_000_000);
+        assert_eq!(health.total_debt_value, 80_000_000);
+        assert!(health.is_healthy());
+    }
+
+    #[test]
+    fn collateral_is_valued_at_what_its_shares_redeem_for() {
+        let mut sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let prices = prices(150_000_000, 1_000_000);
+        let user = User { positions: vec![position(SOL_MINT_ADDRESS, 2_000_000_000, 0)], ..Default::default() };
+
+        // 10% interest earned on the bank's deposits: 2 SOL of principal is now worth 2.2 SOL ($330).
+        sol_bank.total_deposits += sol_bank.total_deposits / 10;
+        let earned = compute_account_health(&user, &prices, &[&sol_bank]).unwrap();
+        assert_eq!(current_deposit(&user, &sol_bank, &SOL_MINT_ADDRESS).unwrap(), 2_200_000_000);
+        assert_eq!(earned.total_collateral_value, 330_000_000);
+
+        // A write-off halves every share, so the same principal is only worth 1.1 SOL ($165).
+        sol_bank.total_deposits /= 2;
+        let written_off = compute_account_health(&user, &prices, &[&sol_bank]).unwrap();
+        assert_eq!(written_off.total_collateral_value, 165_000_000);
+        assert_eq!(written_off.weighted_collateral_value, 132_000_000);
+    }
+
+    #[test]
+    fn debt_grows_with_the_banks_borrows() {
+        let mut usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let user = User { positions: vec![position(USDC_MINT_ADDRESS, 0, 100_000_000)], ..Default::default() };
+        assert_eq!(current_debt(&user, &usdc_bank, &USDC_MINT_ADDRESS).unwrap(), 100_000_000);
+
+        // 10% interest accrues on the bank; the user's account is never touched.
+        usdc_bank.total_borrows += usdc_bank.total_borrows / 10;
+        assert_eq!(current_debt(&user, &usdc_bank, &USDC_MINT_ADDRESS).unwrap(), 110_000_000);
+
+        // A fraction of a token owed is rounded up.
+        usdc_bank.total_borrows += 1;
+        assert_eq!(current_debt(&user, &usdc_bank, &USDC_MINT_ADDRESS).unwrap(), 110_000_001);
+        assert_eq!(current_debt(&user, &usdc_bank, &SOL_MINT_ADDRESS).unwrap(), 0);
+    }
+
+    #[test]
+    fn debt_grows_by_the_borrow_index_since_each_users_snapshot() {
+        let mut usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        usdc_bank.base_rate = 1_000; // 10% APR
+        usdc_bank.borrow_index = BORROW_INDEX_SCALE;
+        usdc_bank.total_borrows = 1_000_000_000;
+        usdc_bank.total_borrow_shares = 1_000_000_000;
+        let borrower_at = |bank: &Bank, debt: u64| {
+            let mut debt_position = position(USDC_MINT_ADDRESS, 0, debt);
+            debt_position.set_debt(debt, bank.borrow_index);
+            User { positions: vec![debt_position], ..Default::default() }
+        };
+
+        // The first user borrows at an index of 1.0, the second a year later at 1.1.
+        let early = borrower_at(&usdc_bank, 1_000_000_000);
+        accrue_interest(&mut usdc_bank, SECONDS_PER_YEAR as i64).unwrap();
+        let late = borrower_at(&usdc_bank, 1_000_000_000);
+        usdc_bank.total_borrows += 1_000_000_000;
+
+        // After another year the index is 1.21: the early debt has compounded twice, the late one once.
+        accrue_interest(&mut usdc_bank, 2 * SECONDS_PER_YEAR as i64).unwrap();
+        assert_eq!(current_debt(&early, &usdc_bank, &USDC_MINT_ADDRESS).unwrap(), 1_210_000_000);
+        assert_eq!(current_debt(&late, &usdc_bank, &USDC_MINT_ADDRESS).unwrap(), 1_100_000_000);
+        assert_eq!(usdc_bank.total_borrows, 2_310_000_000);
+    }
+
+    #[test]
+    fn health_values_debt_with_the_interest_accrued_since_the_bank_was_touched() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let mut usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        usdc_bank.base_rate = 1_000; // 10% APR
+        usdc_bank.borrow_index = BORROW_INDEX_SCALE;
+        usdc_bank.total_borrows = 1_150_000_000;
+        usdc_bank.total_borrow_shares = 1_150_000_000;
+        let mut debt_position = position(USDC_MINT_ADDRESS, 0, 1_150_000_000);
+        debt_position.set_debt(1_150_000_000, usdc_bank.borrow_index);
+        let user = User {
+            positions: vec![position(SOL_MINT_ADDRESS, 10_000_000_000, 0), debt_position], // 10 SOL, 1150 USDC owed
+            ..Default::default()
+        };
+        let prices = prices(150_000_000, 1_000_000); // $150, $1
+
+        // At the stored index the $1150 debt sits inside the $1200 of weighted collateral.
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+        assert!(health.is_healthy());
+
+        // A year of interest the bank hasn't recorded yet takes it to $1265.
+        accrue_interest(&mut usdc_bank, SECONDS_PER_YEAR as i64).unwrap();
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+        assert_eq!(health.total_debt_value, 1_265_000_000);
+        assert!(!health.is_healthy());
+    }
+
+    #[test]
+    fn prices_come_from_each_banks_own_feed() {
+        let feed_id = [7u8; 32];
+        let sol_bank = Bank { price_feed_id: feed_id, ..bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80) };
+        let clock = Clock { unix_timestamp: 1_000, ..Default::default() };
+
+        // $200 with Pyth's usual -8 exponent.
+        let update = price_update(feed_id, 20_000_000_000, -8, 990);
+        let prices = Prices::load(&[&update], &[], &clock, 60, &[&sol_bank], &[0; 32]).unwrap();
+        assert_eq!(prices.for_mint(&SOL_MINT_ADDRESS).unwrap(), 200_000_000);
+
+        // The same update can't price a bank registered with another feed.
+        let other_bank = Bank { price_feed_id: [8u8; 32], ..sol_bank };
+        assert_eq!(
+            Prices::load(&[&update], &[], &clock, 60, &[&other_bank], &[0; 32]).unwrap_err(),
+            error!(ErrorCode::WrongPriceFeed)
+        );
+        // A bank with no update passed is just left unpriced.
+        let prices = Prices::load(&[], &[], &clock, 60, &[&other_bank], &[0; 32]).unwrap();
+        assert_eq!(prices.for_mint(&SOL_MINT_ADDRESS).unwrap_err(), error!(ErrorCode::MissingPriceFeed));
+        // Nor can a price older than the allowed age.
+        assert!(Prices::load(&[&update], &[], &clock, 5, &[&sol_bank], &[0; 32]).is_err());
+    }
+
+    #[test]
+    fn each_asset_is_priced_from_its_own_update() {
+        let sol_feed = [7u8; 32];
+        let usdc_feed = [9u8; 32];
+        let sol_bank = Bank { price_feed_id: sol_feed, ..bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80) };
+        let usdc_bank = Bank { price_feed_id: usdc_feed, ..bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90) };
+        let clock = Clock { unix_timestamp: 1_000, ..Default::default() };
+        let sol_update = price_update(sol_feed, 15_000_000_000, -8, 990);
+        let usdc_update = price_update(usdc_feed, 99_990_000, -8, 995);
+
+        // The order the updates are passed in doesn't matter.
+        let prices = Prices::load(&[&usdc_update, &sol_update], &[], &clock, 60, &[&sol_bank, &usdc_bank], &[0; 32]).unwrap();
+        assert_eq!(prices.for_mint(&SOL_MINT_ADDRESS).unwrap(), 150_000_000);
+        assert_eq!(prices.for_mint(&USDC_MINT_ADDRESS).unwrap(), 999_900);
+
+        // Without the USDC update, only a position that needs the USDC price fails.
+        let prices = Prices::load(&[&sol_update], &[], &clock, 60, &[&sol_bank, &usdc_bank], &[0; 32]).unwrap();
+        let sol_only = User { positions: vec![position(SOL_MINT_ADDRESS, 1_000_000_000, 0)], ..Default::default() };
+        assert!(compute_account_health(&sol_only, &prices, &[&sol_bank, &usdc_bank]).is_ok());
+        let with_usdc_debt = User {
+            positions: vec![position(SOL_MINT_ADDRESS, 1_000_000_000, 0), position(USDC_MINT_ADDRESS, 0, 50_000_000)],
+            ..Default::default()
+        };
+        assert_eq!(
+            compute_account_health(&with_usdc_debt, &prices, &[&sol_bank, &usdc_bank]).unwrap_err(),
+            error!(ErrorCode::MissingPriceFeed)
+        );
+    }
+
+    #[test]
+    fn old_prices_surface_as_stale_price_feed() {
+        let feed_id = [7u8; 32];
+        let clock = Clock { unix_timestamp: 1_000, ..Default::default() };
+        let update = price_update(feed_id, 20_000_000_000, -8, 990);
+
+        assert_eq!(fetch_price(&update, &feed_id, 60, &clock).unwrap().price, 20_000_000_000);
+        let too_old = fetch_price(&update, &feed_id, 5, &clock).unwrap_err();
+        assert_eq!(too_old, error!(ErrorCode::StalePriceFeed));
+    }
+
+    #[test]
+    fn values_health_in_the_quote_asset() {
+        let sol_feed = [7u8; 32];
+        let usdc_feed = [9u8; 32];
+        let sol_bank = Bank { price_feed_id: sol_feed, ..bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80) };
+        let usdc_bank = Bank { price_feed_id: usdc_feed, ..bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90) };
+        let clock = Clock { unix_timestamp: 1_000, ..Default::default() };
+        let sol_update = price_update(sol_feed, 20_000_000_000, -8, 990); // $200
+        let usdc_update = price_update(usdc_feed, 100_000_000, -8, 995); // $1
+        let user = User {
+            positions: vec![position(USDC_MINT_ADDRESS, 300_000_000, 0), position(SOL_MINT_ADDRESS, 0, 1_000_000_000)], // 300 USDC, 1 SOL owed
+            ..Default::default()
+        };
+
+        // Quoted in SOL, 300 USDC is worth 1.5 SOL and the debt exactly 1 SOL.
+        let prices = Prices::load(&[&sol_update, &usdc_update], &[], &clock, 60, &[&sol_bank, &usdc_bank], &sol_feed).unwrap();
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+        assert_eq!(health.total_collateral_value, 1_500_000);
+        assert_eq!(health.weighted_collateral_value, 1_350_000);
+        assert_eq!(health.total_debt_value, 1_000_000);
+        // The asset prices themselves stay in USD, for the deviation breaker.
+        assert_eq!(prices.for_mint(&USDC_MINT_ADDRESS).unwrap(), 1_000_000);
+
+        // The quote's own update is required, even when no position is in it.
+        let quote_feed = [3u8; 32];
+        assert_eq!(
+            Prices::load(&[&usdc_update], &[], &clock, 60, &[&usdc_bank], &quote_feed).unwrap_err(),
+            error!(ErrorCode::MissingPriceFeed)
+        );
+        let quote_update = price_update(quote_feed, 400_000_000, -8, 995); // $4
+        let prices = Prices::load(&[&usdc_update, &quote_update], &[], &clock, 60, &[&usdc_bank], &quote_feed).unwrap();
+        assert_eq!(prices.in_quote(1_000_000).unwrap(), 250_000);
+    }
+
+    #[test]
+    fn an_update_for_another_feed_is_rejected_before_it_is_read() {
+        let clock = Clock { unix_timestamp: 1_000, ..Default::default() };
+        // Fresh and positive, so only the feed id is wrong.
+        let update = price_update([7u8; 32], 20_000_000_000, -8, 990);
+
+        assert_eq!(fetch_price(&update, &[8u8; 32], 60, &clock).unwrap_err(), error!(ErrorCode::WrongPriceFeed));
+        let sol_bank = Bank { price_feed_id: [8u8; 32], ..bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80) };
+        let usdc_bank = Bank { price_feed_id: [9u8; 32], ..bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90) };
+        assert_eq!(
+            Prices::load(&[&update], &[], &clock, 60, &[&sol_bank, &usdc_bank], &[0; 32]).unwrap_err(),
+            error!(ErrorCode::WrongPriceFeed)
+        );
+    }
+
+    #[test]
+    fn zero_and_negative_prices_are_rejected() {
+        let sol_bank = Bank { price_feed_id: [7u8; 32], ..bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80) };
+        let clock = Clock { unix_timestamp: 1_000, ..Default::default() };
+
+        for price in [0, -1, -150_00000000] {
+            let update = price_update(sol_bank.price_feed_id, price, -8, 1_000);
+            assert_eq!(
+                fetch_price(&update, &sol_bank.price_feed_id, 60, &clock).unwrap_err(),
+                error!(ErrorCode::InvalidOraclePrice)
+            );
+            assert_eq!(
+                Prices::load(&[&update], &[], &clock, 60, &[&sol_bank], &[0; 32]).unwrap_err(),
+                error!(ErrorCode::InvalidOraclePrice)
+            );
+        }
+    }
+
+    #[test]
+    fn values_near_u64_max_deposits_exactly() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        let prices = prices(150_000_000, 1_000_000); // $150, $1
+        let user = User {
+            positions: vec![position(SOL_MINT_ADDRESS, u64::MAX, 0), position(USDC_MINT_ADDRESS, u64::MAX - 1, 0)],
+            ..Default::default()
+        };
+
+        let health = compute_account_health(&user, &prices, &[&sol_bank, &usdc_bank]).unwrap();
+
+        let sol_value = 150_000_000 * u64::MAX as u128 / 1_000_000_000;
+        let usdc_value = (u64::MAX - 1) as u128;
+        assert_eq!(health.total_collateral_value, sol_value + usdc_value);
+        assert_eq!(health.weighted_collateral_value, sol_value * 80 / 100 + usdc_value * 90 / 100);
+    }
+
+    #[test]
+    fn impossible_values_fail_instead_of_wrapping() {
+        let sol_bank = bank(SOL_MINT_ADDRESS, SOL_DECIMALS, 80);
+        let usdc_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+
+        // The largest price Pyth can report, times the largest deposit, does not fit in a u128.
+        let max_price = crate::math::normalize_price(i64::MAX.into(), 0, crate::constants::USD_EXPONENT).unwrap();
+        let whale = User { positions: vec![position(SOL_MINT_ADDRESS, u64::MAX, 0)], ..Default::default() };
+        assert_eq!(
+            compute_account_health(&whale, &prices(max_price, 1_000_000), &[&sol_bank, &usdc_bank]).unwrap_err(),
+            error!(ErrorCode::ValueOverflow)
+        );
+
+        // Two deposits that can each be valued, but whose values do not fit in a u128 together.
+        let whole_sol_bank = bank(SOL_MINT_ADDRESS, 0, 80);
+        let whole_usdc_bank = bank(USDC_MINT_ADDRESS, 0, 90);
+        let just_over_half = (1u128 << 63) + 1; // times u64::MAX is just over u128::MAX / 2
+        let both = User {
+            positions: vec![position(SOL_MINT_ADDRESS, u64::MAX, 0), position(USDC_MINT_ADDRESS, u64::MAX, 0)],
+            ..Default::default()
+        };
+        assert!(token_value_usd(just_over_half, u64::MAX, 0).is_ok());
+        assert_eq!(
+            compute_account_health(&both, &prices(just_over_half, just_over_half), &[&whole_sol_bank, &whole_usdc_bank])
+                .unwrap_err(),
+            error!(ErrorCode::ValueOverflow)
+        );
+
+        // A debt that the bank's shares say is larger than a u64 is rejected too.
+        let mut inflated_bank = bank(USDC_MINT_ADDRESS, USDC_DECIMALS, 90);
+        inflated_bank.total_borrows = u64::MAX;
+        inflated_bank.total_borrow_shares = 1;
+        let debtor = User { positions: vec![position(USDC_MINT_ADDRESS, 0, 2)], ..Default::default() };
+        assert_eq!(
+            compute_account_health(&debtor, &prices(150_000_000, 1_000_000), &[&sol_bank, &inflated_bank]).unwrap_err(),
+            error!(ErrorCode::ShareConversionOverflow)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::events::{AssetRegisteredEvent, BankSettings};
+use super::init_bank::{init_bank_state, InitBankParams};
+
+#[derive(Accounts)]
+pub struct AddSupportedAsset<'info> {
+    /// The protocol admin, who alone may list new assets.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.admin == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        space = 8 + Bank::INIT_SPACE,
+        payer = signer,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        init,
+        token::mint = mint,
+        token::authority = bank_token_account,
+        payer = signer,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Lists a new collateral asset: creates its bank with `params` and records the Pyth feed it is priced by.
+/// `feed_id` is the feed's hex id, as published by Pyth.
+pub fn process_add_supported_asset(ctx: Context<AddSupportedAsset>, feed_id: String, params: InitBankParams) -> Result<()> {
+    let authority = ctx.accounts.signer.key();
+    let bank = &mut ctx.accounts.bank;
+    init_bank_state(bank, &ctx.accounts.mint, authority, &feed_id, params)?;
+    emit!(AssetRegisteredEvent {
+        admin: authority,
+        mint: bank.mint_address,
+        bank: bank.key(),
+        price_feed_id: bank.price_feed_id,
+        settings: BankSettings::from(&**bank),
+    });
+
+    msg!("Listed {} with price feed {}", bank.mint_address, feed_id);
+    Ok(())
+}
@@ -1,64 +1,360 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{ Mint, TokenAccount, TokenInterface };
+use anchor_spl::token_interface::Mint;
+use pyth_solana_receiver_sdk::price_update::get_feed_id_from_hex;
 use crate::state::*;
+use crate::error::ErrorCode;
+use crate::constants::BPS_DENOMINATOR;
+use crate::interest::force_accrue_interest;
+use crate::health::feed_hex;
+use crate::events::{BankConfigUpdatedEvent, BankSettings, ConfigSettings, ConfigUpdatedEvent, PausedChangedEvent};
 
+/// Signed by the config's admin, who alone may change a bank's settings. Checking the config rather
+/// than the bank means an accepted admin transfer hands over every bank along with it.
 #[derive(Accounts)]
-pub struct InitBank<'info> {
-    #[account(mut)]
+pub struct UpdateBank<'info> {
     pub signer: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.admin == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
     pub mint: InterfaceAccount<'info, Mint>,
     #[account(
-        init, 
-        space = 8 + Bank::INIT_SPACE, 
-        payer = signer,
+        mut,
         seeds = [mint.key().as_ref()],
-        bump, 
+        bump,
     )]
     pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub signer: Signer<'info>,
     #[account(
-        init, 
-        token::mint = mint, 
-        token::authority = bank_token_account,
-        payer = signer,
-        seeds = [b"treasury", mint.key().as_ref()],
+        mut,
+        seeds = [b"config"],
         bump,
+        constraint = config.admin == signer.key() @ ErrorCode::Unauthorized,
     )]
-    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
-    pub token_program: Interface<'info, TokenInterface>, 
-    pub system_program: Program <'info, System>,
+    pub config: Account<'info, Config>,
 }
 
+/// Signed by the proposed admin to take over the config.
 #[derive(Accounts)]
-pub struct InitUser<'info> {
-    #[account(mut)]
+pub struct AcceptAdmin<'info> {
     pub signer: Signer<'info>,
     #[account(
-        init,
-        payer = signer, 
-        space = 8 + User::INIT_SPACE,
-        seeds = [signer.key().as_ref()],
+        mut,
+        seeds = [b"config"],
         bump,
+        constraint = config.pending_admin == Some(signer.key()) @ ErrorCode::Unauthorized,
     )]
-    pub user_account: Account<'info, User>,
-    pub system_program: Program <'info, System>,
+    pub config: Account<'info, Config>,
+}
+
+/// Starts handing the config to `new_admin`. The current admin keeps control, and may cancel or
+/// re-propose, until `new_admin` signs `accept_admin`, so a mistyped key can't lock the protocol out.
+pub fn process_propose_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+    update_config(&mut ctx.accounts.config, ctx.accounts.signer.key(), |config| {
+        config.pending_admin = Some(new_admin);
+    });
+    msg!("Proposed admin: {}", new_admin);
+    Ok(())
+}
+
+pub fn process_cancel_admin_transfer(ctx: Context<UpdateConfig>) -> Result<()> {
+    update_config(&mut ctx.accounts.config, ctx.accounts.signer.key(), |config| {
+        config.pending_admin = None;
+    });
+    msg!("Admin transfer cancelled");
+    Ok(())
+}
+
+pub fn process_accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+    let new_admin = ctx.accounts.signer.key();
+    update_config(&mut ctx.accounts.config, new_admin, |config| {
+        config.admin = new_admin;
+        config.pending_admin = None;
+    });
+    msg!("Admin transferred to {}", new_admin);
+    Ok(())
+}
+
+pub fn process_set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    let old_paused = config.paused;
+    config.paused = paused;
+    emit!(PausedChangedEvent {
+        admin: ctx.accounts.signer.key(),
+        old_paused,
+        new_paused: paused,
+    });
+    msg!("Protocol paused: {}", paused);
+    Ok(())
+}
+
+/// Applies `update` to the config and emits a `ConfigUpdatedEvent` with its settings before and after,
+/// signed off by `admin`, so config changes leave the same audit trail as bank ones.
+fn update_config(config: &mut Config, admin: Pubkey, update: impl FnOnce(&mut Config)) {
+    let before = ConfigSettings::from(&*config);
+    update(config);
+    emit!(ConfigUpdatedEvent {
+        admin,
+        before,
+        after: ConfigSettings::from(&*config),
+    });
+}
+
+/// Applies `update` to the bank and emits a `BankConfigUpdatedEvent` with its settings before and after,
+/// so every bank setter leaves an on-chain audit trail.
+fn update_bank(accounts: &mut UpdateBank, update: impl FnOnce(&mut Bank) -> Result<()>) -> Result<()> {
+    let bank = &mut accounts.bank;
+    let before = BankSettings::from(&**bank);
+    update(bank)?;
+    emit!(BankConfigUpdatedEvent {
+        admin: accounts.signer.key(),
+        mint: bank.mint_address,
+        before,
+        after: BankSettings::from(&**bank),
+    });
+    Ok(())
+}
+
+pub fn process_set_fee_recipient(ctx: Context<UpdateConfig>, fee_recipient: Pubkey) -> Result<()> {
+    update_config(&mut ctx.accounts.config, ctx.accounts.signer.key(), |config| {
+        config.fee_recipient = fee_recipient;
+    });
+    msg!("Fee recipient: {}", fee_recipient);
+    Ok(())
 }
 
-pub fn process_init_bank(ctx: Context<InitBank>, liquidation_threshold: u64, max_ltv: u64) -> Result<()> {
-    let bank = &mut ctx.accounts.bank;
-    bank.mint_address = ctx.accounts.mint.key();
-    bank.authority = ctx.accounts.signer.key();
-    bank.liquidation_threshold = liquidation_threshold;
-    bank.max_ltv = max_ltv;
+pub fn process_set_min_liquidation_incentive(ctx: Context<UpdateConfig>, min_liquidation_incentive_usd: u128) -> Result<()> {
+    update_config(&mut ctx.accounts.config, ctx.accounts.signer.key(), |config| {
+        config.min_liquidation_incentive_usd = min_liquidation_incentive_usd;
+    });
+    msg!("Min liquidation incentive (USD): {}", min_liquidation_incentive_usd);
     Ok(())
 }
 
-pub fn process_init_user(ctx: Context<InitUser>, usdc_address: Pubkey) -> Result<()> {
-    let user = &mut ctx.accounts.user_account;
-    user.owner = ctx.accounts.signer.key();
-    user.usdc_address = usdc_address;
-    
-    let now = Clock::get()?.unix_timestamp; 
-    user.last_updated = now;
+pub fn process_set_auction_mode(ctx: Context<UpdateConfig>, auction_mode: bool, auction_duration_seconds: u64) -> Result<()> {
+    // An auction with no duration would offer the full bonus at once, which is what turning it off does.
+    require!(!auction_mode || auction_duration_seconds > 0, ErrorCode::InvalidAuctionDuration);
+    update_config(&mut ctx.accounts.config, ctx.accounts.signer.key(), |config| {
+        config.auction_mode = auction_mode;
+        config.auction_duration_seconds = auction_duration_seconds;
+    });
+    msg!("Auction mode: {}, duration: {}s", auction_mode, auction_duration_seconds);
+    Ok(())
+}
 
+/// Values health in the asset priced by `quote_feed_id` instead of USD, e.g. SOL for a SOL-denominated
+/// market. `None` goes back to USD. Every instruction that checks health then needs an update for the
+/// quote feed too. Liquidation sizing and the USD-denominated settings are unaffected.
+pub fn process_set_quote_asset(ctx: Context<UpdateConfig>, quote_feed_id: Option<String>) -> Result<()> {
+    let quote_feed_id = match quote_feed_id {
+        Some(feed_id) => get_feed_id_from_hex(&feed_id).map_err(|_| ErrorCode::InvalidPriceFeedId)?,
+        None => [0; 32],
+    };
+    update_config(&mut ctx.accounts.config, ctx.accounts.signer.key(), |config| {
+        config.quote_feed_id = quote_feed_id;
+    });
+    msg!("Quote feed: 0x{}", feed_hex(&quote_feed_id));
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Sets how many distinct assets a single user may owe at once; 0 removes the limit. Users already over
+/// a lowered limit keep their debts, but can't open a new one until they are back under it.
+pub fn process_set_max_borrowed_assets(ctx: Context<UpdateConfig>, max_borrowed_assets: u8) -> Result<()> {
+    update_config(&mut ctx.accounts.config, ctx.accounts.signer.key(), |config| {
+        config.max_borrowed_assets = max_borrowed_assets;
+    });
+    msg!("Max borrowed assets: {}", max_borrowed_assets);
+    Ok(())
+}
+
+pub fn process_set_max_price_age(ctx: Context<UpdateBank>, max_price_age_seconds: u64) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        bank.max_price_age_seconds = max_price_age_seconds;
+        Ok(())
+    })
+}
+
+pub fn process_set_risk_params(ctx: Context<UpdateBank>, max_ltv: u64, liquidation_threshold: u64) -> Result<()> {
+    // Same ordering `init_bank` enforces: max_ltv <= threshold <= 100.
+    require!(liquidation_threshold <= 100, ErrorCode::InvalidLiquidationThreshold);
+    require!(max_ltv <= liquidation_threshold, ErrorCode::InvalidMaxLtv);
+    update_bank(ctx.accounts, |bank| {
+        bank.max_ltv = max_ltv;
+        bank.liquidation_threshold = liquidation_threshold;
+        Ok(())
+    })
+}
+
+pub fn process_set_caps(ctx: Context<UpdateBank>, supply_cap: u64, borrow_cap: u64) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        bank.supply_cap = supply_cap;
+        bank.borrow_cap = borrow_cap;
+        Ok(())
+    })
+}
+
+pub fn process_set_min_borrow_amount(ctx: Context<UpdateBank>, min_borrow_amount: u64) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        bank.min_borrow_amount = min_borrow_amount;
+        msg!("Minimum borrow for {}: {}", bank.mint_address, min_borrow_amount);
+        Ok(())
+    })
+}
+
+pub fn process_set_accrual_interval(ctx: Context<UpdateBank>, accrual_interval_seconds: u64) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        // Settle what is owed under the old interval, so a longer one can't stretch time already elapsed.
+        force_accrue_interest(bank, Clock::get()?.unix_timestamp)?;
+        bank.accrual_interval_seconds = accrual_interval_seconds;
+        msg!("Accrual interval for {}: {}s", bank.mint_address, accrual_interval_seconds);
+        Ok(())
+    })
+}
+
+pub fn process_set_safety_buffer(ctx: Context<UpdateBank>, safety_buffer_bps: u16) -> Result<()> {
+    require!(safety_buffer_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidSafetyBuffer);
+    update_bank(ctx.accounts, |bank| {
+        bank.safety_buffer_bps = safety_buffer_bps;
+        msg!("Safety buffer for {}: {} bps", bank.mint_address, safety_buffer_bps);
+        Ok(())
+    })
+}
+
+pub fn process_set_reserve_factor(ctx: Context<UpdateBank>, reserve_factor_bps: u16) -> Result<()> {
+    require!(reserve_factor_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidReserveFactor);
+
+    update_bank(ctx.accounts, |bank| {
+        // Accrue at the old factor first so the new one only applies to interest from now on.
+        force_accrue_interest(bank, Clock::get()?.unix_timestamp)?;
+        bank.reserve_factor_bps = reserve_factor_bps;
+        Ok(())
+    })
+}
+
+pub fn process_set_flash_fee(ctx: Context<UpdateBank>, flash_fee_bps: u16) -> Result<()> {
+    require!(flash_fee_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidFlashFee);
+    update_bank(ctx.accounts, |bank| {
+        bank.flash_fee_bps = flash_fee_bps;
+        Ok(())
+    })
+}
+
+pub fn process_set_deposit_cooldown(ctx: Context<UpdateBank>, deposit_cooldown_seconds: u64) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        bank.deposit_cooldown_seconds = deposit_cooldown_seconds;
+        Ok(())
+    })
+}
+
+pub fn process_set_origination_fee(ctx: Context<UpdateBank>, origination_fee_bps: u16) -> Result<()> {
+    require!(origination_fee_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidOriginationFee);
+    update_bank(ctx.accounts, |bank| {
+        bank.origination_fee_bps = origination_fee_bps;
+        Ok(())
+    })
+}
+
+pub fn process_set_secondary_feed(
+    ctx: Context<UpdateBank>,
+    secondary_feed: Pubkey,
+    max_oracle_deviation_bps: u16,
+) -> Result<()> {
+    require!(max_oracle_deviation_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidOracleDeviation);
+    update_bank(ctx.accounts, |bank| {
+        bank.secondary_feed = secondary_feed;
+        bank.max_oracle_deviation_bps = max_oracle_deviation_bps;
+        Ok(())
+    })
+}
+
+pub fn process_set_price_deviation_limit(
+    ctx: Context<UpdateBank>,
+    max_price_deviation_bps: u16,
+    window_seconds: u64,
+) -> Result<()> {
+    require!(max_price_deviation_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidOracleDeviation);
+    update_bank(ctx.accounts, |bank| {
+        bank.max_price_deviation_bps = max_price_deviation_bps;
+        bank.price_deviation_window_seconds = window_seconds;
+        Ok(())
+    })
+}
+
+pub fn process_set_dynamic_liquidation_bonus(
+    ctx: Context<UpdateBank>,
+    dynamic_bonus: bool,
+    min_liquidation_bonus: u64,
+    max_liquidation_bonus: u64,
+) -> Result<()> {
+    // Same 50% ceiling as the fixed bonus in `init_bank`.
+    require!(min_liquidation_bonus <= max_liquidation_bonus, ErrorCode::InvalidLiquidationBonus);
+    require!(max_liquidation_bonus <= 50, ErrorCode::InvalidLiquidationBonus);
+    update_bank(ctx.accounts, |bank| {
+        bank.dynamic_bonus = dynamic_bonus;
+        bank.min_liquidation_bonus = min_liquidation_bonus;
+        bank.max_liquidation_bonus = max_liquidation_bonus;
+        Ok(())
+    })
+}
+
+pub fn process_set_borrowing_enabled(ctx: Context<UpdateBank>, borrowing_enabled: bool) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        bank.borrowing_enabled = borrowing_enabled;
+        msg!("Borrowing enabled for {}: {}", bank.mint_address, borrowing_enabled);
+        Ok(())
+    })
+}
+
+pub fn process_set_usable_as_collateral(ctx: Context<UpdateBank>, usable_as_collateral: bool) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        bank.usable_as_collateral = usable_as_collateral;
+        msg!("Usable as collateral for {}: {}", bank.mint_address, usable_as_collateral);
+        Ok(())
+    })
+}
+
+pub fn process_set_peg_deviation(ctx: Context<UpdateBank>, peg_deviation_bps: u16) -> Result<()> {
+    require!(peg_deviation_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidOracleDeviation);
+    update_bank(ctx.accounts, |bank| {
+        bank.peg_deviation_bps = peg_deviation_bps;
+        msg!("Peg deviation for {}: {} bps", bank.mint_address, peg_deviation_bps);
+        Ok(())
+    })
+}
+
+pub fn process_set_max_liquidation_value(ctx: Context<UpdateBank>, max_liquidation_value_usd: u128) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        bank.max_liquidation_value_usd = max_liquidation_value_usd;
+        Ok(())
+    })
+}
+
+/// Switches how the bank's asset is priced. An `LpToken` bank is valued from its Raydium CPMM pool's
+/// reserves and the feeds of the two reserve assets, so every instruction that values it needs updates for
+/// both feeds, and the pool, its observation account, both vaults and the LP mint, passed in
+/// `remaining_accounts`.
+pub fn process_set_collateral_pricer(ctx: Context<UpdateBank>, collateral_pricer: CollateralPricer) -> Result<()> {
+    update_bank(ctx.accounts, |bank| {
+        match collateral_pricer {
+            CollateralPricer::DirectFeed => msg!("{} priced from feed 0x{}", bank.mint_address, feed_hex(&bank.price_feed_id)),
+            CollateralPricer::LpToken { reserve_a_feed, reserve_b_feed, pool } => {
+                require!(pool != Pubkey::default(), ErrorCode::InvalidLpPool);
+                msg!(
+                    "{} priced as an LP token of pool {} from feeds 0x{} and 0x{}",
+                    bank.mint_address,
+                    pool,
+                    feed_hex(&reserve_a_feed),
+                    feed_hex(&reserve_b_feed)
+                );
+            }
+        }
+        bank.collateral_pricer = collateral_pricer;
+        Ok(())
+    })
+}
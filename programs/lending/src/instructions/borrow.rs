@@ -1,10 +1,15 @@
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
-use pyth_solana_receiver_sdk::price_update::{self, get_feed_id_from_hex, PriceUpdateV2};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 use crate::state::*; // Assumes your Bank, User, etc., structs are here
 use crate::error::ErrorCode; // Assumes your custom errors are here
-use crate::constants::{SOL_USD_FEED_ID, USDC_USD_FEED_ID, SOL_MINT_ADDRESS}; // Assumes you have these constants defined
+use crate::token_utils::transfer_from_vault;
+use crate::math::{borrow_shares_for_amount, mul_div_ceil, token_value_usd};
+use crate::constants::BPS_DENOMINATOR;
+use crate::health::{apply_isolation, compute_account_health, current_debt, debt_price, is_healthy, load_banks, load_price_updates, Prices};
+use crate::interest::accrue_interest;
+use crate::events::BorrowEvent;
 
 //================================================================
 // Accounts Struct for the Borrow Instruction
@@ -15,24 +20,40 @@ pub struct Borrow<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
-    /// The Mint account of the token the user wants TO BORROW.
+    /// The global protocol config, checked for the pause switch and read for the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the borrow.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The Mint account of the token the user wants TO BORROW. It must belong to `token_program`.
+    #[account(constraint = *mint_to_borrow.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch)]
     pub mint_to_borrow: InterfaceAccount<'info, Mint>,
 
     /// The bank's state account for the asset being borrowed. This is crucial for
-    /// getting the correct rules (like max_ltv) for this specific lending market.
+    /// getting the correct rules (like the borrow cap) for this specific lending market.
     #[account(
         mut,
         seeds = [mint_to_borrow.key().as_ref()],
         bump,
+        constraint = bank.decimals == mint_to_borrow.decimals @ ErrorCode::DecimalsMismatch,
     )]
     pub bank: Account<'info, Bank>,
 
+    /// The bank of the user's OTHER collateral asset. Its max LTV sets how much that collateral
+    /// can be borrowed against.
+    #[account(mut, constraint = other_bank.key() != bank.key() @ ErrorCode::UnsupportedAsset)]
+    pub other_bank: Account<'info, Bank>,
+
     /// The bank's token vault for the asset being borrowed. This is the PDA account
     /// FROM WHICH tokens will be transferred to the user.
     #[account(
         mut,
         seeds = [b"treasury", mint_to_borrow.key().as_ref()],
         bump,
+        constraint = *bank_token_account.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch,
     )]
     pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
@@ -45,19 +66,21 @@ pub struct Borrow<'info> {
     pub user_account: Account<'info, User>,
 
     /// The user's Associated Token Account (ATA) where the borrowed tokens will be sent.
-    /// Anchor creates this account if it doesn't exist (`init_if_needed`).
+    /// Anchor creates this account if it doesn't exist (`init_if_needed`). An existing one must be the
+    /// signer's with no delegate, so nobody else can spend the loan.
     #[account(
         init_if_needed,
         payer = signer,
         associated_token::mint = mint_to_borrow,
         associated_token::authority = signer,
+        associated_token::token_program = token_program,
+        constraint = user_token_account.owner == signer.key() && user_token_account.delegate.is_none() @ ErrorCode::UnexpectedTokenAccountState,
     )]
     pub user_token_account: InterfaceAccount<'info, TokenAccount>,
     
     /// The Pyth PriceUpdateV2 account, which contains recent price feed data.
     /// This is crucial for getting real-time asset prices to value collateral.
-    /// Note: In a real app, you might need to pass multiple price feed accounts
-    /// if they don't all fit in one transaction.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
     pub price_update: Account<'info, PriceUpdateV2>,
 
     /// The SPL Token Program (or the new Token-2022 Interface).
@@ -74,8 +97,11 @@ pub struct Borrow<'info> {
 //================================================================
 // Instruction Logic for Processing a Borrow
 //================================================================
-pub fn process_borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
+pub fn process_borrow(ctx: Context<Borrow>, amount: u64, isolated_mint: Option<Pubkey>) -> Result<()> {
     // --- 1. Security Check ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+    // A deposit-only asset can back loans but can't be lent out itself.
+    require!(ctx.accounts.bank.borrowing_enabled, ErrorCode::BorrowingDisabled);
     if amount == 0 {
         return err!(ErrorCode::ZeroAmount);
     }
@@ -85,125 +111,162 @@ pub fn process_borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
     let price_update = &ctx.accounts.price_update;
     let clock = Clock::get()?;
 
+    // Bring the bank's totals up to date before any share math. The other bank is accrued too, so the
+    // health check values debt there with the interest owed since it was last touched.
+    accrue_interest(bank, clock.unix_timestamp)?;
+    accrue_interest(&mut ctx.accounts.other_bank, clock.unix_timestamp)?;
+
+    // The user receives `amount` but owes it plus the bank's origination fee.
+    let fee = origination_fee(bank, amount)?;
+    let debt = amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    check_min_borrow(bank, user, &ctx.accounts.mint_to_borrow.key(), debt)?;
+    check_borrowed_assets(&ctx.accounts.config, user, &ctx.accounts.mint_to_borrow.key())?;
+
+    // Enforce the bank's borrow cap. A cap of 0 means unlimited.
+    if bank.borrow_cap > 0 {
+        let new_total_borrows = bank.total_borrows.checked_add(debt).ok_or(ErrorCode::MathOverflow)?;
+        require!(new_total_borrows <= bank.borrow_cap, ErrorCode::BorrowCapExceeded);
+    }
+
+    // Fail clearly rather than with a token-program error if the vault can't cover the loan.
+    require!(amount <= bank.available_liquidity(), ErrorCode::InsufficientLiquidity);
+
     // --- 2. Calculate Total Collateral Value (Cross-Collateral Logic) ---
     // This section correctly calculates the total USD value of ALL assets the user has deposited.
     msg!("Calculating total collateral value...");
 
-    // Get the price of SOL.
-    let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID)?;
-    let sol_price = price_update.get_price_no_older_than(&clock, 60, &sol_feed_id)?;
-    
-    // Get the price of USDC.
-    let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID)?;
-    let usdc_price = price_update.get_price_no_older_than(&clock, 60, &usdc_feed_id)?;
-
-    // Calculate the USD value of the user's SOL deposits.
-    let sol_collateral_value = (sol_price.price as u128)
-        .checked_mul(user.deposited_sol as u128)
-        .ok_or(ErrorCode::MathOverflow)?;
-
-    // Calculate the USD value of the user's USDC deposits.
-    let usdc_collateral_value = (usdc_price.price as u128)
-        .checked_mul(user.deposited_usdc as u128)
-        .ok_or(ErrorCode::MathOverflow)?;
-    
-    // Sum the value of all assets to get the total collateral value.
-    let total_collateral_value = sol_collateral_value
-        .checked_add(usdc_collateral_value)
-        .ok_or(ErrorCode::MathOverflow)?;
+    // Pick or enforce the user's collateral mode before deciding which deposits count.
+    apply_isolation(user, ctx.accounts.mint_to_borrow.key(), isolated_mint)?;
 
-    msg!("Total Collateral Value (USD cents equivalent): {}", total_collateral_value);
+    // Positions in any asset besides these two banks' are priced with the banks in `remaining_accounts`.
+    let extra_banks = load_banks(ctx.remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![bank, &ctx.accounts.other_bank];
+    banks.extend(extra_banks.iter());
+
+    // Get every asset's price on the same fixed USD scale before doing any value math.
+    let extra_price_updates = load_price_updates(ctx.remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![price_update];
+    price_updates.extend(extra_price_updates.iter());
+    // Both named banks' prices back this loan, so the stricter staleness limit applies.
+    let max_price_age = bank.max_price_age_seconds.min(ctx.accounts.other_bank.max_price_age_seconds);
+    let prices = Prices::load(&price_updates, ctx.remaining_accounts, &clock, max_price_age, &banks, &ctx.accounts.config.quote_feed_id)?;
+    // A price that jumped too far since the last one accepted can't back new debt until it settles.
+    prices.check_deviation(&banks, clock.unix_timestamp)?;
+
+    // Sum the value of all of the user's counted deposits to get the total collateral value.
+    let health = compute_account_health(user, &prices, &banks)?;
+
+    msg!("Total Collateral Value (USD, 6 decimals): {}", health.total_collateral_value);
 
     // --- 3. Calculate Borrowing Power ---
-    // This calculates the maximum USD value the user is allowed to borrow based on their
-    // total collateral and the bank's Max Loan-to-Value (LTV) ratio.
-    let borrowable_usd_value = total_collateral_value
-        .checked_mul(bank.max_ltv as u128) // e.g., 75
-        .ok_or(ErrorCode::MathOverflow)?
-        .checked_div(100) // for percentage -> e.g., 75 / 100 = 0.75
-        .ok_or(ErrorCode::MathOverflow)?;
-    
-    msg!("Max Borrowable Value (USD cents equivalent): {}", borrowable_usd_value);
+    // This calculates the maximum USD value the user is allowed to borrow: each collateral asset's
+    // value times that asset's own Max Loan-to-Value (LTV) ratio, less what they already owe.
+    let borrowable_usd_value = health.remaining_borrowing_power();
+
+    msg!("Max Borrowable Value (USD, 6 decimals): {}", borrowable_usd_value);
 
     // --- 4. Calculate Requested Borrow Value ---
-    // This determines the USD value of the tokens the user is asking to borrow right now.
-    let requested_borrow_asset_price: i64;
-    match ctx.accounts.mint_to_borrow.key() {
-        key if key == usdc_price.get_price_unchecked().price_expo => {
-            requested_borrow_asset_price = usdc_price.get_price_unchecked().price;
-        }
-        key if key == SOL_MINT_ADDRESS.parse().unwrap() => { // Assumes wSOL mint
-            requested_borrow_asset_price = sol_price.price;
-        }
-        _ => return err!(ErrorCode::UnsupportedAsset) // Strict check for supported assets.
-    }
+    // This determines the USD value of the debt the user is asking to take on right now, fee included.
+    // It is valued the way the health check values debt, so a depegged stablecoin counts at no less than $1
+    // and the value is in the same quote asset as the borrowing power.
+    let requested_borrow_asset_price = prices.in_quote(debt_price(bank, prices.for_mint(&ctx.accounts.mint_to_borrow.key())?))?;
 
-    let requested_borrow_value = (requested_borrow_asset_price as u128)
-        .checked_mul(amount as u128)
-        .ok_or(ErrorCode::MathOverflow)?;
+    let requested_borrow_value = token_value_usd(
+        requested_borrow_asset_price,
+        debt,
+        ctx.accounts.mint_to_borrow.decimals,
+    )?;
 
     // --- 5. The Final Check: Collateral vs. Borrow ---
-    if borrowable_usd_value < requested_borrow_value {
+    if !is_healthy(borrowable_usd_value, requested_borrow_value) {
         return err!(ErrorCode::InsufficientCollateral);
     }
+    // Max LTV alone can sit right under the liquidation threshold, so the new position must also keep
+    // the bank's safety buffer between its debt and the liquidation point.
+    if !is_healthy(health.buffered_borrowing_power(bank.safety_buffer_bps)?, requested_borrow_value) {
+        return err!(ErrorCode::SafetyBufferBreached);
+    }
     
     // --- 6. Transfer Tokens to User (CPI) ---
     // The program signs using its PDA seeds to authorize the transfer FROM the bank's vault.
     let mint_key = ctx.accounts.mint_to_borrow.key();
-    let signer_seeds: &[&[&[u8]]] = &[
-        &[
-            b"treasury",
-            mint_key.as_ref(),
-            &[ctx.bumps.bank_token_account], // The bump seed for the vault PDA
-        ],
-    ];
-    
-    let cpi_accounts = TransferChecked {
-        from: ctx.accounts.bank_token_account.to_account_info(),
-        mint: ctx.accounts.mint_to_borrow.to_account_info(),
-        to: ctx.accounts.user_token_account.to_account_info(),
-        authority: ctx.accounts.bank_token_account.to_account_info(), // The PDA is the authority
-    };
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts).with_signer(signer_seeds);
-
-    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint_to_borrow.decimals)?;
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint_to_borrow.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        ctx.bumps.bank_token_account,
+        amount,
+        ctx.accounts.mint_to_borrow.decimals,
+    )?;
 
     // --- 7. Update Bank and User State (Correct Accounting) ---
     // This logic correctly calculates borrow shares and adds them to the user's LIABILITIES.
-    let users_borrow_shares: u64;
-    if bank.total_borrows == 0 || bank.total_borrow_shares == 0 {
-        users_borrow_shares = amount;
-    } else {
-        users_borrow_shares = (amount as u128)
-            .checked_mul(bank.total_borrow_shares as u128)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(bank.total_borrows as u128)
-            .ok_or(ErrorCode::MathOverflow)? as u64;
-    }
-
-    // Update the bank's global state.
-    bank.total_borrows = bank.total_borrows.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
-    bank.total_borrow_shares = bank.total_borrow_shares.checked_add(users_borrow_shares).ok_or(ErrorCode::MathOverflow)?;
-
-    // Update the user's specific debt accounts.
-    match ctx.accounts.mint_to_borrow.key() {
-        key if key == usdc_price.get_price_unchecked().price_expo => {
-            user.borrowed_usdc = user.borrowed_usdc.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
-            user.borrowed_usdc_shares = user.borrowed_usdc_shares.checked_add(users_borrow_shares).ok_or(ErrorCode::MathOverflow)?;
-        }
-        key if key == SOL_MINT_ADDRESS.parse().unwrap() => {
-            user.borrowed_sol = user.borrowed_sol.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
-            user.borrowed_sol_shares = user.borrowed_sol_shares.checked_add(users_borrow_shares).ok_or(ErrorCode::MathOverflow)?;
-        }
-        _ => return err!(ErrorCode::UnsupportedAsset) // Should be unreachable, but good practice.
-    }
+    let users_borrow_shares = record_borrow(bank, user, &mint_key, debt, fee)?;
 
-    // Update timestamps.
-    bank.last_updated = clock.unix_timestamp;
+    // Update the user's timestamp, and remember the prices this borrow was valued at for the deviation breaker.
     user.last_updated = clock.unix_timestamp;
+    prices.record(bank, clock.unix_timestamp);
+    prices.record(&mut ctx.accounts.other_bank, clock.unix_timestamp);
+    ctx.accounts.protocol_stats.record_borrow(bank, debt, clock.unix_timestamp)?;
 
-    msg!("Borrow successful. Amount: {}, Shares: {}", amount, users_borrow_shares);
+    emit!(BorrowEvent {
+        user: ctx.accounts.signer.key(),
+        mint: ctx.accounts.mint_to_borrow.key(),
+        amount,
+        shares: users_borrow_shares,
+        total_borrows: bank.total_borrows,
+        total_borrow_shares: bank.total_borrow_shares,
+    });
+
+    msg!("Borrow successful. Amount: {}, Fee: {}, Shares: {}", amount, fee, users_borrow_shares);
     
     Ok(())
+}
+
+/// Adds `debt` to what `user` owes in `mint` and to `bank`'s totals, returning the borrow shares minted.
+/// `fee` is the part of `debt` that never left the vault, kept as reserves. Shared by `borrow` and
+/// `deposit_and_borrow`; `bank` must already be accrued to now.
+pub(crate) fn record_borrow(bank: &mut Bank, user: &mut User, mint: &Pubkey, debt: u64, fee: u64) -> Result<u64> {
+    // Fold the interest owed so far into the position before recording it at the current index.
+    let new_debt = current_debt(user, bank, mint)?.checked_add(debt).ok_or(ErrorCode::MathOverflow)?;
+
+    // Debt shares round up, so a borrower always owes at least what they took out.
+    let shares = borrow_shares_for_amount(debt, bank.total_borrows, bank.total_borrow_shares)?;
+    bank.total_borrows = bank.total_borrows.checked_add(debt).ok_or(ErrorCode::MathOverflow)?;
+    bank.total_borrow_shares = bank.total_borrow_shares.checked_add(shares).ok_or(ErrorCode::MathOverflow)?;
+    bank.total_reserves = bank.total_reserves.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+
+    let position = user.position_or_insert(mint)?;
+    let new_asset = position.borrowed_shares == 0;
+    position.borrowed_shares = position.borrowed_shares.checked_add(shares).ok_or(ErrorCode::MathOverflow)?;
+    position.set_debt(new_debt, bank.borrow_index);
+    if new_asset {
+        user.borrowed_asset_count = user.borrowed_asset_count.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    }
+    Ok(shares)
+}
+
+/// Rejects a borrow of `mint` that would have `user` owe more distinct assets than the config's
+/// `max_borrowed_assets`. Borrowing more of an asset they already owe is always allowed.
+pub(crate) fn check_borrowed_assets(config: &Config, user: &User, mint: &Pubkey) -> Result<()> {
+    let new_asset = user.position(mint).is_none_or(|position| position.borrowed_shares == 0);
+    if config.max_borrowed_assets > 0 && new_asset {
+        require!(user.borrowed_asset_count < config.max_borrowed_assets, ErrorCode::TooManyBorrowedAssets);
+    }
+    Ok(())
+}
+
+/// Rejects a borrow of `debt` that would leave `user` owing less than `bank`'s minimum borrow amount in
+/// `mint`, since a position that small costs more to liquidate than it returns. `bank` must already be
+/// accrued to now.
+pub(crate) fn check_min_borrow(bank: &Bank, user: &User, mint: &Pubkey, debt: u64) -> Result<()> {
+    let new_debt = current_debt(user, bank, mint)?.checked_add(debt).ok_or(ErrorCode::MathOverflow)?;
+    require!(new_debt >= bank.min_borrow_amount, ErrorCode::BorrowTooSmall);
+    Ok(())
+}
+
+/// Returns the origination fee `bank` charges on a borrow of `amount`, rounded up.
+pub(crate) fn origination_fee(bank: &Bank, amount: u64) -> Result<u64> {
+    mul_div_ceil(amount, bank.origination_fee_bps as u64, BPS_DENOMINATOR)
 }
\ No newline at end of file
@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::error::ErrorCode;
+
+//================================================================
+// Accounts Struct for the CloseUser Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct CloseUser<'info> {
+    /// The user closing their account. The account's rent is returned to them.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The user's state account, closed once every position in it is empty.
+    #[account(
+        mut,
+        close = signer,
+        seeds = [signer.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Closing a User Account
+//================================================================
+pub fn process_close_user(ctx: Context<CloseUser>) -> Result<()> {
+    // Closing the account would forget any deposit or debt still recorded in it.
+    let is_empty = ctx.accounts.user_account.positions.iter().all(|position| {
+        position.deposited == 0
+            && position.deposited_shares == 0
+            && position.borrowed == 0
+            && position.borrowed_shares == 0
+    });
+    require!(is_empty, ErrorCode::AccountNotEmpty);
+
+    msg!("Closed user account {}", ctx.accounts.user_account.key());
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::transfer_from_vault;
+use crate::interest::accrue_interest;
+
+//================================================================
+// Accounts Struct for the CollectFees Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct CollectFees<'info> {
+    /// The protocol admin, the only account allowed to sweep fees. Pays for the recipient's ATA if needed.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The global protocol config, which names the fee recipient.
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.admin == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+
+    /// The mint of the asset whose fees are being swept.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank whose accumulated reserves are being swept.
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+        constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    /// The bank's vault (PDA) the fees are paid out of.
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Only used as the authority of `fee_recipient_token_account`, and must match the config.
+    #[account(address = config.fee_recipient @ ErrorCode::Unauthorized)]
+    pub fee_recipient: UncheckedAccount<'info>,
+
+    /// The fee recipient's token account (ATA) that receives the fees.
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = fee_recipient,
+        associated_token::token_program = token_program,
+    )]
+    pub fee_recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Fee Sweep
+//================================================================
+/// Pays a bank's whole `total_reserves` to the config's fee recipient and zeroes it.
+pub fn process_collect_fees(ctx: Context<CollectFees>) -> Result<()> {
+    // Bring reserves up to date so the sweep collects everything accrued so far.
+    let now = Clock::get()?.unix_timestamp;
+    accrue_interest(&mut ctx.accounts.bank, now)?;
+
+    let amount = ctx.accounts.bank.total_reserves;
+    require!(amount > 0, ErrorCode::InsufficientReserves);
+
+    // Reserves are only a claim on the vault. The cash depositors could withdraw right now,
+    // `available_liquidity`, stays put, so only what the vault holds beyond it can be swept.
+    let spare = ctx.accounts.bank_token_account.amount.saturating_sub(ctx.accounts.bank.available_liquidity());
+    require!(amount <= spare, ErrorCode::InsufficientLiquidity);
+
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.fee_recipient_token_account.to_account_info(),
+        ctx.bumps.bank_token_account,
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    let bank = &mut ctx.accounts.bank;
+    bank.total_reserves = 0;
+
+    msg!("Swept {} of fees to {}", amount, ctx.accounts.fee_recipient.key());
+    Ok(())
+}
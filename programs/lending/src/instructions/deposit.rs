@@ -1,8 +1,14 @@
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::AssociatedToken;
 // Using token_interface allows for compatibility with both SPL Token and Token-2022
-use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use crate::state::*; // Assuming your Bank and User structs are in here
+use crate::error::ErrorCode;
+use crate::token_utils::transfer_to_vault;
+use crate::interest::accrue_interest;
+use crate::math::shares_for_amount;
+use crate::constants::{DEAD_SHARES, MIN_INITIAL_DEPOSIT};
+use crate::events::DepositEvent;
 
 //================================================================
 // Accounts Struct for the Deposit Instruction
@@ -13,8 +19,17 @@ pub struct Deposit<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
+    /// The global protocol config, checked for the pause switch.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the deposit.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
     /// The Mint account of the token being deposited (e.g., USDC, wSOL).
-    /// This is used to validate the token accounts and for CPI calls.
+    /// This is used to validate the token accounts and for CPI calls. It must belong to `token_program`.
+    #[account(constraint = *mint.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch)]
     pub mint: InterfaceAccount<'info, Mint>,
 
     /// The bank's state account. We use the mint's address as a seed to ensure
@@ -24,6 +39,7 @@ pub struct Deposit<'info> {
         mut,
         seeds = [mint.key().as_ref()],
         bump,
+        constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch,
     )]
     pub bank: Account<'info, Bank>,
 
@@ -34,6 +50,7 @@ pub struct Deposit<'info> {
         mut,
         seeds = [b"treasury", mint.key().as_ref()],
         bump,
+        constraint = *bank_token_account.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch,
     )]
     pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
@@ -54,6 +71,7 @@ pub struct Deposit<'info> {
         mut,
         associated_token::mint = mint,
         associated_token::authority = signer,
+        associated_token::token_program = token_program,
     )]
     pub user_token_account: InterfaceAccount<'info, TokenAccount>,
 
@@ -73,89 +91,101 @@ pub struct Deposit<'info> {
 //================================================================
 pub fn process_deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
     // --- 1. Security Check ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
     // Ensure the user is not trying to deposit zero, which could cause issues.
     if amount == 0 {
-        // You can define custom errors for more specific feedback.
         return err!(ErrorCode::ZeroAmount);
     }
 
+    // Bring the bank's totals up to date before any share math.
+    let now = Clock::get()?.unix_timestamp;
+    accrue_interest(&mut ctx.accounts.bank, now)?;
+
+    // Enforce the bank's supply cap. A cap of 0 means unlimited.
+    let supply_cap = ctx.accounts.bank.supply_cap;
+    if supply_cap > 0 {
+        let new_total_deposits = ctx.accounts.bank.total_deposits.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        require!(new_total_deposits <= supply_cap, ErrorCode::SupplyCapExceeded);
+    }
+
     // --- 2. Transfer Tokens via CPI ---
     // This section creates a Cross-Program Invocation (CPI) to the official
     // SPL Token Program to securely transfer tokens from the user's account
     // to the bank's vault.
-    let transfer_cpi_accounts = TransferChecked {
-        from: ctx.accounts.user_token_account.to_account_info(),
-        mint: ctx.accounts.mint.to_account_info(),
-        to: ctx.accounts.bank_token_account.to_account_info(),
-        authority: ctx.accounts.signer.to_account_info(),
-    };
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts);
-
+    let vault_balance_before = ctx.accounts.bank_token_account.amount;
     // `transfer_checked` is safer than `transfer` because it requires the `decimals`
     // parameter, preventing potential token scaling attacks.
-    token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+    transfer_to_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.signer.to_account_info(),
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    // A Token-2022 mint with a transfer fee delivers less than was sent, so only credit what the
+    // vault actually received.
+    ctx.accounts.bank_token_account.reload()?;
+    let amount = ctx.accounts.bank_token_account.amount
+        .checked_sub(vault_balance_before)
+        .ok_or(ErrorCode::MathOverflow)?;
 
     // --- 3. Calculate Deposit Shares ---
     // This is the core logic for a lending protocol. We mint "shares" that represent
     // a user's claim on the underlying assets in the bank. This system ensures
     // that interest earned by the bank is distributed proportionally to all depositors.
     let bank = &mut ctx.accounts.bank;
-    let users_shares: u64;
+    let (credited_amount, users_shares) = mint_deposit_shares(bank, amount)?;
 
-    if bank.total_deposits == 0 || bank.total_deposit_shares == 0 {
-        // CASE A: The bank is empty (first depositor ever for this asset).
-        // The share price is initialized at 1:1. 1 token = 1 share.
-        users_shares = amount;
-    } else {
-        // CASE B: The bank already has deposits.
-        // We calculate the number of shares to mint based on the current ratio of
-        // shares to tokens. This prevents diluting the value for existing depositors.
-        // Formula: new_shares = (amount_to_deposit * total_shares) / total_tokens
-        //
-        // We use u128 for the intermediate multiplication to prevent arithmetic overflow,
-        // which can happen if `amount` and `total_deposit_shares` are both large.
-        users_shares = (amount as u128)
-            .checked_mul(bank.total_deposit_shares as u128)
-            .unwrap() // Use .ok_or(ErrorCode::MathOverflow)? for better error handling
-            .checked_div(bank.total_deposits as u128)
-            .unwrap() as u64;
-    }
-
-    // --- 4. Update User and Bank State ---
+    // --- 4. Update User State ---
     let user = &mut ctx.accounts.user_account;
 
-    // The logic below assumes the User struct has specific fields like `deposited_usdc`.
-    // A more scalable design might use a Map or a Vec of structs, but this is clear
-    // for a tutorial.
-    match ctx.accounts.mint.key() {
-        // A placeholder for the actual USDC mint address on mainnet/devnet
-        key if key == pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v") => {
-            user.deposited_usdc = user.deposited_usdc.checked_add(amount).unwrap();
-            user.deposited_usdc_shares = user.deposited_usdc_shares.checked_add(users_shares).unwrap();
-        }
-        // A placeholder for the wSOL mint address
-        key if key == pubkey!("So11111111111111111111111111111111111111112") => {
-            user.deposited_sol = user.deposited_sol.checked_add(amount).unwrap();
-            user.deposited_sol_shares = user.deposited_sol_shares.checked_add(users_shares).unwrap();
-        }
-        _ => {
-            // It's good practice to return an error if the asset is not supported.
-            return err!(ErrorCode::UnsupportedAsset);
-        }
-    }
+    let position = user.position_or_insert(&ctx.accounts.mint.key())?;
+    position.deposited = position.deposited.checked_add(credited_amount).ok_or(ErrorCode::MathOverflow)?;
+    position.deposited_shares = position.deposited_shares.checked_add(users_shares).ok_or(ErrorCode::MathOverflow)?;
+    position.last_deposit_ts = now;
 
-    // Finally, update the bank's global state totals.
-    bank.total_deposits = bank.total_deposits.checked_add(amount).unwrap();
-    bank.total_deposit_shares = bank.total_deposit_shares.checked_add(users_shares).unwrap();
+    // Update the user's timestamp to reflect recent activity.
+    user.last_updated = now;
+    ctx.accounts.protocol_stats.record_deposit(bank, amount, now)?;
 
-    // Update the timestamp to reflect recent activity. Useful for interest calculations.
-    bank.last_updated = Clock::get()?.unix_timestamp;
-    user.last_updated = Clock::get()?.unix_timestamp;
+    emit!(DepositEvent {
+        user: ctx.accounts.signer.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        shares: users_shares,
+        total_deposits: bank.total_deposits,
+        total_deposit_shares: bank.total_deposit_shares,
+    });
 
     msg!("Deposit successful. Amount: {}, Shares minted: {}", amount, users_shares);
 
     Ok(())
 }
 
-/
+/// Mints deposit shares for `amount` tokens added to `bank`'s vault and adds both to the bank's totals.
+/// Returns the tokens and shares credited to the depositor.
+///
+/// The very first deposit must be at least `MIN_INITIAL_DEPOSIT`, and `DEAD_SHARES` of it are locked in
+/// the bank for good. Without them an attacker could own the only share, donate tokens straight to the
+/// vault to inflate its price, and have later deposits round down to nothing.
+pub(crate) fn mint_deposit_shares(bank: &mut Bank, amount: u64) -> Result<(u64, u64)> {
+    let (credited_amount, shares) = if bank.total_deposit_shares == 0 {
+        require!(amount >= MIN_INITIAL_DEPOSIT, ErrorCode::InitialDepositTooSmall);
+        bank.total_deposit_shares = DEAD_SHARES;
+        bank.total_deposits = bank.total_deposits.checked_add(DEAD_SHARES).ok_or(ErrorCode::MathOverflow)?;
+        (amount - DEAD_SHARES, amount - DEAD_SHARES)
+    } else {
+        // Shares are rounded down in the bank's favour, and a deposit too small to mint a single share
+        // is rejected rather than silently donated to the other depositors.
+        let shares = shares_for_amount(amount, bank.total_deposits, bank.total_deposit_shares)?;
+        require!(shares > 0, ErrorCode::DepositTooSmall);
+        (amount, shares)
+    };
+
+    bank.total_deposits = bank.total_deposits.checked_add(credited_amount).ok_or(ErrorCode::MathOverflow)?;
+    bank.total_deposit_shares = bank.total_deposit_shares.checked_add(shares).ok_or(ErrorCode::MathOverflow)?;
+    Ok((credited_amount, shares))
+}
@@ -0,0 +1,234 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::{transfer_from_vault, transfer_to_vault};
+use crate::math::token_value_usd;
+use super::borrow::{check_borrowed_assets, check_min_borrow, origination_fee, record_borrow};
+use super::deposit::mint_deposit_shares;
+use crate::health::{apply_isolation, compute_account_health, debt_price, is_healthy, load_banks, load_price_updates, Prices};
+use crate::interest::accrue_interest;
+use crate::events::{BorrowEvent, DepositEvent};
+
+//================================================================
+// Accounts Struct for the DepositAndBorrow Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct DepositAndBorrow<'info> {
+    /// The user depositing collateral and borrowing against it in one go.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The global protocol config, checked for the pause switch and read for the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the deposit and the borrow.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The Mint account of the token being deposited as collateral.
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's state account for the collateral asset.
+    #[account(mut, seeds = [collateral_mint.key().as_ref()], bump, constraint = collateral_bank.decimals == collateral_mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub collateral_bank: Account<'info, Bank>,
+
+    /// The bank's vault for the collateral asset, where the deposit is sent.
+    #[account(mut, seeds = [b"treasury", collateral_mint.key().as_ref()], bump)]
+    pub collateral_bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account the collateral is deposited from.
+    #[account(
+        mut,
+        associated_token::mint = collateral_mint,
+        associated_token::authority = signer,
+    )]
+    pub user_collateral_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The Mint account of the token being borrowed.
+    pub borrow_mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's state account for the borrowed asset.
+    #[account(mut, seeds = [borrow_mint.key().as_ref()], bump, constraint = borrow_bank.decimals == borrow_mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub borrow_bank: Account<'info, Bank>,
+
+    /// The bank's vault for the borrowed asset, from which the loan is paid out.
+    #[account(mut, seeds = [b"treasury", borrow_mint.key().as_ref()], bump)]
+    pub borrow_bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account the borrowed tokens are sent to. An existing one must be the signer's
+    /// with no delegate, as in `borrow`.
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = borrow_mint,
+        associated_token::authority = signer,
+        constraint = user_borrow_token_account.owner == signer.key() && user_borrow_token_account.delegate.is_none() @ ErrorCode::UnexpectedTokenAccountState,
+    )]
+    pub user_borrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's state account, which tracks their total portfolio of deposits and borrows.
+    #[account(mut, seeds = [signer.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    /// Pyth price feed account for valuing the user's collateral.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    // Standard required programs
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Deposit followed by a Borrow
+//================================================================
+pub fn process_deposit_and_borrow(
+    ctx: Context<DepositAndBorrow>,
+    deposit_amount: u64,
+    borrow_amount: u64,
+    isolated_mint: Option<Pubkey>,
+) -> Result<()> {
+    // --- 1. Security Checks ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+    require!(ctx.accounts.borrow_bank.borrowing_enabled, ErrorCode::BorrowingDisabled);
+    if deposit_amount == 0 || borrow_amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
+    require_keys_neq!(ctx.accounts.collateral_mint.key(), ctx.accounts.borrow_mint.key(), ErrorCode::IdenticalMints);
+
+    // Bring both banks' totals up to date before any share math.
+    let clock = Clock::get()?;
+    accrue_interest(&mut ctx.accounts.collateral_bank, clock.unix_timestamp)?;
+    accrue_interest(&mut ctx.accounts.borrow_bank, clock.unix_timestamp)?;
+
+    // Enforce both banks' caps. A cap of 0 means unlimited.
+    let collateral_bank = &ctx.accounts.collateral_bank;
+    if collateral_bank.supply_cap > 0 {
+        let new_total_deposits = collateral_bank.total_deposits.checked_add(deposit_amount).ok_or(ErrorCode::MathOverflow)?;
+        require!(new_total_deposits <= collateral_bank.supply_cap, ErrorCode::SupplyCapExceeded);
+    }
+    // The user receives `borrow_amount` but owes it plus the borrow bank's origination fee.
+    let borrow_bank = &ctx.accounts.borrow_bank;
+    let fee = origination_fee(borrow_bank, borrow_amount)?;
+    let debt = borrow_amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    check_min_borrow(borrow_bank, &ctx.accounts.user_account, &ctx.accounts.borrow_mint.key(), debt)?;
+    check_borrowed_assets(&ctx.accounts.config, &ctx.accounts.user_account, &ctx.accounts.borrow_mint.key())?;
+    if borrow_bank.borrow_cap > 0 {
+        let new_total_borrows = borrow_bank.total_borrows.checked_add(debt).ok_or(ErrorCode::MathOverflow)?;
+        require!(new_total_borrows <= borrow_bank.borrow_cap, ErrorCode::BorrowCapExceeded);
+    }
+    require!(borrow_amount <= borrow_bank.available_liquidity(), ErrorCode::InsufficientLiquidity);
+
+    // --- 2. Deposit the Collateral ---
+    let vault_balance_before = ctx.accounts.collateral_bank_token_account.amount;
+    transfer_to_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.user_collateral_token_account.to_account_info(),
+        ctx.accounts.collateral_mint.to_account_info(),
+        ctx.accounts.collateral_bank_token_account.to_account_info(),
+        ctx.accounts.signer.to_account_info(),
+        deposit_amount,
+        ctx.accounts.collateral_mint.decimals,
+    )?;
+
+    // Credit only what the vault received, net of any Token-2022 transfer fee.
+    ctx.accounts.collateral_bank_token_account.reload()?;
+    let deposit_amount = ctx.accounts.collateral_bank_token_account.amount
+        .checked_sub(vault_balance_before)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    let collateral_bank = &mut ctx.accounts.collateral_bank;
+    let (credited_amount, deposit_shares) = mint_deposit_shares(collateral_bank, deposit_amount)?;
+
+    let user = &mut ctx.accounts.user_account;
+    let position = user.position_or_insert(&ctx.accounts.collateral_mint.key())?;
+    position.deposited = position.deposited.checked_add(credited_amount).ok_or(ErrorCode::MathOverflow)?;
+    position.deposited_shares = position.deposited_shares.checked_add(deposit_shares).ok_or(ErrorCode::MathOverflow)?;
+    position.last_deposit_ts = clock.unix_timestamp;
+
+    // --- 3. Borrow Health Check Against the Updated Collateral ---
+    apply_isolation(user, ctx.accounts.borrow_mint.key(), isolated_mint)?;
+    let borrow_bank = &ctx.accounts.borrow_bank;
+    let extra_banks = load_banks(ctx.remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![collateral_bank, borrow_bank];
+    banks.extend(extra_banks.iter());
+    let extra_price_updates = load_price_updates(ctx.remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![&ctx.accounts.price_update];
+    price_updates.extend(extra_price_updates.iter());
+    let prices = Prices::load(
+        &price_updates,
+        ctx.remaining_accounts,
+        &clock,
+        collateral_bank.max_price_age_seconds.min(borrow_bank.max_price_age_seconds),
+        &banks,
+        &ctx.accounts.config.quote_feed_id,
+    )?;
+    prices.check_deviation(&banks, clock.unix_timestamp)?;
+    let health = compute_account_health(user, &prices, &banks)?;
+
+    let borrowable_usd_value = health.remaining_borrowing_power();
+    let requested_borrow_value = token_value_usd(
+        prices.in_quote(debt_price(borrow_bank, prices.for_mint(&ctx.accounts.borrow_mint.key())?))?,
+        debt,
+        ctx.accounts.borrow_mint.decimals,
+    )?;
+    if !is_healthy(borrowable_usd_value, requested_borrow_value) {
+        return err!(ErrorCode::InsufficientCollateral);
+    }
+    if !is_healthy(health.buffered_borrowing_power(borrow_bank.safety_buffer_bps)?, requested_borrow_value) {
+        return err!(ErrorCode::SafetyBufferBreached);
+    }
+
+    // --- 4. Pay Out the Loan ---
+    let borrow_mint_key = ctx.accounts.borrow_mint.key();
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.borrow_bank_token_account.to_account_info(),
+        ctx.accounts.borrow_mint.to_account_info(),
+        ctx.accounts.user_borrow_token_account.to_account_info(),
+        ctx.bumps.borrow_bank_token_account,
+        borrow_amount,
+        ctx.accounts.borrow_mint.decimals,
+    )?;
+
+    let borrow_shares = record_borrow(
+        &mut ctx.accounts.borrow_bank,
+        &mut ctx.accounts.user_account,
+        &borrow_mint_key,
+        debt,
+        fee,
+    )?;
+
+    // Update the user's timestamp, and remember the prices this borrow was valued at for the deviation breaker.
+    ctx.accounts.user_account.last_updated = clock.unix_timestamp;
+    prices.record(&mut ctx.accounts.collateral_bank, clock.unix_timestamp);
+    prices.record(&mut ctx.accounts.borrow_bank, clock.unix_timestamp);
+    let stats = &mut ctx.accounts.protocol_stats;
+    stats.record_deposit(&ctx.accounts.collateral_bank, deposit_amount, clock.unix_timestamp)?;
+    stats.record_borrow(&ctx.accounts.borrow_bank, debt, clock.unix_timestamp)?;
+
+    emit!(DepositEvent {
+        user: ctx.accounts.signer.key(),
+        mint: ctx.accounts.collateral_mint.key(),
+        amount: deposit_amount,
+        shares: deposit_shares,
+        total_deposits: ctx.accounts.collateral_bank.total_deposits,
+        total_deposit_shares: ctx.accounts.collateral_bank.total_deposit_shares,
+    });
+    emit!(BorrowEvent {
+        user: ctx.accounts.signer.key(),
+        mint: ctx.accounts.borrow_mint.key(),
+        amount: borrow_amount,
+        shares: borrow_shares,
+        total_borrows: ctx.accounts.borrow_bank.total_borrows,
+        total_borrow_shares: ctx.accounts.borrow_bank.total_borrow_shares,
+    });
+
+    msg!("Deposited {} and borrowed {} in one shot.", deposit_amount, borrow_amount);
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::transfer_to_vault;
+use crate::interest::accrue_interest;
+use crate::events::DepositEvent;
+use super::deposit::mint_deposit_shares;
+
+/// How many assets `deposit_multi` supplies at once, one per mint/bank/vault/ATA group in its accounts.
+const DEPOSIT_MULTI_ASSETS: usize = 2;
+
+//================================================================
+// Accounts Struct for the DepositMulti Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct DepositMulti<'info> {
+    /// The user supplying both assets, who signs for both transfers.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The global protocol config, checked for the pause switch.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the deposits.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The Mint account of the first asset being deposited.
+    pub first_mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's state account for the first asset.
+    #[account(mut, seeds = [first_mint.key().as_ref()], bump, constraint = first_bank.decimals == first_mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub first_bank: Account<'info, Bank>,
+
+    /// The bank's vault for the first asset.
+    #[account(mut, seeds = [b"treasury", first_mint.key().as_ref()], bump)]
+    pub first_bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account the first asset is deposited from.
+    #[account(
+        mut,
+        associated_token::mint = first_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub user_first_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The Mint account of the second asset being deposited.
+    pub second_mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's state account for the second asset.
+    #[account(mut, seeds = [second_mint.key().as_ref()], bump, constraint = second_bank.decimals == second_mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub second_bank: Account<'info, Bank>,
+
+    /// The bank's vault for the second asset.
+    #[account(mut, seeds = [b"treasury", second_mint.key().as_ref()], bump)]
+    pub second_bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account the second asset is deposited from.
+    #[account(
+        mut,
+        associated_token::mint = second_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub user_second_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's state account, which tracks their deposits and shares.
+    #[account(mut, seeds = [signer.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    // Standard required programs
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Batch Deposit
+//================================================================
+/// Deposits `amounts[0]` of the first mint and `amounts[1]` of the second in one instruction, so a user
+/// supplying two collateral assets doesn't need two transactions. Either both deposits land or neither does.
+pub fn process_deposit_multi(ctx: Context<DepositMulti>, amounts: Vec<u64>) -> Result<()> {
+    // --- 1. Security Checks ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+    // There is one amount per mint/bank/vault/ATA group in the accounts, in the same order.
+    require!(amounts.len() == DEPOSIT_MULTI_ASSETS, ErrorCode::DepositLengthMismatch);
+    if amounts.contains(&0) {
+        return err!(ErrorCode::ZeroAmount);
+    }
+    require_keys_neq!(ctx.accounts.first_mint.key(), ctx.accounts.second_mint.key(), ErrorCode::IdenticalMints);
+
+    // Bring both banks' totals up to date before any share math.
+    let now = Clock::get()?.unix_timestamp;
+    accrue_interest(&mut ctx.accounts.first_bank, now)?;
+    accrue_interest(&mut ctx.accounts.second_bank, now)?;
+
+    // Enforce both banks' supply caps before moving anything. A cap of 0 means unlimited.
+    for (bank, amount) in [&ctx.accounts.first_bank, &ctx.accounts.second_bank].into_iter().zip(&amounts) {
+        if bank.supply_cap > 0 {
+            let new_total_deposits = bank.total_deposits.checked_add(*amount).ok_or(ErrorCode::MathOverflow)?;
+            require!(new_total_deposits <= bank.supply_cap, ErrorCode::SupplyCapExceeded);
+        }
+    }
+
+    // --- 2. Deposit Each Asset ---
+    let accounts = &mut *ctx.accounts;
+    deposit_one(
+        &accounts.signer,
+        &accounts.first_mint,
+        &mut accounts.first_bank,
+        &mut accounts.first_bank_token_account,
+        &accounts.user_first_token_account,
+        &mut accounts.user_account,
+        &mut accounts.protocol_stats,
+        &accounts.token_program,
+        amounts[0],
+        now,
+    )?;
+    deposit_one(
+        &accounts.signer,
+        &accounts.second_mint,
+        &mut accounts.second_bank,
+        &mut accounts.second_bank_token_account,
+        &accounts.user_second_token_account,
+        &mut accounts.user_account,
+        &mut accounts.protocol_stats,
+        &accounts.token_program,
+        amounts[1],
+        now,
+    )?;
+
+    accounts.user_account.last_updated = now;
+    msg!("Batch deposit successful. Amounts: {:?}", amounts);
+    Ok(())
+}
+
+/// Transfers `amount` of `mint` into its bank's vault and credits the shares it mints to the user,
+/// exactly as `deposit` does.
+#[allow(clippy::too_many_arguments)]
+fn deposit_one<'info>(
+    signer: &Signer<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    bank: &mut Account<'info, Bank>,
+    bank_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    user_token_account: &InterfaceAccount<'info, TokenAccount>,
+    user: &mut User,
+    stats: &mut ProtocolStats,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    let vault_balance_before = bank_token_account.amount;
+    transfer_to_vault(
+        token_program.to_account_info(),
+        user_token_account.to_account_info(),
+        mint.to_account_info(),
+        bank_token_account.to_account_info(),
+        signer.to_account_info(),
+        amount,
+        mint.decimals,
+    )?;
+
+    // Credit only what the vault received, net of any Token-2022 transfer fee.
+    bank_token_account.reload()?;
+    let amount = bank_token_account.amount
+        .checked_sub(vault_balance_before)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    let (credited_amount, shares) = mint_deposit_shares(bank, amount)?;
+    let position = user.position_or_insert(&mint.key())?;
+    position.deposited = position.deposited.checked_add(credited_amount).ok_or(ErrorCode::MathOverflow)?;
+    position.deposited_shares = position.deposited_shares.checked_add(shares).ok_or(ErrorCode::MathOverflow)?;
+    position.last_deposit_ts = now;
+    stats.record_deposit(bank, amount, now)?;
+
+    emit!(DepositEvent {
+        user: signer.key(),
+        mint: mint.key(),
+        amount,
+        shares,
+        total_deposits: bank.total_deposits,
+        total_deposit_shares: bank.total_deposit_shares,
+    });
+    Ok(())
+}
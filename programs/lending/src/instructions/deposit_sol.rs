@@ -0,0 +1,135 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use anchor_spl::token::{self, SyncNative, Token};
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::constants::SOL_MINT_ADDRESS;
+use crate::interest::accrue_interest;
+use crate::events::DepositEvent;
+use super::deposit::mint_deposit_shares;
+
+//================================================================
+// Accounts Struct for the DepositSol Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct DepositSol<'info> {
+    /// The user depositing native SOL. Their lamports are moved straight into the vault.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The global protocol config, checked for the pause switch.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the deposit.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The wrapped SOL mint. Only the SOL bank accepts native deposits.
+    #[account(address = SOL_MINT_ADDRESS @ ErrorCode::UnsupportedAsset)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The SOL bank's state account.
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+        constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    /// The SOL bank's vault, a wrapped SOL token account owned by its own PDA.
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's state account, which tracks their deposits and shares.
+    #[account(
+        mut,
+        seeds = [signer.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+
+    /// Wrapped SOL only exists under the original SPL Token program.
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Native SOL Deposit
+//================================================================
+pub fn process_deposit_sol(ctx: Context<DepositSol>, amount: u64) -> Result<()> {
+    // --- 1. Security Check ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+    if amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    accrue_interest(&mut ctx.accounts.bank, now)?;
+
+    let supply_cap = ctx.accounts.bank.supply_cap;
+    if supply_cap > 0 {
+        let new_total_deposits = ctx.accounts.bank.total_deposits.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        require!(new_total_deposits <= supply_cap, ErrorCode::SupplyCapExceeded);
+    }
+
+    // --- 2. Wrap the Lamports Into the Vault ---
+    // The lamports go straight into the vault, which is already rent-exempt, so no temporary wrapped
+    // SOL account is needed. `sync_native` then raises the vault's token balance to match everything
+    // it holds above its rent-exempt reserve.
+    let vault_balance_before = ctx.accounts.bank_token_account.amount;
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.signer.to_account_info(),
+                to: ctx.accounts.bank_token_account.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+    token::sync_native(CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        SyncNative { account: ctx.accounts.bank_token_account.to_account_info() },
+    ))?;
+
+    // `sync_native` also picks up any lamports someone sent the vault directly, which aren't this
+    // depositor's, so credit no more than they actually sent.
+    ctx.accounts.bank_token_account.reload()?;
+    let amount = ctx.accounts.bank_token_account.amount
+        .checked_sub(vault_balance_before)
+        .ok_or(ErrorCode::MathOverflow)?
+        .min(amount);
+
+    // --- 3. Mint Deposit Shares and Update State ---
+    let bank = &mut ctx.accounts.bank;
+    let (credited_amount, users_shares) = mint_deposit_shares(bank, amount)?;
+
+    let user = &mut ctx.accounts.user_account;
+    let position = user.position_or_insert(&ctx.accounts.mint.key())?;
+    position.deposited = position.deposited.checked_add(credited_amount).ok_or(ErrorCode::MathOverflow)?;
+    position.deposited_shares = position.deposited_shares.checked_add(users_shares).ok_or(ErrorCode::MathOverflow)?;
+    position.last_deposit_ts = now;
+
+    user.last_updated = now;
+    ctx.accounts.protocol_stats.record_deposit(bank, amount, now)?;
+
+    emit!(DepositEvent {
+        user: ctx.accounts.signer.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        shares: users_shares,
+        total_deposits: bank.total_deposits,
+        total_deposit_shares: bank.total_deposit_shares,
+    });
+
+    msg!("Native SOL deposit successful. Amount: {}, Shares minted: {}", amount, users_shares);
+
+    Ok(())
+}
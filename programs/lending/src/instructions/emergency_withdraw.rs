@@ -0,0 +1,72 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::transfer_from_vault;
+
+//================================================================
+// Accounts Struct for the EmergencyWithdraw Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    /// The protocol admin, the only account allowed to drain a treasury.
+    pub signer: Signer<'info>,
+
+    /// The global protocol config. The protocol must be paused first.
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.admin == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+
+    /// The mint of the asset being pulled out.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's vault (PDA) being drained.
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The admin's token account (ATA) that receives the funds, e.g. a multisig's.
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+//================================================================
+// Instruction Logic for Processing an Emergency Withdrawal
+//================================================================
+/// Moves `amount` out of a bank's treasury while the protocol is paused. Bank accounting is left
+/// untouched on purpose, so depositors' claims can be reconciled off-chain afterwards.
+pub fn process_emergency_withdraw(ctx: Context<EmergencyWithdraw>, amount: u64) -> Result<()> {
+    require!(ctx.accounts.config.paused, ErrorCode::ProtocolNotPaused);
+    if amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
+
+    let mint_key = ctx.accounts.mint.key();
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.destination.to_account_info(),
+        ctx.bumps.bank_token_account,
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    msg!("Emergency withdrawal of {} from the {} treasury", amount, mint_key);
+    Ok(())
+}
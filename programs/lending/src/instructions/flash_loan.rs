@@ -0,0 +1,162 @@
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as sysvar_instructions,
+    load_current_index_checked,
+    load_instruction_at_checked,
+};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::{transfer_from_vault, transfer_to_vault};
+use crate::constants::BPS_DENOMINATOR;
+use crate::token_extensions::gross_up_for_transfer_fee;
+
+//================================================================
+// Accounts Struct for the FlashBorrow Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct FlashBorrow<'info> {
+    /// The borrower. Receives the loan and must repay it later in the same transaction.
+    pub signer: Signer<'info>,
+
+    /// The global protocol config, checked for the pause switch.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// The Mint account of the token being flash borrowed.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank lending the tokens. Records what is owed until `flash_repay` runs.
+    #[account(mut, seeds = [mint.key().as_ref()], bump, constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub bank: Account<'info, Bank>,
+
+    /// The bank's vault the loan is paid out of.
+    #[account(mut, seeds = [b"treasury", mint.key().as_ref()], bump)]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The borrower's token account the loan is sent to.
+    #[account(mut, token::mint = mint)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The instructions sysvar, used to check that a matching `flash_repay` follows in this transaction.
+    /// CHECK: Constrained to the instructions sysvar address.
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+//================================================================
+// Accounts Struct for the FlashRepay Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    /// The borrower returning the loan plus fee.
+    pub signer: Signer<'info>,
+
+    /// The Mint account of the token being repaid.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank that made the loan.
+    #[account(mut, seeds = [mint.key().as_ref()], bump, constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub bank: Account<'info, Bank>,
+
+    /// The bank's vault the loan is returned to.
+    #[account(mut, seeds = [b"treasury", mint.key().as_ref()], bump)]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The borrower's token account the repayment is taken from.
+    #[account(mut, token::mint = mint, token::authority = signer)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Flash Borrow
+//================================================================
+pub fn process_flash_borrow(ctx: Context<FlashBorrow>, amount: u64) -> Result<()> {
+    // --- 1. Security Checks ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+    if amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
+    // Only one flash loan per bank may be in flight at a time.
+    require!(ctx.accounts.bank.flash_loan_principal == 0, ErrorCode::FlashLoanNotRepaid);
+
+    // --- 2. Require a Matching Repay Later in this Transaction ---
+    // The current instruction must be ours at the top level, so the index below really refers to it.
+    let instructions = &ctx.accounts.instructions;
+    let current_index = load_current_index_checked(instructions)? as usize;
+    let current = load_instruction_at_checked(current_index, instructions)?;
+    require_keys_eq!(current.program_id, crate::ID, ErrorCode::FlashLoanNotRepaid);
+
+    let bank_key = ctx.accounts.bank.key();
+    let mut index = current_index + 1;
+    let repay_follows = loop {
+        let Ok(ix) = load_instruction_at_checked(index, instructions) else {
+            break false;
+        };
+        if ix.program_id == crate::ID
+            && ix.data.starts_with(crate::instruction::FlashRepay::DISCRIMINATOR)
+            && ix.accounts.iter().any(|meta| meta.pubkey == bank_key)
+        {
+            break true;
+        }
+        index += 1;
+    };
+    require!(repay_follows, ErrorCode::FlashLoanNotRepaid);
+
+    // --- 3. Pay Out the Loan ---
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        ctx.bumps.bank_token_account,
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    // --- 4. Record What is Owed ---
+    let bank = &mut ctx.accounts.bank;
+    bank.flash_loan_principal = amount;
+    bank.flash_loan_fee = (amount as u128)
+        .checked_mul(bank.flash_fee_bps as u128).ok_or(ErrorCode::MathOverflow)?
+        .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::MathOverflow)? as u64;
+
+    msg!("Flash loan of {} issued. Fee: {}", amount, bank.flash_loan_fee);
+    Ok(())
+}
+
+//================================================================
+// Instruction Logic for Processing a Flash Repay
+//================================================================
+pub fn process_flash_repay(ctx: Context<FlashRepay>) -> Result<()> {
+    let bank = &ctx.accounts.bank;
+    require!(bank.flash_loan_principal > 0, ErrorCode::NothingToRepay);
+    let owed = bank.flash_loan_principal.checked_add(bank.flash_loan_fee).ok_or(ErrorCode::MathOverflow)?;
+
+    // Pull back the principal plus fee, grossed up so a Token-2022 transfer fee can't leave the vault
+    // short of it. If the borrower can't cover it the whole transaction reverts.
+    let transfer_amount = gross_up_for_transfer_fee(&ctx.accounts.mint.to_account_info(), owed, Clock::get()?.epoch)?;
+    transfer_to_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.signer.to_account_info(),
+        transfer_amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    // The principal is back in the vault; the fee is paid out to lenders like interest.
+    let bank = &mut ctx.accounts.bank;
+    bank.total_deposits = bank.total_deposits.checked_add(bank.flash_loan_fee).ok_or(ErrorCode::MathOverflow)?;
+    bank.flash_loan_principal = 0;
+    bank.flash_loan_fee = 0;
+
+    msg!("Flash loan repaid: {}", owed);
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+use crate::state::*;
+use crate::interest::{accrue_interest, current_borrow_rate, current_supply_rate, utilization};
+
+//================================================================
+// Accounts Struct for the GetBankRates Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct GetBankRates<'info> {
+    /// The mint of the asset being queried.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank being queried. Read-only: interest is accrued on a copy.
+    #[account(seeds = [mint.key().as_ref()], bump)]
+    pub bank: Account<'info, Bank>,
+}
+
+/// The live rates of a bank as returned by `get_bank_rates`, all in basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct BankRatesView {
+    pub utilization: u64,
+    pub borrow_rate: u64,
+    pub supply_rate: u64,
+}
+
+//================================================================
+// Instruction Logic for Processing a Bank Rates Query
+//================================================================
+pub fn process_get_bank_rates(ctx: Context<GetBankRates>) -> Result<BankRatesView> {
+    // Accrue on a copy so the query reflects interest owed up to now without writing to the bank.
+    let mut bank = ctx.accounts.bank.clone().into_inner();
+    accrue_interest(&mut bank, Clock::get()?.unix_timestamp)?;
+
+    // Anchor serializes the returned value into the transaction's return data.
+    Ok(BankRatesView {
+        utilization: utilization(&bank),
+        borrow_rate: current_borrow_rate(&bank),
+        supply_rate: current_supply_rate(&bank),
+    })
+}
@@ -0,0 +1,82 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::constants::{SOL_MINT_ADDRESS, USDC_MINT_ADDRESS};
+use crate::health::{compute_account_health, load_banks, load_price_updates, Prices};
+use crate::interest::accrue_interest;
+
+//================================================================
+// Accounts Struct for the GetHealth Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct GetHealth<'info> {
+    /// The owner of the position being queried. Anyone may query any user, so this is NOT a signer.
+    /// CHECK: The user_account is derived from this key, ensuring we read the correct position.
+    pub owner: AccountInfo<'info>,
+
+    /// The global protocol config, read for the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// The state account of the user being queried. Read-only.
+    #[account(seeds = [owner.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    /// The SOL bank, whose liquidation threshold weights the user's SOL collateral. Read-only: interest
+    /// is accrued on a copy.
+    #[account(constraint = sol_bank.mint_address == SOL_MINT_ADDRESS @ ErrorCode::UnsupportedAsset)]
+    pub sol_bank: Account<'info, Bank>,
+
+    /// The USDC bank, whose liquidation threshold weights the user's USDC collateral. Read-only: interest
+    /// is accrued on a copy.
+    #[account(constraint = usdc_bank.mint_address == USDC_MINT_ADDRESS @ ErrorCode::UnsupportedAsset)]
+    pub usdc_bank: Account<'info, Bank>,
+
+    /// Pyth price feed account for valuing assets.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+}
+
+/// The health of a position as returned by `get_health`, on the `USD_EXPONENT` scale in the config's
+/// quote asset.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct HealthView {
+    pub total_collateral_value: u128,
+    pub weighted_collateral_value: u128,
+    pub total_debt_value: u128,
+    pub liquidatable: bool,
+}
+
+//================================================================
+// Instruction Logic for Processing a Health Query
+//================================================================
+pub fn process_get_health(ctx: Context<GetHealth>) -> Result<HealthView> {
+    let clock = Clock::get()?;
+    // Value debt with the interest owed up to now, as the instructions that act on health do.
+    let mut sol_bank = ctx.accounts.sol_bank.clone().into_inner();
+    let mut usdc_bank = ctx.accounts.usdc_bank.clone().into_inner();
+    accrue_interest(&mut sol_bank, clock.unix_timestamp)?;
+    accrue_interest(&mut usdc_bank, clock.unix_timestamp)?;
+
+    // Use the stricter of the two banks' staleness windows, since both assets are priced here.
+    let max_price_age = sol_bank.max_price_age_seconds.min(usdc_bank.max_price_age_seconds);
+
+    // Positions in any other asset are valued with the banks in `remaining_accounts`.
+    let extra_banks = load_banks(ctx.remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![&sol_bank, &usdc_bank];
+    banks.extend(extra_banks.iter());
+    let extra_price_updates = load_price_updates(ctx.remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![&ctx.accounts.price_update];
+    price_updates.extend(extra_price_updates.iter());
+    let prices = Prices::load(&price_updates, ctx.remaining_accounts, &clock, max_price_age, &banks, &ctx.accounts.config.quote_feed_id)?;
+    let health = compute_account_health(&ctx.accounts.user_account, &prices, &banks)?;
+
+    // Anchor serializes the returned value into the transaction's return data.
+    Ok(HealthView {
+        total_collateral_value: health.total_collateral_value,
+        weighted_collateral_value: health.weighted_collateral_value,
+        total_debt_value: health.total_debt_value,
+        liquidatable: !health.is_healthy(),
+    })
+}
@@ -0,0 +1,82 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::health::{apply_isolation, compute_account_health, debt_price, load_banks, load_price_updates, Prices};
+use crate::interest::accrue_interest;
+use crate::math::{mul_div_floor, usd_value_to_native};
+use crate::constants::BPS_DENOMINATOR;
+
+//================================================================
+// Accounts Struct for the GetMaxBorrow Instruction
+//================================================================
+#[derive(Accounts)]
+#[instruction(mint_to_borrow: Pubkey)]
+pub struct GetMaxBorrow<'info> {
+    /// The owner of the position being queried. Anyone may query any user, so this is NOT a signer.
+    /// CHECK: The user_account is derived from this key, ensuring we read the correct position.
+    pub owner: AccountInfo<'info>,
+
+    /// The global protocol config, read for the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// The state account of the user being queried. Read-only.
+    #[account(seeds = [owner.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    /// The bank of the asset the user would borrow. Read-only: interest is accrued on a copy.
+    #[account(seeds = [mint_to_borrow.as_ref()], bump)]
+    pub bank: Account<'info, Bank>,
+
+    /// The bank of another asset the user holds a position in. Read-only: interest is accrued on a copy.
+    #[account(constraint = other_bank.key() != bank.key() @ ErrorCode::UnsupportedAsset)]
+    pub other_bank: Account<'info, Bank>,
+
+    /// Pyth price feed account for valuing assets.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Max Borrow Query
+//================================================================
+/// Returns how many native units of `mint_to_borrow` the user may still borrow, the same limit
+/// `borrow` enforces.
+pub fn process_get_max_borrow(ctx: Context<GetMaxBorrow>, mint_to_borrow: Pubkey) -> Result<u64> {
+    let clock = Clock::get()?;
+    // `borrow` accrues both banks before its health check, so do the same here.
+    let mut bank = ctx.accounts.bank.clone().into_inner();
+    let mut other_bank = ctx.accounts.other_bank.clone().into_inner();
+    accrue_interest(&mut bank, clock.unix_timestamp)?;
+    accrue_interest(&mut other_bank, clock.unix_timestamp)?;
+    let bank = &bank;
+    // A user without debt may pick either mode when they borrow; cross-collateral counts all of
+    // their collateral, so quote that. With debt the mode is fixed, and an isolated position
+    // can't borrow a second asset at all.
+    let mut user = ctx.accounts.user_account.clone().into_inner();
+    let mode = if user.has_debt() { user.isolated.then_some(user.isolated_mint) } else { None };
+    if apply_isolation(&mut user, mint_to_borrow, mode).is_err() {
+        return Ok(0);
+    }
+
+    // Positions in any other asset are valued with the banks in `remaining_accounts`.
+    let extra_banks = load_banks(ctx.remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![bank, &other_bank];
+    banks.extend(extra_banks.iter());
+    let extra_price_updates = load_price_updates(ctx.remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![&ctx.accounts.price_update];
+    price_updates.extend(extra_price_updates.iter());
+    let max_price_age = bank.max_price_age_seconds.min(other_bank.max_price_age_seconds);
+    let prices = Prices::load(&price_updates, ctx.remaining_accounts, &clock, max_price_age, &banks, &ctx.accounts.config.quote_feed_id)?;
+    let health = compute_account_health(&user, &prices, &banks)?;
+
+    // The borrowing power must also cover the origination fee, so quote the largest amount whose
+    // fee-inclusive debt still fits, both under max LTV and outside the bank's safety buffer.
+    let borrowable_usd_value = health.remaining_borrowing_power().min(health.buffered_borrowing_power(bank.safety_buffer_bps)?);
+    let max_debt = usd_value_to_native(borrowable_usd_value, prices.in_quote(debt_price(bank, prices.for_mint(&mint_to_borrow)?))?, bank.decimals)?;
+    let fee_multiplier = BPS_DENOMINATOR.checked_add(bank.origination_fee_bps as u64).ok_or(ErrorCode::MathOverflow)?;
+
+    // Anchor serializes the returned value into the transaction's return data.
+    mul_div_floor(max_debt, BPS_DENOMINATOR, fee_multiplier)
+}
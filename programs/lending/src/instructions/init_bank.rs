@@ -0,0 +1,111 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{ Mint, TokenAccount, TokenInterface };
+use pyth_solana_receiver_sdk::price_update::get_feed_id_from_hex;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::constants::{BORROW_INDEX_SCALE, MAXIMUM_AGE};
+use crate::token_extensions::assert_safe_mint;
+use crate::events::{AssetRegisteredEvent, BankSettings};
+
+#[derive(Accounts)]
+pub struct InitBank<'info> {
+    /// The protocol admin. Banks share their seeds with `add_supported_asset`'s, so anyone else creating
+    /// one first would pick its feed and risk parameters.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.admin == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        space = 8 + Bank::INIT_SPACE,
+        payer = signer,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        init,
+        token::mint = mint,
+        token::authority = bank_token_account,
+        payer = signer,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program <'info, System>,
+}
+
+/// Risk and interest rate parameters of a new bank. All percentages are whole numbers (75 = 75%),
+/// all rates are in basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct InitBankParams {
+    pub liquidation_threshold: u64,
+    pub liquidation_bonus: u64,
+    pub liquidation_close_factor: u64,
+    pub max_ltv: u64,
+    pub base_rate: u64,
+    pub slope1: u64,
+    pub slope2: u64,
+    pub optimal_utilization: u64,
+    pub max_borrow_rate: u64,
+}
+
+/// Creates the bank for a mint. Only the config admin may call it. `feed_id` is the hex id of the Pyth
+/// feed that prices the asset.
+pub fn process_init_bank(ctx: Context<InitBank>, feed_id: String, params: InitBankParams) -> Result<()> {
+    let authority = ctx.accounts.signer.key();
+    let bank = &mut ctx.accounts.bank;
+    init_bank_state(bank, &ctx.accounts.mint, authority, &feed_id, params)?;
+    emit!(AssetRegisteredEvent {
+        admin: authority,
+        mint: bank.mint_address,
+        bank: bank.key(),
+        price_feed_id: bank.price_feed_id,
+        settings: BankSettings::from(&**bank),
+    });
+    Ok(())
+}
+
+/// Validates `params`, the mint and the feed id, then writes them into a freshly created bank.
+pub(crate) fn init_bank_state(
+    bank: &mut Bank,
+    mint: &InterfaceAccount<Mint>,
+    authority: Pubkey,
+    feed_id: &str,
+    params: InitBankParams,
+) -> Result<()> {
+    // A position must become borrowable-against before it becomes liquidatable: max_ltv <= threshold <= 100
+    require!(params.liquidation_threshold <= 100, ErrorCode::InvalidLiquidationThreshold);
+    require!(params.max_ltv <= params.liquidation_threshold, ErrorCode::InvalidMaxLtv);
+    require!(params.liquidation_bonus <= 50, ErrorCode::InvalidLiquidationBonus);
+    require!(params.liquidation_close_factor <= 100, ErrorCode::InvalidLiquidationCloseFactor);
+    assert_safe_mint(mint)?;
+    let price_feed_id = get_feed_id_from_hex(feed_id).map_err(|_| ErrorCode::InvalidPriceFeedId)?;
+
+    bank.mint_address = mint.key();
+    bank.decimals = mint.decimals;
+    bank.authority = authority;
+    bank.liquidation_threshold = params.liquidation_threshold;
+    bank.liquidation_bonus = params.liquidation_bonus;
+    bank.liquidation_close_factor = params.liquidation_close_factor;
+    bank.max_ltv = params.max_ltv;
+    bank.base_rate = params.base_rate;
+    bank.slope1 = params.slope1;
+    bank.slope2 = params.slope2;
+    bank.optimal_utilization = params.optimal_utilization;
+    bank.max_borrow_rate = params.max_borrow_rate;
+    bank.price_feed_id = price_feed_id;
+    bank.max_price_age_seconds = MAXIMUM_AGE;
+    bank.borrowing_enabled = true;
+    bank.usable_as_collateral = true;
+    bank.borrow_index = BORROW_INDEX_SCALE;
+    bank.last_updated = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::constants::USER_VERSION;
+use crate::events::UserCreatedEvent;
+
+#[derive(Accounts)]
+pub struct InitUser<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    /// The global protocol config, whose user counter hands out each new user's `position_nonce`.
+    #[account(mut, seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+    /// Seeded with the signer's key, the same PDA every other instruction resolves as `user_account`.
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + User::INIT_SPACE,
+        seeds = [signer.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+    pub system_program: Program <'info, System>,
+}
+
+pub fn process_init_user(ctx: Context<InitUser>, usdc_address: Pubkey) -> Result<()> {
+    // A `User` PDA is derived from its owner's key alone, so there is no on-chain way to list them.
+    // The nonce and creation slot give indexers something to discover and order accounts by.
+    let config = &mut ctx.accounts.config;
+    config.user_count = config.user_count.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    let position_nonce = config.user_count;
+    let clock = Clock::get()?;
+
+    // Every deposit and borrow field starts at zero.
+    ctx.accounts.user_account.set_inner(User {
+        owner: ctx.accounts.signer.key(),
+        usdc_address,
+        last_updated: clock.unix_timestamp,
+        created_at: clock.slot,
+        position_nonce,
+        version: USER_VERSION,
+        ..Default::default()
+    });
+
+    emit!(UserCreatedEvent {
+        user: ctx.accounts.signer.key(),
+        user_account: ctx.accounts.user_account.key(),
+        position_nonce,
+        created_at: clock.slot,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::constants::BPS_DENOMINATOR;
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    /// The deployer, who becomes the protocol admin.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + Config::INIT_SPACE,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+    /// Protocol-wide totals, created alongside the config and zeroed.
+    #[account(
+        init,
+        payer = signer,
+        space = 8 + ProtocolStats::INIT_SPACE,
+        seeds = [b"stats"],
+        bump,
+    )]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+    pub system_program: Program <'info, System>,
+}
+
+pub fn process_initialize(ctx: Context<Initialize>, protocol_fee_bps: u16) -> Result<()> {
+    require!(protocol_fee_bps as u64 <= BPS_DENOMINATOR, ErrorCode::InvalidProtocolFee);
+
+    ctx.accounts.config.set_inner(Config {
+        admin: ctx.accounts.signer.key(),
+        paused: false,
+        protocol_fee_bps,
+        fee_recipient: ctx.accounts.signer.key(),
+        user_count: 0,
+        pending_admin: None,
+        min_liquidation_incentive_usd: 0,
+        auction_mode: false,
+        auction_duration_seconds: 0,
+        quote_feed_id: [0; 32],
+        max_borrowed_assets: 0,
+    });
+
+    Ok(())
+}
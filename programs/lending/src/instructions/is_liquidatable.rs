@@ -0,0 +1,78 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::health::{compute_account_health, load_banks, load_price_updates, Prices};
+use crate::interest::accrue_interest;
+
+//================================================================
+// Accounts Struct for the IsLiquidatable Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct IsLiquidatable<'info> {
+    /// The owner of the position being screened. Anyone may query any user, so this is NOT a signer.
+    /// CHECK: The user_account is derived from this key, ensuring we read the correct position.
+    pub owner: AccountInfo<'info>,
+
+    /// The global protocol config, read for the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// The state account of the user being screened. Read-only.
+    #[account(seeds = [owner.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    /// The bank of the asset the user borrowed. Read-only: interest is accrued on a copy.
+    pub borrowed_bank: Account<'info, Bank>,
+
+    /// The bank of the asset backing the loan. Read-only: interest is accrued on a copy.
+    #[account(constraint = collateral_bank.key() != borrowed_bank.key() @ ErrorCode::IdenticalMints)]
+    pub collateral_bank: Account<'info, Bank>,
+
+    /// Pyth price feed account for valuing assets.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+}
+
+/// Whether a position can be liquidated, as returned by `is_liquidatable`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LiquidationEligibility {
+    pub liquidatable: bool,
+    /// How far the debt exceeds the threshold-weighted collateral, on the `USD_EXPONENT` scale in the
+    /// config's quote asset. Zero for a healthy position.
+    pub shortfall_usd: u128,
+}
+
+//================================================================
+// Instruction Logic for Processing a Liquidation Eligibility Query
+//================================================================
+/// Runs the same health check as `liquidate` without moving any tokens, so bots can screen positions
+/// before sending a liquidation.
+pub fn process_is_liquidatable(ctx: Context<IsLiquidatable>) -> Result<LiquidationEligibility> {
+    let clock = Clock::get()?;
+
+    // `liquidate` accrues both banks before its health check, so do the same here.
+    let mut borrowed_bank = ctx.accounts.borrowed_bank.clone().into_inner();
+    let mut collateral_bank = ctx.accounts.collateral_bank.clone().into_inner();
+    accrue_interest(&mut borrowed_bank, clock.unix_timestamp)?;
+    accrue_interest(&mut collateral_bank, clock.unix_timestamp)?;
+
+    // Use the stricter of the two banks' staleness windows, since both assets are priced here.
+    let max_price_age = borrowed_bank.max_price_age_seconds.min(collateral_bank.max_price_age_seconds);
+
+    // Positions in any other asset are valued with the banks in `remaining_accounts`.
+    let extra_banks = load_banks(ctx.remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![&borrowed_bank, &collateral_bank];
+    banks.extend(extra_banks.iter());
+    let extra_price_updates = load_price_updates(ctx.remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![&ctx.accounts.price_update];
+    price_updates.extend(extra_price_updates.iter());
+    let prices = Prices::load(&price_updates, ctx.remaining_accounts, &clock, max_price_age, &banks, &ctx.accounts.config.quote_feed_id)?;
+    let health = compute_account_health(&ctx.accounts.user_account, &prices, &banks)?;
+
+    // Anchor serializes the returned value into the transaction's return data.
+    Ok(LiquidationEligibility {
+        liquidatable: !health.is_healthy(),
+        shortfall_usd: health.shortfall(),
+    })
+}
@@ -1,15 +1,17 @@
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
-use pyth_solana_receiver_sdk::price_update::{self, get_feed_id_from_hex, PriceUpdateV2};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 use crate::state::*;
 use crate::error::ErrorCode;
-use crate::constants::{
-    SOL_USD_FEED_ID, 
-    USDC_USD_FEED_ID, 
-    SOL_MINT_ADDRESS, 
-    USDC_MINT_ADDRESS
-};
+use crate::token_utils::{transfer_from_vault, transfer_to_vault};
+use crate::health::{auction_bonus_bps, collateral_price, compute_account_health, current_debt, current_deposit, debt_price, liquidation_bonus_bps, load_banks, load_price_updates, HealthFactor, Prices};
+use crate::constants::BPS_DENOMINATOR;
+use crate::math::{amount_for_shares_ceil, mul_div_floor, require_non_empty, shares_for_amount_ceil, token_value_usd, usd_value_to_native, usd_value_to_native_ceil};
+use crate::interest::accrue_interest;
+use crate::events::LiquidateEvent;
+use crate::token_extensions::gross_up_for_transfer_fee;
+use super::repay::burn_debt_shares;
 
 //================================================================
 // Accounts Struct for the Liquidate Instruction
@@ -20,6 +22,15 @@ pub struct Liquidate<'info> {
     #[account(mut)]
     pub liquidator: Signer<'info>,
 
+    /// The global protocol config, checked for the pause switch and read for the liquidation incentive
+    /// floor, auction mode and the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the repayment, the seized collateral and the liquidation volume.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
     /// The user account being liquidated. This is NOT a signer. We only need their address
     /// to derive the PDA for their user state account. This is a CRITICAL FIX.
     /// CHECK: The user_account is derived from this key, ensuring we liquidate the correct person.
@@ -34,26 +45,37 @@ pub struct Liquidate<'info> {
     pub user_account: Account<'info, User>,
 
     /// The mint of the asset that was BORROWED by the user (and is now being repaid by the liquidator).
-    #[account(mut)]
+    #[account(mut, constraint = *borrowed_mint.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch)]
     pub borrowed_mint: InterfaceAccount<'info, Mint>,
 
     /// The state account for the bank of the borrowed asset.
-    #[account(mut, seeds = [borrowed_mint.key().as_ref()], bump)]
+    #[account(mut, seeds = [borrowed_mint.key().as_ref()], bump, constraint = borrowed_bank.decimals == borrowed_mint.decimals @ ErrorCode::DecimalsMismatch)]
     pub borrowed_bank: Account<'info, Bank>,
 
     /// The vault for the borrowed asset, where the liquidator will send funds.
-    #[account(mut, seeds = [b"treasury", borrowed_mint.key().as_ref()], bump)]
+    #[account(
+        mut,
+        seeds = [b"treasury", borrowed_mint.key().as_ref()],
+        bump,
+        constraint = *borrowed_bank_token_account.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch,
+    )]
     pub borrowed_bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
     /// The mint of the asset that was DEPOSITED as collateral (and is now being seized by the liquidator).
+    #[account(constraint = *collateral_mint.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch)]
     pub collateral_mint: InterfaceAccount<'info, Mint>,
 
     /// The state account for the bank of the collateral asset.
-    #[account(mut, seeds = [collateral_mint.key().as_ref()], bump)]
+    #[account(mut, seeds = [collateral_mint.key().as_ref()], bump, constraint = collateral_bank.decimals == collateral_mint.decimals @ ErrorCode::DecimalsMismatch)]
     pub collateral_bank: Account<'info, Bank>,
     
     /// The vault for the collateral asset, from which the liquidator will receive funds.
-    #[account(mut, seeds = [b"treasury", collateral_mint.key().as_ref()], bump)]
+    #[account(
+        mut,
+        seeds = [b"treasury", collateral_mint.key().as_ref()],
+        bump,
+        constraint = *collateral_bank_token_account.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch,
+    )]
     pub collateral_bank_token_account: InterfaceAccount<'info, TokenAccount>,
     
     /// The liquidator's token account for the BORROWED asset (where they send from).
@@ -61,6 +83,7 @@ pub struct Liquidate<'info> {
         mut,
         associated_token::mint = borrowed_mint,
         associated_token::authority = liquidator,
+        associated_token::token_program = token_program,
     )]
     pub liquidator_borrowed_token_account: InterfaceAccount<'info, TokenAccount>,
 
@@ -70,13 +93,15 @@ pub struct Liquidate<'info> {
         payer = liquidator,
         associated_token::mint = collateral_mint,
         associated_token::authority = liquidator,
+        associated_token::token_program = token_program,
     )]
     pub liquidator_collateral_token_account: InterfaceAccount<'info, TokenAccount>,
     
     /// Pyth price feed account for valuing assets.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
     pub price_update: Account<'info, PriceUpdateV2>,
     
-    // Standard required programs
+    /// The token program both mints and vaults belong to.
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
@@ -86,36 +111,224 @@ pub struct Liquidate<'info> {
 //================================================================
 // Instruction Logic for Processing a Liquidation
 //================================================================
-pub fn process_liquidate(ctx: Context<Liquidate>) -> Result<()> {
-    let user = &mut ctx.accounts.user_account;
-    let price_update = &ctx.accounts.price_update;
+pub fn process_liquidate(ctx: Context<Liquidate>, repay_amount: u64) -> Result<()> {
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+    // Self-liquidation would let a user capture the liquidation bonus on their own position.
+    require_keys_neq!(ctx.accounts.liquidator.key(), ctx.accounts.user_to_liquidate.key(), ErrorCode::SelfLiquidation);
+    // Repaying and seizing the same asset would corrupt the share accounting.
+    require_keys_neq!(ctx.accounts.borrowed_mint.key(), ctx.accounts.collateral_mint.key(), ErrorCode::IdenticalMints);
+    // Each bank and vault must be the canonical one for its mint, so a mismatched account can never
+    // receive the repayment or pay out the seized collateral.
+    require_canonical_vault(&ctx.accounts.borrowed_bank, &ctx.accounts.borrowed_bank_token_account, &ctx.accounts.borrowed_mint.key())?;
+    require_canonical_vault(&ctx.accounts.collateral_bank, &ctx.accounts.collateral_bank_token_account, &ctx.accounts.collateral_mint.key())?;
+
     let clock = Clock::get()?;
 
+    // Bring both banks' totals up to date before any share math.
+    accrue_interest(&mut ctx.accounts.borrowed_bank, clock.unix_timestamp)?;
+    accrue_interest(&mut ctx.accounts.collateral_bank, clock.unix_timestamp)?;
+
+    // --- 1-2. Check the Position Is Unhealthy and Size the Liquidation ---
+    let (LiquidationAmounts { repay_amount: repay_amount_native, seize_amount: seize_amount_native, .. }, health_before, prices) = plan_liquidation(
+        &ctx.accounts.user_account,
+        &ctx.accounts.borrowed_bank,
+        &ctx.accounts.borrowed_mint,
+        &ctx.accounts.collateral_bank,
+        &ctx.accounts.collateral_mint,
+        &ctx.accounts.price_update,
+        ctx.remaining_accounts,
+        &clock,
+        &ctx.accounts.config,
+        repay_amount,
+    )?;
+    // The liquidator covers any Token-2022 transfer fee on the repayment, so the vault receives all of it.
+    let repay_transfer_amount = gross_up_for_transfer_fee(&ctx.accounts.borrowed_mint.to_account_info(), repay_amount_native, clock.epoch)?;
+    // Fail with a clear error up front rather than with a token-program error partway through the transfers.
+    require!(
+        ctx.accounts.liquidator_borrowed_token_account.amount >= repay_transfer_amount,
+        ErrorCode::InsufficientLiquidatorFunds
+    );
+
+    // --- 3. Perform CPI Transfers ---
+    // A. Liquidator repays the user's debt to the bank.
+    transfer_to_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.liquidator_borrowed_token_account.to_account_info(),
+        ctx.accounts.borrowed_mint.to_account_info(),
+        ctx.accounts.borrowed_bank_token_account.to_account_info(),
+        ctx.accounts.liquidator.to_account_info(),
+        repay_transfer_amount,
+        ctx.accounts.borrowed_mint.decimals,
+    )?;
+
+    // B. Liquidator seizes discounted collateral from the bank's vault.
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.collateral_bank_token_account.to_account_info(),
+        ctx.accounts.collateral_mint.to_account_info(),
+        ctx.accounts.liquidator_collateral_token_account.to_account_info(),
+        ctx.bumps.collateral_bank_token_account,
+        seize_amount_native,
+        ctx.accounts.collateral_mint.decimals,
+    )?;
+
+    // --- 4. Update All State Accounts (CRITICAL) ---
+    // This is the accounting that was missing from the original code.
+    let user = &mut ctx.accounts.user_account;
+
+    // Burn shares for both debt and collateral. Both round against the liquidated user:
+    // fewer debt shares are cleared and more collateral shares are taken, capped at what they hold.
+    // `plan_liquidation` already rejected either pool being empty, including one left holding shares but
+    // no tokens by a bad-debt write-off, so neither division can be by zero. Repaying the user's whole
+    // debt clears exactly the shares they hold, since converting it back into shares could round past
+    // them once a share is worth less than a token.
+    let borrowed_mint_key = ctx.accounts.borrowed_mint.key();
+    let outstanding_debt = current_debt(user, &ctx.accounts.borrowed_bank, &borrowed_mint_key)?;
+    let shares_repaid = burn_debt_shares(
+        &mut ctx.accounts.borrowed_bank,
+        user,
+        &borrowed_mint_key,
+        repay_amount_native,
+        outstanding_debt,
+    )?;
+    let shares_seized = seize_collateral(
+        &mut ctx.accounts.collateral_bank,
+        user,
+        &ctx.accounts.collateral_mint.key(),
+        seize_amount_native,
+    )?;
+
+    // --- 5. Write Off Bad Debt ---
+    // Once the user has no collateral left in any asset, what they still owe in every asset can never be
+    // repaid: the borrowed asset, the collateral asset, and any other, against its bank in `remaining_accounts`.
+    let mut bad_debt = 0;
+    let mut collateral_bad_debt = 0;
+    let collateral_exhausted = user.positions.iter().all(|position| position.deposited_shares == 0);
+    if collateral_exhausted {
+        let collateral_mint_key = ctx.accounts.collateral_mint.key();
+        bad_debt = write_off_bad_debt(&mut ctx.accounts.borrowed_bank, user, &borrowed_mint_key)?;
+        collateral_bad_debt = write_off_bad_debt(&mut ctx.accounts.collateral_bank, user, &collateral_mint_key)?;
+        write_off_other_bad_debt(
+            user,
+            &[borrowed_mint_key, collateral_mint_key],
+            ctx.remaining_accounts,
+            &mut ctx.accounts.protocol_stats,
+            clock.unix_timestamp,
+        )?;
+    }
+
+    // --- 6. Check the Liquidation Helped ---
+    // A bonus large enough can seize more weighted collateral than the debt it repays, pushing the
+    // position further underwater (the incentive floor already stops short of that). Revalue it at the
+    // same prices and refuse that, unless the liquidation closed the position out entirely.
+    if !collateral_exhausted && user.has_debt() {
+        let extra_banks = load_banks(ctx.remaining_accounts, clock.unix_timestamp)?;
+        let mut banks: Vec<&Bank> = vec![&ctx.accounts.borrowed_bank, &ctx.accounts.collateral_bank];
+        banks.extend(extra_banks.iter());
+        let health_after = compute_account_health(user, &prices, &banks)?;
+        require!(health_after.is_no_worse_than(&health_before), ErrorCode::LiquidationWorsensHealth);
+    }
+    start_auction(&ctx.accounts.config, user, clock.unix_timestamp);
+
+    // Debt written off leaves the outstanding total just as repaid debt does.
+    let stats = &mut ctx.accounts.protocol_stats;
+    let debt_cleared = repay_amount_native.checked_add(bad_debt).ok_or(ErrorCode::MathOverflow)?;
+    stats.record_repayment(&ctx.accounts.borrowed_bank, debt_cleared, clock.unix_timestamp)?;
+    stats.record_repayment(&ctx.accounts.collateral_bank, collateral_bad_debt, clock.unix_timestamp)?;
+    stats.record_withdrawal(&ctx.accounts.collateral_bank, seize_amount_native, clock.unix_timestamp)?;
+    stats.record_liquidation(&ctx.accounts.borrowed_bank, repay_amount_native, clock.unix_timestamp)?;
+
+    emit!(LiquidateEvent {
+        liquidator: ctx.accounts.liquidator.key(),
+        user: ctx.accounts.user_to_liquidate.key(),
+        borrowed_mint: ctx.accounts.borrowed_mint.key(),
+        collateral_mint: ctx.accounts.collateral_mint.key(),
+        repay_amount: repay_amount_native,
+        repay_shares: shares_repaid,
+        seize_amount: seize_amount_native,
+        seize_shares: shares_seized,
+        total_borrows: ctx.accounts.borrowed_bank.total_borrows,
+        total_borrow_shares: ctx.accounts.borrowed_bank.total_borrow_shares,
+        total_deposits: ctx.accounts.collateral_bank.total_deposits,
+        total_deposit_shares: ctx.accounts.collateral_bank.total_deposit_shares,
+        bad_debt,
+    });
+
+    msg!("Liquidation successful!");
+    Ok(())
+}
+
+/// The amounts a liquidation moves, as returned by `preview_liquidation`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LiquidationAmounts {
+    /// Borrowed-asset tokens the liquidator pays in, after the close factor, value cap and collateral clamp.
+    pub repay_amount: u64,
+    /// Collateral tokens the liquidator receives, including any Token-2022 transfer fee the user covers.
+    pub seize_amount: u64,
+    /// The bonus on top of the repaid value the seize is worth, in basis points.
+    pub liquidation_bonus_bps: u64,
+}
+
+/// Checks `user` can be liquidated for `repay_amount` of their `borrowed_mint` debt against their
+/// `collateral_mint` deposit, and works out what the liquidation moves, along with the position's health
+/// and the prices it was valued at. Shared by `liquidate` and `preview_liquidation` so the preview can
+/// never drift from what is actually paid.
+/// Both banks must already be accrued to now. The seize is topped up so its bonus is worth at least
+/// the config's `min_liquidation_incentive_usd`, as far as the user's collateral and health allow.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn plan_liquidation(
+    user: &User,
+    borrowed_bank: &Bank,
+    borrowed_mint: &InterfaceAccount<Mint>,
+    collateral_bank: &Bank,
+    collateral_mint: &InterfaceAccount<Mint>,
+    price_update: &PriceUpdateV2,
+    remaining_accounts: &[AccountInfo],
+    clock: &Clock,
+    config: &Config,
+    repay_amount: u64,
+) -> Result<(LiquidationAmounts, HealthFactor, Prices)> {
+    if repay_amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
+
+    // Collateral can only be seized from a bank that holds deposits.
+    require_non_empty(collateral_bank.total_deposits, collateral_bank.total_deposit_shares)?;
+
+    // The user must actually owe the borrowed asset and hold the collateral asset.
+    let has_debt = user.position(&borrowed_mint.key()).is_some_and(|position| position.borrowed_shares > 0);
+    // The collateral is what the user's shares redeem for, not the principal they deposited.
+    let collateral_deposit = current_deposit(user, collateral_bank, &collateral_mint.key())?;
+    require!(has_debt, ErrorCode::NothingToRepay);
+    require!(collateral_deposit > 0, ErrorCode::InsufficientCollateralToSeize);
+    require_non_empty(borrowed_bank.total_borrows, borrowed_bank.total_borrow_shares)?;
+
+    let borrowed_debt = current_debt(user, borrowed_bank, &borrowed_mint.key())?;
+
+    // Use the stricter of the two banks' staleness windows, since both assets are priced here.
+    let max_price_age = borrowed_bank.max_price_age_seconds
+        .min(collateral_bank.max_price_age_seconds);
+
     // --- 1. Perform Health Check ---
     // First, we must verify that the user's position is actually unhealthy and eligible for liquidation.
-    msg!("Performing health check for user: {}", user.key());
+    msg!("Performing health check before liquidating...");
+
+    // Positions in any other asset are valued with the banks in `remaining_accounts`.
+    let extra_banks = load_banks(remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![borrowed_bank, collateral_bank];
+    banks.extend(extra_banks.iter());
 
     // Get prices for all assets involved.
-    let sol_price = price_update.get_price_no_older_than(&clock, 60, &get_feed_id_from_hex(SOL_USD_FEED_ID)?)?;
-    let usdc_price = price_update.get_price_no_older_than(&clock, 60, &get_feed_id_from_hex(USDC_USD_FEED_ID)?)?;
-
-    // A. Calculate the total USD value of the user's DEBT.
-    let total_debt_value = (sol_price.price as u128 * user.borrowed_sol as u128)
-        .checked_add(usdc_price.price as u128 * user.borrowed_usdc as u128)
-        .ok_or(ErrorCode::MathOverflow)?;
-
-    // B. Calculate the total USD value of the user's COLLATERAL.
-    let total_collateral_value = (sol_price.price as u128 * user.deposited_sol as u128)
-        .checked_add(usdc_price.price as u128 * user.deposited_usdc as u128)
-        .ok_or(ErrorCode::MathOverflow)?;
-
-    // C. Apply the liquidation threshold to the collateral value.
-    let weighted_collateral_value = total_collateral_value
-        .checked_mul(ctx.accounts.collateral_bank.liquidation_threshold as u128).ok_or(ErrorCode::MathOverflow)?
-        .checked_div(100).ok_or(ErrorCode::MathOverflow)?; // For percentage
-    
-    // D. The Health Check: If weighted collateral is still greater than or equal to the debt, revert.
-    if weighted_collateral_value >= total_debt_value {
+    let extra_price_updates = load_price_updates(remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![price_update];
+    price_updates.extend(extra_price_updates.iter());
+    let prices = Prices::load(&price_updates, remaining_accounts, clock, max_price_age, &banks, &config.quote_feed_id)?;
+
+    // Value the user's debt and collateral, weighting each deposit by its own bank's liquidation threshold.
+    let health = compute_account_health(user, &prices, &banks)?;
+
+    // If weighted collateral is still greater than or equal to the debt, revert.
+    if health.is_healthy() {
         return err!(ErrorCode::PositionHealthy);
     }
     msg!("Health check passed. Position is undercollateralized.");
@@ -124,109 +337,261 @@ pub fn process_liquidate(ctx: Context<Liquidate>) -> Result<()> {
     // This part is critical. We calculate everything in USD value first, then convert back to
     // the native token amounts for the actual transfers.
 
-    // A. Determine the USD value of the debt to be repaid, capped by the close factor.
-    let repay_value_usd = total_debt_value
-        .checked_mul(ctx.accounts.borrowed_bank.liquidation_close_factor as u128).ok_or(ErrorCode::MathOverflow)?
-        .checked_div(100).ok_or(ErrorCode::MathOverflow)?;
+    // A. Cap the repayment by the close factor of the debt in the BORROWED asset alone, so a liquidator
+    // can't use the user's other debts to repay more of this one than the close factor allows. A dust
+    // debt can be repaid in full.
+    let max_repay_for_asset = max_liquidation_repay(borrowed_bank, borrowed_debt)?;
+    require!(repay_amount <= max_repay_for_asset, ErrorCode::CloseFactorExceeded);
+    // The close factor can't exceed 100%, but clamp to what the user actually owes in this asset anyway:
+    // everything below only shrinks the repayment, and the accounting relies on it never overshooting.
+    let mut repay_amount_native = repay_amount.min(borrowed_debt);
 
-    // B. Convert the repay USD value back into the native amount of the BORROWED token.
-    let (borrowed_token_price, borrowed_token_decimals) = match ctx.accounts.borrowed_mint.key() {
-        key if key == USDC_MINT_ADDRESS.parse().unwrap() => (usdc_price.price, ctx.accounts.borrowed_mint.decimals),
-        key if key == SOL_MINT_ADDRESS.parse().unwrap() => (sol_price.price, ctx.accounts.borrowed_mint.decimals),
-        _ => return err!(ErrorCode::UnsupportedAsset),
-    };
-    let repay_amount_native = repay_value_usd.checked_div(borrowed_token_price as u128).ok_or(ErrorCode::MathOverflow)? as u64;
+    // B. Determine the USD value of the debt being repaid.
+    // Capped at the value one call may repay.
+    let borrowed_token_price = prices.for_mint(&borrowed_mint.key())?;
+    let borrowed_token_decimals = borrowed_mint.decimals;
+    let (capped_repay_amount, repay_value_usd) =
+        cap_liquidation_repay(borrowed_bank, borrowed_token_price, borrowed_token_decimals, repay_amount_native)?;
+    repay_amount_native = capped_repay_amount;
 
-    // C. Determine the USD value of the collateral to be seized (repaid value + bonus).
+    // C. Determine the USD value of the collateral to be seized (repaid value + bonus). The collateral
+    // bank's bonus may scale with how unhealthy the position is, or with how long its auction has run.
+    let liquidation_bonus_bps = effective_liquidation_bonus_bps(config, user, collateral_bank, &health, clock.unix_timestamp)?;
     let seize_value_usd = repay_value_usd
-        .checked_mul(100 + ctx.accounts.collateral_bank.liquidation_bonus as u128).ok_or(ErrorCode::MathOverflow)?
-        .checked_div(100).ok_or(ErrorCode::MathOverflow)?;
+        .checked_mul((BPS_DENOMINATOR + liquidation_bonus_bps) as u128).ok_or(ErrorCode::MathOverflow)?
+        .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::MathOverflow)?;
     
     // D. Convert the seize USD value back into the native amount of the COLLATERAL token.
-    let (collateral_token_price, collateral_token_decimals) = match ctx.accounts.collateral_mint.key() {
-        key if key == USDC_MINT_ADDRESS.parse().unwrap() => (usdc_price.price, ctx.accounts.collateral_mint.decimals),
-        key if key == SOL_MINT_ADDRESS.parse().unwrap() => (sol_price.price, ctx.accounts.collateral_mint.decimals),
-        _ => return err!(ErrorCode::UnsupportedAsset),
-    };
-    let seize_amount_native = seize_value_usd.checked_div(collateral_token_price as u128).ok_or(ErrorCode::MathOverflow)? as u64;
+    let collateral_token_price = prices.for_mint(&collateral_mint.key())?;
+    let collateral_token_decimals = collateral_mint.decimals;
+    let mut seize_amount_native = usd_value_to_native(seize_value_usd, collateral_token_price, collateral_token_decimals)?;
 
-    // --- 3. Perform CPI Transfers ---
-    // A. Liquidator repays the user's debt to the bank.
-    token_interface::transfer_checked(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            TransferChecked {
-                from: ctx.accounts.liquidator_borrowed_token_account.to_account_info(),
-                mint: ctx.accounts.borrowed_mint.to_account_info(),
-                to: ctx.accounts.borrowed_bank_token_account.to_account_info(),
-                authority: ctx.accounts.liquidator.to_account_info(),
-            },
-        ),
-        repay_amount_native,
-        borrowed_token_decimals,
-    )?;
+    // E. Never seize more collateral than the user holds. If the bonus pushes past it, seize everything
+    // and shrink the repayment by the same proportion so the liquidator still gets the same rate.
+    if seize_amount_native > collateral_deposit {
+        repay_amount_native = mul_div_floor(repay_amount_native, collateral_deposit, seize_amount_native)?;
+        seize_amount_native = collateral_deposit;
+        msg!("Seize clamped to available collateral: {}, repay reduced to {}", seize_amount_native, repay_amount_native);
+    }
 
-    // B. Liquidator seizes discounted collateral from the bank's vault.
-    let collateral_mint_key = ctx.accounts.collateral_mint.key();
-    let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", collateral_mint_key.as_ref(), &[ctx.bumps.collateral_bank_token_account]]];
-    token_interface::transfer_checked(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            TransferChecked {
-                from: ctx.accounts.collateral_bank_token_account.to_account_info(),
-                mint: ctx.accounts.collateral_mint.to_account_info(),
-                to: ctx.accounts.liquidator_collateral_token_account.to_account_info(),
-                authority: ctx.accounts.collateral_bank_token_account.to_account_info(),
-            },
-        ).with_signer(signer_seeds),
+    // F. A percentage bonus on a small position can be worth less than the gas to liquidate it, leaving
+    // it to rot. Raise the seize so the bonus is worth at least the protocol's floor, up to everything
+    // the user holds, rounding up so the bonus never falls short of it. The repayment is left as is.
+    // The floor stops short of a seize that would leave the position further underwater, which
+    // `liquidate` refuses, so on a position too small to pay it in full the liquidator gets what it can.
+    let min_liquidation_incentive_usd = config.min_liquidation_incentive_usd;
+    if min_liquidation_incentive_usd > 0 {
+        let repay_value_usd = token_value_usd(borrowed_token_price, repay_amount_native, borrowed_token_decimals)?;
+        let floor_seize_value_usd = repay_value_usd
+            .checked_add(min_liquidation_incentive_usd).ok_or(ErrorCode::MathOverflow)?;
+        let floor_seize_amount = usd_value_to_native_ceil(floor_seize_value_usd, collateral_token_price, collateral_token_decimals)?;
+        if floor_seize_amount > seize_amount_native {
+            let repaid_value = repaid_debt_value(borrowed_bank, &prices, repay_amount_native)?;
+            let sound_seize_amount = max_sound_seize(user, collateral_bank, &prices, repaid_value)?;
+            let raised_seize_amount = floor_seize_amount.min(collateral_deposit).min(sound_seize_amount);
+            if raised_seize_amount > seize_amount_native {
+                seize_amount_native = raised_seize_amount;
+                msg!("Seize raised to the liquidation incentive floor: {}", seize_amount_native);
+            }
+        }
+    }
+
+    // G. If the collateral mint charges a Token-2022 transfer fee, seize enough extra from the user that
+    // the liquidator still nets the seize amount, up to everything the user holds.
+    let seize_amount_native = gross_up_for_transfer_fee(
+        &collateral_mint.to_account_info(),
         seize_amount_native,
-        collateral_token_decimals,
-    )?;
+        clock.epoch,
+    )?.min(collateral_deposit);
 
-    // --- 4. Update All State Accounts (CRITICAL) ---
-    // This is the accounting that was missing from the original code.
+    let amounts = LiquidationAmounts {
+        repay_amount: repay_amount_native,
+        seize_amount: seize_amount_native,
+        liquidation_bonus_bps,
+    };
+    Ok((amounts, health, prices))
+}
 
-    // Calculate shares to burn for both debt and collateral
-    let shares_repaid = (repay_amount_native as u128 * ctx.accounts.borrowed_bank.total_borrow_shares as u128)
-        .checked_div(ctx.accounts.borrowed_bank.total_borrows as u128).ok_or(ErrorCode::MathOverflow)? as u64;
-    let shares_seized = (seize_amount_native as u128 * ctx.accounts.collateral_bank.total_deposit_shares as u128)
-        .checked_div(ctx.accounts.collateral_bank.total_deposits as u128).ok_or(ErrorCode::MathOverflow)? as u64;
-
-    // Update the state of the BORROWED bank
-    let borrowed_bank = &mut ctx.accounts.borrowed_bank;
-    borrowed_bank.total_borrows = borrowed_bank.total_borrows.checked_sub(repay_amount_native).ok_or(ErrorCode::MathOverflow)?;
-    borrowed_bank.total_borrow_shares = borrowed_bank.total_borrow_shares.checked_sub(shares_repaid).ok_or(ErrorCode::MathOverflow)?;
-
-    // Update the state of the COLLATERAL bank
-    let collateral_bank = &mut ctx.accounts.collateral_bank;
-    collateral_bank.total_deposits = collateral_bank.total_deposits.checked_sub(seize_amount_native).ok_or(ErrorCode::MathOverflow)?;
-    collateral_bank.total_deposit_shares = collateral_bank.total_deposit_shares.checked_sub(shares_seized).ok_or(ErrorCode::MathOverflow)?;
-    
-    // Update the liquidated USER's state
-    match ctx.accounts.borrowed_mint.key() {
-        key if key == USDC_MINT_ADDRESS.parse().unwrap() => {
-            user.borrowed_usdc = user.borrowed_usdc.checked_sub(repay_amount_native).ok_or(ErrorCode::MathOverflow)?;
-            user.borrowed_usdc_shares = user.borrowed_usdc_shares.checked_sub(shares_repaid).ok_or(ErrorCode::MathOverflow)?;
-        },
-        key if key == SOL_MINT_ADDRESS.parse().unwrap() => {
-            user.borrowed_sol = user.borrowed_sol.checked_sub(repay_amount_native).ok_or(ErrorCode::MathOverflow)?;
-            user.borrowed_sol_shares = user.borrowed_sol_shares.checked_sub(shares_repaid).ok_or(ErrorCode::MathOverflow)?;
-        },
-        _ => return err!(ErrorCode::UnsupportedAsset),
+/// Caps `repay_amount` of `bank`'s asset at the bank's `max_liquidation_value_usd`, so a large position is
+/// worked down over several liquidations instead of dumping all of its collateral at once. A cap of 0
+/// means unlimited. Returns the repayment along with its USD value at `price`.
+pub(crate) fn cap_liquidation_repay(bank: &Bank, price: u128, decimals: u8, repay_amount: u64) -> Result<(u64, u128)> {
+    let repay_value_usd = token_value_usd(price, repay_amount, decimals)?;
+    let max_liquidation_value_usd = bank.max_liquidation_value_usd;
+    if max_liquidation_value_usd == 0 || repay_value_usd <= max_liquidation_value_usd {
+        return Ok((repay_amount, repay_value_usd));
     }
+    let capped_repay_amount = usd_value_to_native(max_liquidation_value_usd, price, decimals)?;
+    msg!("Repay capped at the bank's max liquidation value: {}", capped_repay_amount);
+    Ok((capped_repay_amount, token_value_usd(price, capped_repay_amount, decimals)?))
+}
+
+/// The value `compute_account_health` gives `repay_amount` of debt in `bank`'s asset, in the quote asset.
+pub(crate) fn repaid_debt_value(bank: &Bank, prices: &Prices, repay_amount: u64) -> Result<u128> {
+    let repay_price = prices.in_quote(debt_price(bank, prices.for_mint(&bank.mint_address)?))?;
+    token_value_usd(repay_price, repay_amount, bank.decimals)
+}
 
-    match ctx.accounts.collateral_mint.key() {
-        key if key == USDC_MINT_ADDRESS.parse().unwrap() => {
-            user.deposited_usdc = user.deposited_usdc.checked_sub(seize_amount_native).ok_or(ErrorCode::MathOverflow)?;
-            user.deposited_usdc_shares = user.deposited_usdc_shares.checked_sub(shares_seized).ok_or(ErrorCode::MathOverflow)?;
-        },
-        key if key == SOL_MINT_ADDRESS.parse().unwrap() => {
-            user.deposited_sol = user.deposited_sol.checked_sub(seize_amount_native).ok_or(ErrorCode::MathOverflow)?;
-            user.deposited_sol_shares = user.deposited_sol_shares.checked_sub(shares_seized).ok_or(ErrorCode::MathOverflow)?;
-        },
-        _ => return err!(ErrorCode::UnsupportedAsset),
+/// The largest seize of `collateral_bank`'s asset that leaves `user` no further underwater, given
+/// `repaid_value` of debt repaid for it, as `repaid_debt_value` counts it: the seize's weighted value may
+/// not exceed it.
+///
+/// The seize is valued as `compute_account_health` values it, less a unit for each of its rounding steps and
+/// one share's worth for the seized shares rounding up, so the shortfall check after the liquidation holds.
+/// Collateral that backs none of the user's debt can be seized without limit.
+pub(crate) fn max_sound_seize(user: &User, collateral_bank: &Bank, prices: &Prices, repaid_value: u128) -> Result<u64> {
+    let backs_debt = collateral_bank.usable_as_collateral
+        && (!user.isolated || user.isolated_mint == collateral_bank.mint_address);
+    if !backs_debt || collateral_bank.liquidation_threshold == 0 {
+        return Ok(u64::MAX);
     }
 
-    msg!("Liquidation successful!");
+    let seize_price = prices.in_quote(collateral_price(collateral_bank, prices.for_mint(&collateral_bank.mint_address)?))?;
+    let max_seize_value = repaid_value
+        .saturating_sub(2)
+        .checked_mul(100).ok_or(ErrorCode::ValueOverflow)?
+        / collateral_bank.liquidation_threshold as u128;
+    let share_worth = amount_for_shares_ceil(1, collateral_bank.total_deposits, collateral_bank.total_deposit_shares)?;
+    Ok(usd_value_to_native(max_seize_value, seize_price, collateral_bank.decimals)?.saturating_sub(share_worth.saturating_add(1)))
+}
+
+/// The bonus, in basis points, a liquidation of `user` seizing `bank`'s collateral pays at `now`.
+///
+/// In `auction_mode` it ramps with the time since the user's auction started, and a position with no
+/// auction running yet is priced as one starting now. Otherwise it is the bank's fixed or
+/// health-scaled bonus.
+pub(crate) fn effective_liquidation_bonus_bps(
+    config: &Config,
+    user: &User,
+    bank: &Bank,
+    health: &HealthFactor,
+    now: i64,
+) -> Result<u64> {
+    if !config.auction_mode {
+        return liquidation_bonus_bps(bank, health);
+    }
+    let started_at = if user.auction_start_ts == 0 { now } else { user.auction_start_ts };
+    let elapsed = u64::try_from(now.saturating_sub(started_at)).unwrap_or(0);
+    auction_bonus_bps(bank, elapsed, config.auction_duration_seconds)
+}
+
+/// Records `now` as the start of `user`'s liquidation auction if `auction_mode` is on and none is
+/// running, for a position just found liquidatable.
+pub(crate) fn start_auction(config: &Config, user: &mut User, now: i64) {
+    if config.auction_mode && user.auction_start_ts == 0 {
+        user.auction_start_ts = now;
+    }
+}
+
+/// The most of `debt` in `bank`'s asset one liquidation may repay: the bank's close factor of it, or all
+/// of it once that share would be below the bank's minimum borrow amount. Past that point each close-factor
+/// liquidation leaves a smaller remainder that is even less worth a liquidator's while, so the dust would
+/// never clear. Only called for a position already found to be underwater.
+pub(crate) fn max_liquidation_repay(bank: &Bank, debt: u64) -> Result<u64> {
+    let close_factor_repay = mul_div_floor(debt, bank.liquidation_close_factor, 100)?;
+    if close_factor_repay < bank.min_borrow_amount {
+        return Ok(debt);
+    }
+    Ok(close_factor_repay)
+}
+
+/// Takes `seize_amount` of `user`'s collateral in `mint` out of `bank`, returning the deposit shares burned.
+///
+/// The shares round up so the other depositors never cover the rounding, capped at what the user holds.
+/// Seizing everything the user's shares are worth burns all of them, so no dust share is left behind.
+pub(crate) fn seize_collateral(bank: &mut Bank, user: &mut User, mint: &Pubkey, seize_amount: u64) -> Result<u64> {
+    let held_shares = user.position(mint).map_or(0, |position| position.deposited_shares);
+    let shares_seized = if seize_amount >= current_deposit(user, bank, mint)? {
+        held_shares
+    } else {
+        shares_for_amount_ceil(seize_amount, bank.total_deposits, bank.total_deposit_shares)?.min(held_shares)
+    };
+    bank.total_deposits = bank.total_deposits.checked_sub(seize_amount).ok_or(ErrorCode::MathOverflow)?;
+    bank.total_deposit_shares = bank.total_deposit_shares.checked_sub(shares_seized).ok_or(ErrorCode::MathOverflow)?;
+
+    let collateral = user.position_mut(mint)?;
+    collateral.deposited_shares = collateral.deposited_shares.checked_sub(shares_seized).ok_or(ErrorCode::MathOverflow)?;
+    // The principal on record is bookkeeping only: the shares may be worth more or less than it, so it
+    // goes once they do.
+    collateral.deposited = if collateral.deposited_shares == 0 {
+        0
+    } else {
+        collateral.deposited.saturating_sub(seize_amount)
+    };
+    Ok(shares_seized)
+}
+
+/// Clears what `user` still owes in `mint` and writes it off against `bank`'s depositors, returning
+/// the amount.
+///
+/// The bank's borrows and deposits both drop by the shortfall, so every deposit share is worth less,
+/// and the amount is recorded in `bad_debt`.
+pub(crate) fn write_off_bad_debt(bank: &mut Bank, user: &mut User, mint: &Pubkey) -> Result<u64> {
+    let bad_debt = current_debt(user, bank, mint)?;
+    let debt = user.position_mut(mint)?;
+    let had_debt = debt.borrowed_shares > 0;
+    // Each borrower's debt rounds up, so the last of them can owe a unit more than `total_borrows`, just as
+    // in `burn_debt_shares`. A loss larger than the deposits leaves the depositors with nothing.
+    bank.total_borrows = bank.total_borrows.saturating_sub(bad_debt);
+    bank.total_borrow_shares = bank.total_borrow_shares.checked_sub(debt.borrowed_shares).ok_or(ErrorCode::MathOverflow)?;
+    bank.total_deposits = bank.total_deposits.saturating_sub(bad_debt);
+    bank.bad_debt = bank.bad_debt.checked_add(bad_debt).ok_or(ErrorCode::MathOverflow)?;
+    debt.set_debt(0, bank.borrow_index);
+    debt.borrowed_shares = 0;
+    if had_debt {
+        user.borrowed_asset_count = user.borrowed_asset_count.saturating_sub(1);
+    }
+
+    if bad_debt > 0 {
+        msg!("Wrote off bad debt: {}", bad_debt);
+    }
+    Ok(bad_debt)
+}
+
+/// Writes off what `user` still owes in any asset besides `handled_mints`, each against its bank in
+/// `remaining_accounts`, and records it in `stats`. For a user with no collateral left.
+///
+/// Those banks were only read to value the position, so each one written off must have been passed
+/// writable. It is accrued to `now` and written back.
+pub(crate) fn write_off_other_bad_debt(
+    user: &mut User,
+    handled_mints: &[Pubkey],
+    remaining_accounts: &[AccountInfo],
+    stats: &mut ProtocolStats,
+    now: i64,
+) -> Result<()> {
+    let other_debts: Vec<Pubkey> = user
+        .positions
+        .iter()
+        .filter(|position| position.borrowed_shares > 0 && !handled_mints.contains(&position.mint))
+        .map(|position| position.mint)
+        .collect();
+    for mint in other_debts {
+        let (info, mut bank) = remaining_accounts
+            .iter()
+            .filter(|info| *info.owner == crate::ID)
+            .find_map(|info| {
+                let bank = Bank::try_deserialize(&mut &info.try_borrow_data().ok()?[..]).ok()?;
+                (bank.mint_address == mint).then_some((info, bank))
+            })
+            .ok_or(ErrorCode::MissingBank)?;
+        require!(info.is_writable, ErrorCode::BankNotWritable);
+
+        accrue_interest(&mut bank, now)?;
+        let bad_debt = write_off_bad_debt(&mut bank, user, &mint)?;
+        stats.record_repayment(&bank, bad_debt, now)?;
+        bank.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    }
+    Ok(())
+}
+
+/// Checks that `bank` and `vault` are the protocol's accounts for `mint`: the bank tracks that mint, and
+/// the vault is the treasury PDA holding it, acting as its own authority.
+fn require_canonical_vault(bank: &Bank, vault: &InterfaceAccount<TokenAccount>, mint: &Pubkey) -> Result<()> {
+    require_keys_eq!(bank.mint_address, *mint, ErrorCode::InvalidVault);
+    let (expected_vault, _) = Pubkey::find_program_address(&[b"treasury", mint.as_ref()], &crate::ID);
+    require_keys_eq!(vault.key(), expected_vault, ErrorCode::InvalidVault);
+    require_keys_eq!(vault.mint, *mint, ErrorCode::InvalidVault);
+    require_keys_eq!(vault.owner, expected_vault, ErrorCode::InvalidVault);
     Ok(())
 }
@@ -0,0 +1,357 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::{transfer_from_vault, transfer_to_vault};
+use crate::health::{compute_account_health, current_debt, current_deposit, load_banks, load_price_updates, Prices};
+use crate::constants::BPS_DENOMINATOR;
+use crate::math::{mul_div_floor, require_non_empty, token_value_usd, usd_value_to_native, usd_value_to_native_ceil};
+use crate::interest::accrue_interest;
+use crate::events::LiquidateAllEvent;
+use crate::token_extensions::gross_up_for_transfer_fee;
+use super::liquidate::{
+    cap_liquidation_repay, effective_liquidation_bonus_bps, max_liquidation_repay, max_sound_seize, repaid_debt_value,
+    seize_collateral, start_auction, write_off_bad_debt, write_off_other_bad_debt,
+};
+use super::repay::burn_debt_shares;
+
+//================================================================
+// Accounts Struct for the LiquidateAll Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct LiquidateAll<'info> {
+    /// The person initiating the liquidation. They repay both debts and receive the collateral.
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+
+    /// The global protocol config, checked for the pause switch and read for auction mode and the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the repayments, the seized collateral and the liquidation volume.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The owner of the position being liquidated. Not a signer.
+    /// CHECK: The user_account is derived from this key, ensuring we liquidate the correct person.
+    pub user_to_liquidate: AccountInfo<'info>,
+
+    /// The state account of the user being liquidated.
+    #[account(mut, seeds = [user_to_liquidate.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    /// The first asset. The user may owe it, hold it as collateral, or both.
+    pub mint_a: InterfaceAccount<'info, Mint>,
+
+    /// The bank of the first asset.
+    #[account(mut, seeds = [mint_a.key().as_ref()], bump, constraint = bank_a.decimals == mint_a.decimals @ ErrorCode::DecimalsMismatch)]
+    pub bank_a: Account<'info, Bank>,
+
+    /// The vault of the first asset, which receives its repayment and pays out its seized collateral.
+    #[account(mut, seeds = [b"treasury", mint_a.key().as_ref()], bump)]
+    pub bank_a_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The liquidator's token account for the first asset.
+    #[account(
+        init_if_needed,
+        payer = liquidator,
+        associated_token::mint = mint_a,
+        associated_token::authority = liquidator,
+    )]
+    pub liquidator_a_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The second asset. The user may owe it, hold it as collateral, or both.
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    /// The bank of the second asset.
+    #[account(mut, seeds = [mint_b.key().as_ref()], bump, constraint = bank_b.decimals == mint_b.decimals @ ErrorCode::DecimalsMismatch)]
+    pub bank_b: Account<'info, Bank>,
+
+    /// The vault of the second asset, which receives its repayment and pays out its seized collateral.
+    #[account(mut, seeds = [b"treasury", mint_b.key().as_ref()], bump)]
+    pub bank_b_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The liquidator's token account for the second asset.
+    #[account(
+        init_if_needed,
+        payer = liquidator,
+        associated_token::mint = mint_b,
+        associated_token::authority = liquidator,
+    )]
+    pub liquidator_b_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Pyth price feed account for valuing assets.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    // Standard required programs
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Liquidation of Both Assets
+//================================================================
+/// Repays up to the close factor of the user's debt in both assets at once, and seizes collateral
+/// worth the repaid value plus each collateral bank's bonus, split across the user's collateral in
+/// proportion to its value.
+///
+/// The same limits as `liquidate` apply: each bank's max liquidation value per call, the incentive floor,
+/// topped up only as far as health allows, and the refusal of a liquidation that leaves the position
+/// further underwater.
+pub fn process_liquidate_all(ctx: Context<LiquidateAll>) -> Result<()> {
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+    require_keys_neq!(ctx.accounts.liquidator.key(), ctx.accounts.user_to_liquidate.key(), ErrorCode::SelfLiquidation);
+    require_keys_neq!(ctx.accounts.mint_a.key(), ctx.accounts.mint_b.key(), ErrorCode::IdenticalMints);
+
+    let clock = Clock::get()?;
+
+    // Bring both banks' totals up to date before any share math.
+    accrue_interest(&mut ctx.accounts.bank_a, clock.unix_timestamp)?;
+    accrue_interest(&mut ctx.accounts.bank_b, clock.unix_timestamp)?;
+
+    // The assets are always processed in account order: index 0 is asset A, index 1 is asset B.
+    let mints = [ctx.accounts.mint_a.key(), ctx.accounts.mint_b.key()];
+    let decimals = [ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals];
+
+    // --- 1. Perform Health Check ---
+    // Run once for the whole position, before anything is repaid.
+    let max_price_age = ctx.accounts.bank_a.max_price_age_seconds.min(ctx.accounts.bank_b.max_price_age_seconds);
+    let user = &ctx.accounts.user_account;
+    let extra_banks = load_banks(ctx.remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![&ctx.accounts.bank_a, &ctx.accounts.bank_b];
+    banks.extend(extra_banks.iter());
+    let extra_price_updates = load_price_updates(ctx.remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![&ctx.accounts.price_update];
+    price_updates.extend(extra_price_updates.iter());
+    let prices = Prices::load(&price_updates, ctx.remaining_accounts, &clock, max_price_age, &banks, &ctx.accounts.config.quote_feed_id)?;
+    let price = [prices.for_mint(&mints[0])?, prices.for_mint(&mints[1])?];
+    let health = compute_account_health(user, &prices, &banks)?;
+    if health.is_healthy() {
+        return err!(ErrorCode::PositionHealthy);
+    }
+
+    // --- 2. Repay Up to the Close Factor of Each Debt ---
+    // Each capped at its bank's max liquidation value, so repaying both debts at once can't clear more
+    // of a large position in one call than `liquidate` would.
+    let mut repay = [0u64; 2];
+    let mut repay_value_usd: u128 = 0;
+    for i in 0..2 {
+        let debt = current_debt(user, banks[i], &mints[i])?;
+        let (capped_repay, value) = cap_liquidation_repay(banks[i], price[i], decimals[i], max_liquidation_repay(banks[i], debt)?)?;
+        repay[i] = capped_repay;
+        repay_value_usd = repay_value_usd.checked_add(value).ok_or(ErrorCode::MathOverflow)?;
+    }
+    require!(repay_value_usd > 0, ErrorCode::NothingToRepay);
+
+    // --- 3. Split the Seize Across the Collateral by Value ---
+    // An isolated position only backs its debt with the isolated collateral, so only that is seized.
+    let mut deposit = [0u64; 2];
+    let mut collateral_value = [0u128; 2];
+    for i in 0..2 {
+        if user.isolated && user.isolated_mint != mints[i] {
+            continue;
+        }
+        deposit[i] = current_deposit(user, banks[i], &mints[i])?;
+        collateral_value[i] = token_value_usd(price[i], deposit[i], decimals[i])?;
+    }
+    let total_collateral_value = collateral_value[0].checked_add(collateral_value[1]).ok_or(ErrorCode::MathOverflow)?;
+    require!(total_collateral_value > 0, ErrorCode::InsufficientCollateralToSeize);
+
+    let bonus_bps = [
+        effective_liquidation_bonus_bps(&ctx.accounts.config, user, &ctx.accounts.bank_a, &health, clock.unix_timestamp)?,
+        effective_liquidation_bonus_bps(&ctx.accounts.config, user, &ctx.accounts.bank_b, &health, clock.unix_timestamp)?,
+    ];
+    // The part of a value that falls on the collateral in asset `i`.
+    let share_of = |value: u128, i: usize| -> Result<u128> {
+        Ok(value
+            .checked_mul(collateral_value[i]).ok_or(ErrorCode::MathOverflow)?
+            .checked_div(total_collateral_value).ok_or(ErrorCode::MathOverflow)?)
+    };
+    let mut seize = [0u64; 2];
+    for i in 0..2 {
+        let seize_value_usd = share_of(repay_value_usd, i)?
+            .checked_mul((BPS_DENOMINATOR + bonus_bps[i]) as u128).ok_or(ErrorCode::MathOverflow)?
+            .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::MathOverflow)?;
+        seize[i] = usd_value_to_native(seize_value_usd, price[i], decimals[i])?;
+    }
+
+    // Never seize more collateral than the user holds. If either asset falls short, shrink every
+    // repayment and seize by the same proportion so the liquidator still gets the same rate.
+    // The ratios are compared by cross-multiplying, which cannot overflow since both sides are u64 * u64.
+    let mut scale = (1u64, 1u64);
+    for i in 0..2 {
+        if seize[i] > deposit[i] && (deposit[i] as u128) * (scale.1 as u128) < (scale.0 as u128) * (seize[i] as u128) {
+            scale = (deposit[i], seize[i]);
+        }
+    }
+    if scale.0 < scale.1 {
+        for i in 0..2 {
+            repay[i] = mul_div_floor(repay[i], scale.0, scale.1)?;
+            seize[i] = mul_div_floor(seize[i], scale.0, scale.1)?.min(deposit[i]);
+        }
+        msg!("Seize clamped to available collateral, repay reduced to {:?}", repay);
+    }
+
+    // Raise the seize so the bonus is worth at least the protocol's floor, as `liquidate` does, split
+    // across the collateral like the seize itself. Each asset is raised no further than its share of the
+    // repaid debt keeps the position from sinking further underwater.
+    let min_liquidation_incentive_usd = ctx.accounts.config.min_liquidation_incentive_usd;
+    if min_liquidation_incentive_usd > 0 {
+        let mut repay_value_usd: u128 = 0;
+        let mut repaid_value: u128 = 0;
+        for i in 0..2 {
+            let value = token_value_usd(price[i], repay[i], decimals[i])?;
+            repay_value_usd = repay_value_usd.checked_add(value).ok_or(ErrorCode::MathOverflow)?;
+            let value = repaid_debt_value(banks[i], &prices, repay[i])?;
+            repaid_value = repaid_value.checked_add(value).ok_or(ErrorCode::ValueOverflow)?;
+        }
+        let floor_seize_value_usd = repay_value_usd
+            .checked_add(min_liquidation_incentive_usd).ok_or(ErrorCode::MathOverflow)?;
+        for i in 0..2 {
+            if collateral_value[i] == 0 {
+                continue;
+            }
+            let floor_seize_amount = usd_value_to_native_ceil(share_of(floor_seize_value_usd, i)?, price[i], decimals[i])?;
+            if floor_seize_amount > seize[i] {
+                let sound_seize_amount = max_sound_seize(user, banks[i], &prices, share_of(repaid_value, i)?)?;
+                let raised_seize_amount = floor_seize_amount.min(deposit[i]).min(sound_seize_amount);
+                if raised_seize_amount > seize[i] {
+                    seize[i] = raised_seize_amount;
+                    msg!("Seize of {} raised to the liquidation incentive floor: {}", mints[i], seize[i]);
+                }
+            }
+        }
+    }
+
+    // Writing off bad debt can leave a pool holding shares but no tokens, where the shares burned below
+    // can't be priced. Fail with `BankEmpty` before moving anything instead of dividing by zero.
+    for i in 0..2 {
+        if repay[i] > 0 {
+            require_non_empty(banks[i].total_borrows, banks[i].total_borrow_shares)?;
+        }
+        if seize[i] > 0 {
+            require_non_empty(banks[i].total_deposits, banks[i].total_deposit_shares)?;
+        }
+    }
+
+    // If a collateral mint charges a Token-2022 transfer fee, seize enough extra from the user that
+    // the liquidator still nets the seize amount, up to everything the user holds.
+    let mint_infos = [ctx.accounts.mint_a.to_account_info(), ctx.accounts.mint_b.to_account_info()];
+    for i in 0..2 {
+        seize[i] = gross_up_for_transfer_fee(&mint_infos[i], seize[i], clock.epoch)?.min(deposit[i]);
+    }
+
+    // The liquidator covers any Token-2022 transfer fee on each repayment, so the vault receives all of it.
+    // Fail with a clear error up front rather than with a token-program error partway through the transfers.
+    let liquidator_balances = [ctx.accounts.liquidator_a_token_account.amount, ctx.accounts.liquidator_b_token_account.amount];
+    let mut repay_transfer = [0u64; 2];
+    for i in 0..2 {
+        repay_transfer[i] = gross_up_for_transfer_fee(&mint_infos[i], repay[i], clock.epoch)?;
+        require!(liquidator_balances[i] >= repay_transfer[i], ErrorCode::InsufficientLiquidatorFunds);
+    }
+
+    // --- 4. Perform CPI Transfers ---
+    let vaults = [ctx.accounts.bank_a_token_account.to_account_info(), ctx.accounts.bank_b_token_account.to_account_info()];
+    let liquidator_accounts = [
+        ctx.accounts.liquidator_a_token_account.to_account_info(),
+        ctx.accounts.liquidator_b_token_account.to_account_info(),
+    ];
+    let vault_bumps = [ctx.bumps.bank_a_token_account, ctx.bumps.bank_b_token_account];
+    for i in 0..2 {
+        // A. Liquidator repays the user's debt in this asset.
+        if repay[i] > 0 {
+            transfer_to_vault(
+                ctx.accounts.token_program.to_account_info(),
+                liquidator_accounts[i].clone(),
+                mint_infos[i].clone(),
+                vaults[i].clone(),
+                ctx.accounts.liquidator.to_account_info(),
+                repay_transfer[i],
+                decimals[i],
+            )?;
+        }
+
+        // B. Liquidator seizes the user's collateral in this asset.
+        if seize[i] > 0 {
+            transfer_from_vault(
+                ctx.accounts.token_program.to_account_info(),
+                vaults[i].clone(),
+                mint_infos[i].clone(),
+                liquidator_accounts[i].clone(),
+                vault_bumps[i],
+                seize[i],
+                decimals[i],
+            )?;
+        }
+    }
+
+    // --- 5. Update All State Accounts ---
+    let user = &mut ctx.accounts.user_account;
+    let bank_accounts = [&mut ctx.accounts.bank_a, &mut ctx.accounts.bank_b];
+    let mut repay_shares = [0u64; 2];
+    let mut seize_shares = [0u64; 2];
+    for (i, bank) in bank_accounts.into_iter().enumerate() {
+        if repay[i] > 0 {
+            // Round down, so repaying never clears more debt shares than it paid for, and never past the
+            // shares the user holds.
+            let outstanding_debt = current_debt(user, bank, &mints[i])?;
+            repay_shares[i] = burn_debt_shares(bank, user, &mints[i], repay[i], outstanding_debt)?;
+        }
+        if seize[i] > 0 {
+            seize_shares[i] = seize_collateral(bank, user, &mints[i], seize[i])?;
+        }
+    }
+
+    // --- 6. Write Off Bad Debt ---
+    // Once the user has no collateral left in any asset, what they still owe can never be repaid, in
+    // these two assets or any other, whose bank is in `remaining_accounts`.
+    let mut bad_debt = [0u64; 2];
+    let collateral_exhausted = user.positions.iter().all(|position| position.deposited_shares == 0);
+    if collateral_exhausted {
+        let bank_accounts = [&mut ctx.accounts.bank_a, &mut ctx.accounts.bank_b];
+        for (i, bank) in bank_accounts.into_iter().enumerate() {
+            if user.position(&mints[i]).is_some() {
+                bad_debt[i] = write_off_bad_debt(bank, user, &mints[i])?;
+            }
+        }
+        write_off_other_bad_debt(user, &mints, ctx.remaining_accounts, &mut ctx.accounts.protocol_stats, clock.unix_timestamp)?;
+    }
+
+    // --- 7. Check the Liquidation Helped ---
+    // Revalue the position at the same prices and refuse a liquidation that left it further underwater,
+    // unless it closed the position out entirely, as `liquidate` does.
+    if !collateral_exhausted && user.has_debt() {
+        let mut banks: Vec<&Bank> = vec![&ctx.accounts.bank_a, &ctx.accounts.bank_b];
+        banks.extend(extra_banks.iter());
+        let health_after = compute_account_health(user, &prices, &banks)?;
+        require!(health_after.is_no_worse_than(&health), ErrorCode::LiquidationWorsensHealth);
+    }
+    start_auction(&ctx.accounts.config, user, clock.unix_timestamp);
+
+    // Debt written off leaves the outstanding total just as repaid debt does.
+    let stats = &mut ctx.accounts.protocol_stats;
+    for (i, bank) in [&ctx.accounts.bank_a, &ctx.accounts.bank_b].into_iter().enumerate() {
+        let debt_cleared = repay[i].checked_add(bad_debt[i]).ok_or(ErrorCode::MathOverflow)?;
+        stats.record_repayment(bank, debt_cleared, clock.unix_timestamp)?;
+        stats.record_withdrawal(bank, seize[i], clock.unix_timestamp)?;
+        stats.record_liquidation(bank, repay[i], clock.unix_timestamp)?;
+    }
+
+    emit!(LiquidateAllEvent {
+        liquidator: ctx.accounts.liquidator.key(),
+        user: ctx.accounts.user_to_liquidate.key(),
+        mints,
+        repay_amounts: repay,
+        repay_shares,
+        seize_amounts: seize,
+        seize_shares,
+        bad_debt,
+    });
+
+    msg!("Liquidated both assets. Repaid: {:?}, seized: {:?}", repay, seize);
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::constants::{SOL_MINT_ADDRESS, USER_VERSION};
+
+//================================================================
+// Accounts Struct for the MigrateUser Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct MigrateUser<'info> {
+    /// The owner of the account being migrated, who pays for any extra rent the new layout needs.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The user's state account, still in whatever layout it was written with.
+    /// CHECK: Read as raw bytes because an old layout can't deserialize as `User`. The seeds tie it to
+    /// the signer, the owner check to this program, and the discriminator is checked before decoding.
+    #[account(mut, seeds = [signer.key().as_ref()], bump, owner = crate::ID)]
+    pub user_account: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Size of a `User` in layout 2, which ended at `version`. `auction_start_ts` and `borrowed_asset_count`
+/// are the only fields added since, so such an account reads as the current layout once it is zero-padded.
+const USER_V2_SPACE: usize = User::INIT_SPACE - 9;
+
+/// The original `User` layout, which tracked the SOL and USDC banks in fixed fields instead of positions.
+#[derive(AnchorDeserialize, InitSpace)]
+struct LegacyUser {
+    owner: Pubkey,
+    deposited_sol: u64,
+    deposited_sol_shares: u64,
+    borrowed_sol: u64,
+    borrowed_sol_shares: u64,
+    deposited_usdc: u64,
+    deposited_usdc_shares: u64,
+    borrowed_usdc: u64,
+    borrowed_usdc_shares: u64,
+    usdc_address: Pubkey,
+    health_factor: u64,
+    last_updated: i64,
+}
+
+impl LegacyUser {
+    /// Moves the fixed SOL and USDC fields into positions, leaving every newer field at its default.
+    fn into_user(self) -> User {
+        let positions = [
+            Position {
+                mint: SOL_MINT_ADDRESS,
+                deposited: self.deposited_sol,
+                deposited_shares: self.deposited_sol_shares,
+                borrowed: self.borrowed_sol,
+                borrowed_shares: self.borrowed_sol_shares,
+                last_deposit_ts: 0,
+                borrow_index: 0,
+            },
+            Position {
+                mint: self.usdc_address,
+                deposited: self.deposited_usdc,
+                deposited_shares: self.deposited_usdc_shares,
+                borrowed: self.borrowed_usdc,
+                borrowed_shares: self.borrowed_usdc_shares,
+                last_deposit_ts: 0,
+                borrow_index: 0,
+            },
+        ];
+        User {
+            owner: self.owner,
+            // An untouched asset needs no position, which leaves room for new ones.
+            positions: positions
+                .into_iter()
+                .filter(|position| position.deposited_shares > 0 || position.borrowed_shares > 0)
+                .collect(),
+            usdc_address: self.usdc_address,
+            health_factor: self.health_factor,
+            last_updated: self.last_updated,
+            ..Default::default()
+        }
+    }
+}
+
+/// `Position` before it recorded a borrow index, as written by user layouts 0 and 1.
+#[derive(AnchorDeserialize)]
+struct PositionV1 {
+    mint: Pubkey,
+    deposited: u64,
+    deposited_shares: u64,
+    borrowed: u64,
+    borrowed_shares: u64,
+    last_deposit_ts: i64,
+}
+
+/// `User` as written by layouts 0 and 1. Layout 0 is missing some of the trailing fields, which read
+/// as their defaults once the data is zero-padded.
+#[derive(AnchorDeserialize)]
+struct UserV1 {
+    owner: Pubkey,
+    positions: Vec<PositionV1>,
+    usdc_address: Pubkey,
+    health_factor: u64,
+    last_updated: i64,
+    isolated: bool,
+    isolated_mint: Pubkey,
+    created_at: u64,
+    position_nonce: u64,
+    version: u8,
+}
+
+impl UserV1 {
+    /// Carries every field over. Positions get no borrow index, so their debt is valued by their shares
+    /// until their next borrow or repayment records one.
+    fn into_user(self) -> User {
+        User {
+            owner: self.owner,
+            positions: self
+                .positions
+                .into_iter()
+                .map(|position| Position {
+                    mint: position.mint,
+                    deposited: position.deposited,
+                    deposited_shares: position.deposited_shares,
+                    borrowed: position.borrowed,
+                    borrowed_shares: position.borrowed_shares,
+                    last_deposit_ts: position.last_deposit_ts,
+                    borrow_index: 0,
+                })
+                .collect(),
+            usdc_address: self.usdc_address,
+            health_factor: self.health_factor,
+            last_updated: self.last_updated,
+            isolated: self.isolated,
+            isolated_mint: self.isolated_mint,
+            created_at: self.created_at,
+            position_nonce: self.position_nonce,
+            version: self.version,
+            auction_start_ts: 0,
+            borrowed_asset_count: 0,
+        }
+    }
+}
+
+//================================================================
+// Instruction Logic for Migrating a User Account
+//================================================================
+/// Rewrites a user account created under an older layout into the current one, so it deserializes as
+/// `User` again without its owner having to close and recreate it.
+///
+/// An account in the original fixed-field layout has its SOL and USDC fields moved into positions. An
+/// account smaller than layout 2 predates the borrow index on each position, and is read with `UserV1`.
+/// One in layout 2 or 3 only lacks trailing fields, and anything else is already current. Every migrated
+/// account has its borrowed asset count recomputed from its positions, since no older layout kept one.
+pub fn process_migrate_user(ctx: Context<MigrateUser>) -> Result<()> {
+    let info = ctx.accounts.user_account.to_account_info();
+
+    // --- 1. Decode Whichever Layout the Account Is In ---
+    let user = {
+        let data = info.try_borrow_data()?;
+        require!(
+            data.starts_with(User::DISCRIMINATOR),
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+        let body = &data[User::DISCRIMINATOR.len()..];
+        if body.len() == LegacyUser::INIT_SPACE {
+            LegacyUser::deserialize(&mut &body[..])?.into_user()
+        } else if body.len() < USER_V2_SPACE {
+            let mut padded = body.to_vec();
+            padded.resize(User::INIT_SPACE, 0);
+            UserV1::deserialize(&mut &padded[..])?.into_user()
+        } else if body.len() < User::INIT_SPACE {
+            let mut padded = body.to_vec();
+            padded.resize(User::INIT_SPACE, 0);
+            User::deserialize(&mut &padded[..])?
+        } else {
+            User::deserialize(&mut &body[..])?
+        }
+    };
+    require!(user.version < USER_VERSION, ErrorCode::UserAlreadyMigrated);
+    let user = User {
+        version: USER_VERSION,
+        borrowed_asset_count: user.count_borrowed_assets(),
+        ..user
+    };
+
+    // --- 2. Grow the Account to the Current Size ---
+    let space = 8 + User::INIT_SPACE;
+    if info.data_len() < space {
+        let top_up = Rent::get()?.minimum_balance(space).saturating_sub(info.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.signer.to_account_info(),
+                        to: info.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        info.resize(space)?;
+    }
+
+    // --- 3. Write the Current Layout ---
+    // Clear the old bytes first so nothing from the previous layout lingers past the new data.
+    let mut data = info.try_borrow_mut_data()?;
+    data.fill(0);
+    user.try_serialize(&mut &mut data[..])?;
+
+    msg!("Migrated user account {} to version {}", info.key(), USER_VERSION);
+    Ok(())
+}
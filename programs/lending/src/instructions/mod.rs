@@ -1,12 +1,62 @@
+pub use initialize::*;
+pub mod initialize;
 pub use admin::*;
 pub mod admin;
+pub use init_bank::*;
+pub mod init_bank;
+pub use add_supported_asset::*;
+pub mod add_supported_asset;
+pub use init_user::*;
+pub mod init_user;
+pub use close_user::*;
+pub mod close_user;
+pub use migrate_user::*;
+pub mod migrate_user;
 pub use deposit::*;
 pub mod deposit;
+pub use deposit_sol::*;
+pub mod deposit_sol;
+pub use deposit_multi::*;
+pub mod deposit_multi;
 pub use borrow::*;
 pub mod borrow;
 pub use withdraw::*;
 pub mod withdraw;
+pub use withdraw_sol::*;
+pub mod withdraw_sol;
 pub use repay::*;
 pub mod repay;
+pub use repay_on_behalf::*;
+pub mod repay_on_behalf;
+pub use repay_and_withdraw::*;
+pub mod repay_and_withdraw;
 pub use liquidate::*;
-pub mod liquidate;
\ No newline at end of file
+pub mod liquidate;
+pub use deposit_and_borrow::*;
+pub mod deposit_and_borrow;
+pub use flash_loan::*;
+pub mod flash_loan;
+pub use withdraw_reserves::*;
+pub mod withdraw_reserves;
+pub use collect_fees::*;
+pub mod collect_fees;
+pub use get_health::*;
+pub mod get_health;
+pub use is_liquidatable::*;
+pub mod is_liquidatable;
+pub use preview_liquidation::*;
+pub mod preview_liquidation;
+pub use refresh_auction::*;
+pub mod refresh_auction;
+pub use get_max_borrow::*;
+pub mod get_max_borrow;
+pub use emergency_withdraw::*;
+pub mod emergency_withdraw;
+pub use liquidate_all::*;
+pub mod liquidate_all;
+pub use get_bank_rates::*;
+pub mod get_bank_rates;
+#[cfg(feature = "debug")]
+pub use reconcile_bank::*;
+#[cfg(feature = "debug")]
+pub mod reconcile_bank;
@@ -0,0 +1,75 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::interest::accrue_interest;
+use super::liquidate::{plan_liquidation, LiquidationAmounts};
+
+//================================================================
+// Accounts Struct for the PreviewLiquidation Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct PreviewLiquidation<'info> {
+    /// The owner of the position being previewed. Anyone may query any user, so this is NOT a signer.
+    /// CHECK: The user_account is derived from this key, ensuring we read the correct position.
+    pub owner: AccountInfo<'info>,
+
+    /// The global protocol config, read for the liquidation incentive floor, auction mode and the
+    /// quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// The state account of the user being previewed. Read-only.
+    #[account(seeds = [owner.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    /// The mint of the asset the user borrowed.
+    pub borrowed_mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank of the asset the user borrowed. Read-only: interest is accrued on a copy.
+    #[account(seeds = [borrowed_mint.key().as_ref()], bump, constraint = borrowed_bank.decimals == borrowed_mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub borrowed_bank: Account<'info, Bank>,
+
+    /// The mint of the collateral asset that would be seized.
+    #[account(constraint = collateral_mint.key() != borrowed_mint.key() @ ErrorCode::IdenticalMints)]
+    pub collateral_mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank of the collateral asset. Read-only: interest is accrued on a copy.
+    #[account(seeds = [collateral_mint.key().as_ref()], bump, constraint = collateral_bank.decimals == collateral_mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub collateral_bank: Account<'info, Bank>,
+
+    /// Pyth price feed account for valuing assets.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Liquidation Preview
+//================================================================
+/// Works out what `liquidate` would repay and seize for `repay_amount` at current prices, without
+/// moving any tokens, so bots can size a liquidation before committing capital.
+pub fn process_preview_liquidation(ctx: Context<PreviewLiquidation>, repay_amount: u64) -> Result<LiquidationAmounts> {
+    let clock = Clock::get()?;
+
+    // `liquidate` accrues both banks before sizing the liquidation, so do the same here.
+    let mut borrowed_bank = ctx.accounts.borrowed_bank.clone().into_inner();
+    let mut collateral_bank = ctx.accounts.collateral_bank.clone().into_inner();
+    accrue_interest(&mut borrowed_bank, clock.unix_timestamp)?;
+    accrue_interest(&mut collateral_bank, clock.unix_timestamp)?;
+
+    let (amounts, ..) = plan_liquidation(
+        &ctx.accounts.user_account,
+        &borrowed_bank,
+        &ctx.accounts.borrowed_mint,
+        &collateral_bank,
+        &ctx.accounts.collateral_mint,
+        &ctx.accounts.price_update,
+        ctx.remaining_accounts,
+        &clock,
+        &ctx.accounts.config,
+        repay_amount,
+    )?;
+    // Anchor serializes the returned value into the transaction's return data.
+    Ok(amounts)
+}
@@ -0,0 +1,76 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::health::{compute_account_health, load_banks, load_price_updates, Prices};
+use crate::interest::accrue_interest;
+use super::liquidate::start_auction;
+
+//================================================================
+// Accounts Struct for the RefreshAuction Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct RefreshAuction<'info> {
+    /// The owner of the position being checked. Anyone may refresh any user's auction, so this is NOT a signer.
+    /// CHECK: The user_account is derived from this key, ensuring we update the correct position.
+    pub owner: AccountInfo<'info>,
+
+    /// The global protocol config, read for auction mode and the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// The state account of the user whose auction is started or cleared.
+    #[account(mut, seeds = [owner.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    /// The bank of the asset the user borrowed. Read-only: interest is accrued on a copy.
+    pub borrowed_bank: Account<'info, Bank>,
+
+    /// The bank of the asset backing the loan. Read-only: interest is accrued on a copy.
+    #[account(constraint = collateral_bank.key() != borrowed_bank.key() @ ErrorCode::IdenticalMints)]
+    pub collateral_bank: Account<'info, Bank>,
+
+    /// Pyth price feed account for valuing assets.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+}
+
+//================================================================
+// Instruction Logic for Processing an Auction Refresh
+//================================================================
+/// Starts a liquidation auction for a position that has become liquidatable, or clears the one left
+/// running on a position that is healthy again, so its next auction starts from the minimum bonus.
+/// Runs the same health check as `liquidate`, and anyone may call it.
+pub fn process_refresh_auction(ctx: Context<RefreshAuction>) -> Result<()> {
+    require!(ctx.accounts.config.auction_mode, ErrorCode::AuctionModeDisabled);
+    let clock = Clock::get()?;
+
+    // `liquidate` accrues both banks before its health check, so do the same here.
+    let mut borrowed_bank = ctx.accounts.borrowed_bank.clone().into_inner();
+    let mut collateral_bank = ctx.accounts.collateral_bank.clone().into_inner();
+    accrue_interest(&mut borrowed_bank, clock.unix_timestamp)?;
+    accrue_interest(&mut collateral_bank, clock.unix_timestamp)?;
+
+    // Use the stricter of the two banks' staleness windows, since both assets are priced here.
+    let max_price_age = borrowed_bank.max_price_age_seconds.min(collateral_bank.max_price_age_seconds);
+
+    // Positions in any other asset are valued with the banks in `remaining_accounts`.
+    let extra_banks = load_banks(ctx.remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![&borrowed_bank, &collateral_bank];
+    banks.extend(extra_banks.iter());
+    let extra_price_updates = load_price_updates(ctx.remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![&ctx.accounts.price_update];
+    price_updates.extend(extra_price_updates.iter());
+    let prices = Prices::load(&price_updates, ctx.remaining_accounts, &clock, max_price_age, &banks, &ctx.accounts.config.quote_feed_id)?;
+    let health = compute_account_health(&ctx.accounts.user_account, &prices, &banks)?;
+
+    let user = &mut ctx.accounts.user_account;
+    if health.is_healthy() {
+        user.auction_start_ts = 0;
+    } else {
+        start_auction(&ctx.accounts.config, user, clock.unix_timestamp);
+    }
+
+    msg!("Auction start for {}: {}", ctx.accounts.owner.key(), user.auction_start_ts);
+    Ok(())
+}
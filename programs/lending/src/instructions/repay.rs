@@ -1,105 +1,190 @@
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token_interface::{ self, Mint, TokenAccount, TokenInterface, TransferChecked };
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 use crate::state::*;
 use crate::error::ErrorCode;
+use crate::token_utils::transfer_to_vault;
+use crate::interest::accrue_interest;
+use crate::health::current_debt;
+use crate::math::{mul_div_floor, require_non_empty};
+use crate::events::RepayEvent;
+use crate::token_extensions::gross_up_for_transfer_fee;
 
+//================================================================
+// Accounts Struct for the Repay Instruction
+//================================================================
 #[derive(Accounts)]
 pub struct Repay<'info> {
+    /// The borrower repaying their debt. They must sign to authorize the token transfer.
     #[account(mut)]
     pub signer: Signer<'info>,
+
+    /// The mint of the asset being repaid.
     pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's state account for the asset being repaid.
     #[account(
-        mut, 
+        mut,
         seeds = [mint.key().as_ref()],
         bump,
-    )]  
+        constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch,
+    )]
     pub bank: Account<'info, Bank>,
+
+    /// Protocol-wide totals, updated with the repayment.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The bank's vault (PDA) that receives the repaid tokens.
     #[account(
-        mut, 
+        mut,
         seeds = [b"treasury", mint.key().as_ref()],
-        bump, 
-    )]  
+        bump,
+    )]
     pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The borrower's state account, which tracks their outstanding debt.
     #[account(
-        mut, 
+        mut,
         seeds = [signer.key().as_ref()],
         bump,
-    )]  
+    )]
     pub user_account: Account<'info, User>,
-    #[account( 
-        init_if_needed, 
-        payer = signer,
-        associated_token::mint = mint, 
+
+    /// The borrower's token account (ATA) the repayment is transferred FROM.
+    #[account(
+        mut,
+        associated_token::mint = mint,
         associated_token::authority = signer,
         associated_token::token_program = token_program,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>, 
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // Standard required programs
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
-// Repay function just needs to make a CPI transfer from the user's token account into the bank's token account
+//================================================================
+// Instruction Logic for Processing a Repayment
+//================================================================
 pub fn process_repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
-    let user = &mut ctx.accounts.user_account;
+    // --- 1. Security Check ---
+    if amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
 
-    let borrowed_asset; 
+    // Bring the bank's totals up to date before any share math.
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    accrue_interest(&mut ctx.accounts.bank, now)?;
+    // There is nothing to repay into a bank nobody has borrowed from.
+    require_non_empty(ctx.accounts.bank.total_borrows, ctx.accounts.bank.total_borrow_shares)?;
 
-    // Note: For simplicity, interest fees are not included in this calculation
+    // --- 2. Determine the User's Outstanding Debt ---
+    // The debt is the user's share of the bank's borrows, so it includes all interest accrued so far.
+    let outstanding_debt = current_debt(&ctx.accounts.user_account, &ctx.accounts.bank, &ctx.accounts.mint.key())?;
 
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            borrowed_asset = user.borrowed_usdc;
-        },
-        _ => {
-            borrowed_asset = user.borrowed_sol;
-        }
+    if outstanding_debt == 0 {
+        return err!(ErrorCode::NothingToRepay);
     }
 
-    if amount > borrowed_asset {
-        return Err(ErrorCode::OverRepay.into());
-    }
+    // Cap the repayment at the outstanding debt, so a user who overpays only transfers what they owe.
+    let amount = amount.min(outstanding_debt);
 
-    let transfer_cpi_accounts = TransferChecked {
-        from: ctx.accounts.user_token_account.to_account_info(),
-        mint: ctx.accounts.mint.to_account_info(),
-        to: ctx.accounts.bank_token_account.to_account_info(),
-        authority: ctx.accounts.signer.to_account_info(),
-    };
+    // --- 3. Transfer Tokens to the Bank (CPI) ---
+    // The debt only goes down by what reaches the vault, so if the mint charges a Token-2022 transfer
+    // fee the user sends enough extra to cover it.
+    let transfer_amount = gross_up_for_transfer_fee(&ctx.accounts.mint.to_account_info(), amount, clock.epoch)?;
+    transfer_to_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.signer.to_account_info(),
+        transfer_amount,
+        ctx.accounts.mint.decimals,
+    )?;
 
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts);
-    let decimals = ctx.accounts.mint.decimals;
+    // --- 4. Update User and Bank State ---
+    let mint_key = ctx.accounts.mint.key();
+    let bank = &mut ctx.accounts.bank;
+    let user = &mut ctx.accounts.user_account;
+    let shares_repaid = record_repayment(bank, user, &mint_key, amount, outstanding_debt)?;
+    ctx.accounts.protocol_stats.record_repayment(bank, amount, now)?;
 
-    token_interface::transfer_checked(cpi_ctx, amount, decimals)?;
+    user.last_updated = now;
 
-    // Note: The checked_ prefix in Rust is used to perform operations safely by checking for potential 
-    // arithmetic overflow or other errors that could occur during the computation. If such an error occurs, these methods
-    // return None instead of causing a panic.
+    emit!(RepayEvent {
+        user: ctx.accounts.signer.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        shares: shares_repaid,
+        total_borrows: bank.total_borrows,
+        total_borrow_shares: bank.total_borrow_shares,
+    });
 
-    let bank = &mut ctx.accounts.bank;
+    msg!("Repay successful. Amount: {}, Shares burned: {}", amount, shares_repaid);
 
-    let borrowed_ratio = amount.checked_div(bank.total_borrowed).unwrap();
-    let users_shares = bank.total_borrowed_shares.checked_mul(borrowed_ratio).unwrap();
-    
-    let user = &mut ctx.accounts.user_account;
-    
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            user.borrowed_usdc -= amount;
-            user.borrowed_usdc_shares -= users_shares;
-        },
-        _ => {
-            user.borrowed_sol -= amount;
-            user.borrowed_sol_shares -= users_shares; 
-        }
-    }
+    Ok(())
+}
 
-    // Add in "update health factor" function here
+/// Burns the debt shares `amount` pays off from the user's position in `mint` and from the bank's
+/// totals, returning how many shares were burned. `amount` must already be capped at `outstanding_debt`.
+///
+/// A partial repayment may not leave a debt below the bank's minimum borrow amount: the user either
+/// repays in full or stays above the floor.
+pub(crate) fn record_repayment(
+    bank: &mut Bank,
+    user: &mut User,
+    mint: &Pubkey,
+    amount: u64,
+    outstanding_debt: u64,
+) -> Result<u64> {
+    let remaining_debt = outstanding_debt.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+    require!(remaining_debt == 0 || remaining_debt >= bank.min_borrow_amount, ErrorCode::BorrowTooSmall);
+    burn_debt_shares(bank, user, mint, amount, outstanding_debt)
+}
 
-    bank.total_borrowed -= amount;
-    bank.total_borrowed_shares -= users_shares;
+/// Removes `amount` of the user's `outstanding_debt` in `mint` from their position and the bank's totals,
+/// returning how many debt shares were burned. Shared by repayments and liquidations, which both must
+/// already have capped `amount` at `outstanding_debt`.
+pub(crate) fn burn_debt_shares(
+    bank: &mut Bank,
+    user: &mut User,
+    mint: &Pubkey,
+    amount: u64,
+    outstanding_debt: u64,
+) -> Result<u64> {
+    // A partial repayment burns the shares it covers, rounded down.
+    // Formula: shares_repaid = (amount * total_borrow_shares) / total_borrows
+    let position = user.position_mut(mint)?;
+    let full_repayment = amount == outstanding_debt;
+    let shares_repaid = if full_repayment {
+        // Repaying the whole debt burns every share the user holds, including any residue that rounding
+        // left behind, so they end up exactly debt free. The bank can never hold fewer shares than one
+        // of its borrowers, but guard the subtraction anyway.
+        position.borrowed_shares.min(bank.total_borrow_shares)
+    } else {
+        mul_div_floor(amount, bank.total_borrow_shares, bank.total_borrows)?
+    };
 
-    Ok(())
-}
\ No newline at end of file
+    if full_repayment {
+        position.borrowed_shares = 0;
+    } else {
+        position.borrowed_shares = position.borrowed_shares.checked_sub(shares_repaid).ok_or(ErrorCode::MathOverflow)?;
+    }
+    // What is left is recorded at the current index, so the interest folded into it isn't charged twice.
+    let remaining_debt = outstanding_debt.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+    position.set_debt(remaining_debt, bank.borrow_index);
+    if position.borrowed_shares == 0 {
+        user.borrowed_asset_count = user.borrowed_asset_count.saturating_sub(1);
+    }
+
+    // Each borrower's debt rounds up, so together they can owe a unit or two more than `total_borrows`.
+    // The last of them to repay must not underflow it.
+    bank.total_borrows = bank.total_borrows.saturating_sub(amount);
+    bank.total_borrow_shares = bank.total_borrow_shares.checked_sub(shares_repaid).ok_or(ErrorCode::MathOverflow)?;
+    Ok(shares_repaid)
+}
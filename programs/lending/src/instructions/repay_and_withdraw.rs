@@ -0,0 +1,224 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::{transfer_from_vault, transfer_to_vault};
+use crate::interest::accrue_interest;
+use crate::health::current_debt;
+use crate::math::require_non_empty;
+use crate::events::{RepayEvent, WithdrawEvent};
+use crate::token_extensions::gross_up_for_transfer_fee;
+use super::repay::record_repayment;
+use super::withdraw::{plan_withdrawal, record_withdrawal, WithdrawSize};
+
+//================================================================
+// Accounts Struct for the RepayAndWithdraw Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct RepayAndWithdraw<'info> {
+    /// The user repaying their debt and withdrawing collateral. They must sign both transfers.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The global protocol config, checked for the pause switch and read for the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the repayment and the withdrawal.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The mint of the asset being repaid.
+    #[account(constraint = *repay_mint.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch)]
+    pub repay_mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's state account for the asset being repaid. It is also valued in the withdrawal's
+    /// health check.
+    #[account(mut, seeds = [repay_mint.key().as_ref()], bump, constraint = repay_bank.decimals == repay_mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub repay_bank: Account<'info, Bank>,
+
+    /// The bank's vault (PDA) that receives the repaid tokens.
+    #[account(
+        mut,
+        seeds = [b"treasury", repay_mint.key().as_ref()],
+        bump,
+        constraint = *repay_bank_token_account.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch,
+    )]
+    pub repay_bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account (ATA) the repayment is transferred FROM.
+    #[account(
+        mut,
+        associated_token::mint = repay_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub user_repay_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint of the collateral asset being withdrawn.
+    #[account(
+        mut,
+        constraint = withdraw_mint.key() != repay_mint.key() @ ErrorCode::IdenticalMints,
+        constraint = *withdraw_mint.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch,
+    )]
+    pub withdraw_mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's state account for the collateral asset being withdrawn.
+    #[account(mut, seeds = [withdraw_mint.key().as_ref()], bump, constraint = withdraw_bank.decimals == withdraw_mint.decimals @ ErrorCode::DecimalsMismatch)]
+    pub withdraw_bank: Account<'info, Bank>,
+
+    /// The bank's vault (PDA) from which the collateral is paid out.
+    #[account(
+        mut,
+        seeds = [b"treasury", withdraw_mint.key().as_ref()],
+        bump,
+        constraint = *withdraw_bank_token_account.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch,
+    )]
+    pub withdraw_bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account (ATA) the collateral is sent to.
+    /// Anchor will create it if it doesn't exist, with the user paying the rent. An existing one must be
+    /// the signer's with no delegate, as in `withdraw`.
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = withdraw_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+        constraint = user_withdraw_token_account.owner == signer.key() && user_withdraw_token_account.delegate.is_none() @ ErrorCode::UnexpectedTokenAccountState,
+    )]
+    pub user_withdraw_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's master account (PDA), which holds both the debt being repaid and the collateral being withdrawn.
+    #[account(mut, seeds = [signer.key().as_ref()], bump)]
+    pub user_account: Account<'info, User>,
+
+    /// Pyth price feed account for the withdrawal's health check.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    // Standard required programs
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Repay-and-Withdraw
+//================================================================
+/// Repays up to `repay_amount` of the user's debt, then withdraws `withdraw_shares` of their collateral.
+/// The withdrawal is health-checked against the reduced debt, so a user closing out a position can pull
+/// collateral the debt was holding in place without a second transaction.
+pub fn process_repay_and_withdraw(ctx: Context<RepayAndWithdraw>, repay_amount: u64, withdraw_shares: u64) -> Result<()> {
+    // --- 1. Security Checks ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+    if repay_amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
+
+    // Bring both banks' totals up to date before any share math.
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    accrue_interest(&mut ctx.accounts.repay_bank, now)?;
+    accrue_interest(&mut ctx.accounts.withdraw_bank, now)?;
+
+    // --- 2. Repay the Debt ---
+    // There is nothing to repay into a bank nobody has borrowed from.
+    require_non_empty(ctx.accounts.repay_bank.total_borrows, ctx.accounts.repay_bank.total_borrow_shares)?;
+    let repay_mint_key = ctx.accounts.repay_mint.key();
+    let outstanding_debt = current_debt(&ctx.accounts.user_account, &ctx.accounts.repay_bank, &repay_mint_key)?;
+    if outstanding_debt == 0 {
+        return err!(ErrorCode::NothingToRepay);
+    }
+    // Cap the repayment at the outstanding debt, so a user who overpays only transfers what they owe.
+    let repay_amount = repay_amount.min(outstanding_debt);
+
+    // Sent grossed up for any Token-2022 transfer fee, so the vault receives the whole repayment.
+    let repay_transfer_amount = gross_up_for_transfer_fee(&ctx.accounts.repay_mint.to_account_info(), repay_amount, clock.epoch)?;
+    transfer_to_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.user_repay_token_account.to_account_info(),
+        ctx.accounts.repay_mint.to_account_info(),
+        ctx.accounts.repay_bank_token_account.to_account_info(),
+        ctx.accounts.signer.to_account_info(),
+        repay_transfer_amount,
+        ctx.accounts.repay_mint.decimals,
+    )?;
+
+    let shares_repaid = record_repayment(
+        &mut ctx.accounts.repay_bank,
+        &mut ctx.accounts.user_account,
+        &repay_mint_key,
+        repay_amount,
+        outstanding_debt,
+    )?;
+
+    // --- 3. Size the Withdrawal and Check the Position Stays Healthy ---
+    // The user account already reflects the repayment, so the health check sees the reduced debt.
+    let (gross_amount, shares_to_burn, prices) = plan_withdrawal(
+        &ctx.accounts.config,
+        &ctx.accounts.withdraw_bank,
+        &ctx.accounts.repay_bank,
+        &ctx.accounts.user_account,
+        &ctx.accounts.withdraw_mint.to_account_info(),
+        &ctx.accounts.price_update,
+        ctx.remaining_accounts,
+        &clock,
+        WithdrawSize::Shares(withdraw_shares),
+    )?;
+
+    // --- 4. Pay Out the Collateral (CPI) ---
+    let withdraw_mint_key = ctx.accounts.withdraw_mint.key();
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.withdraw_bank_token_account.to_account_info(),
+        ctx.accounts.withdraw_mint.to_account_info(),
+        ctx.accounts.user_withdraw_token_account.to_account_info(),
+        ctx.bumps.withdraw_bank_token_account,
+        gross_amount,
+        ctx.accounts.withdraw_mint.decimals,
+    )?;
+
+    // --- 5. Update State ---
+    record_withdrawal(
+        &mut ctx.accounts.withdraw_bank,
+        &mut ctx.accounts.user_account,
+        &withdraw_mint_key,
+        gross_amount,
+        shares_to_burn,
+    )?;
+    prices.record(&mut ctx.accounts.withdraw_bank, now);
+    prices.record(&mut ctx.accounts.repay_bank, now);
+    let stats = &mut ctx.accounts.protocol_stats;
+    stats.record_repayment(&ctx.accounts.repay_bank, repay_amount, now)?;
+    stats.record_withdrawal(&ctx.accounts.withdraw_bank, gross_amount, now)?;
+
+    ctx.accounts.user_account.last_updated = now;
+
+    emit!(RepayEvent {
+        user: ctx.accounts.signer.key(),
+        mint: repay_mint_key,
+        amount: repay_amount,
+        shares: shares_repaid,
+        total_borrows: ctx.accounts.repay_bank.total_borrows,
+        total_borrow_shares: ctx.accounts.repay_bank.total_borrow_shares,
+    });
+    emit!(WithdrawEvent {
+        user: ctx.accounts.signer.key(),
+        mint: withdraw_mint_key,
+        amount: gross_amount,
+        shares: shares_to_burn,
+        total_deposits: ctx.accounts.withdraw_bank.total_deposits,
+        total_deposit_shares: ctx.accounts.withdraw_bank.total_deposit_shares,
+    });
+
+    msg!(
+        "Repay and withdraw successful. Repaid: {}, Withdrawn: {}, Shares redeemed: {}",
+        repay_amount,
+        gross_amount,
+        shares_to_burn
+    );
+    Ok(())
+}
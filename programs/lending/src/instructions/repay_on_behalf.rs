@@ -0,0 +1,143 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::transfer_to_vault;
+use crate::interest::accrue_interest;
+use crate::health::current_debt;
+use crate::math::require_non_empty;
+use crate::events::RepayEvent;
+use crate::token_extensions::gross_up_for_transfer_fee;
+use super::repay::record_repayment;
+
+//================================================================
+// Accounts Struct for the RepayOnBehalf Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct RepayOnBehalf<'info> {
+    /// The third party paying down someone else's debt, e.g. a keeper or a deleveraging service.
+    /// They sign for the token transfer but gain no claim on the borrower's position.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// The borrower whose debt is being repaid. They do not need to sign.
+    /// CHECK: The user_account is derived from this key, ensuring we reduce the correct debt.
+    pub owner: AccountInfo<'info>,
+
+    /// The mint of the asset being repaid.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank's state account for the asset being repaid.
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+        constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    /// Protocol-wide totals, updated with the repayment.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The bank's vault (PDA) that receives the repaid tokens.
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The borrower's state account. Only its debt in `mint` changes.
+    #[account(
+        mut,
+        seeds = [owner.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+
+    /// The payer's token account (ATA) the repayment is transferred FROM.
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub payer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // Standard required programs
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Repayment on Behalf of Another User
+//================================================================
+/// Repays up to `amount` of `owner`'s debt in `mint` out of the payer's tokens.
+///
+/// Repaying only ever burns debt shares and never touches collateral, so the borrower's position can
+/// only get healthier and no health check is needed.
+pub fn process_repay_on_behalf(ctx: Context<RepayOnBehalf>, amount: u64) -> Result<()> {
+    // --- 1. Security Check ---
+    if amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
+
+    // Bring the bank's totals up to date before any share math.
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    accrue_interest(&mut ctx.accounts.bank, now)?;
+    // There is nothing to repay into a bank nobody has borrowed from.
+    require_non_empty(ctx.accounts.bank.total_borrows, ctx.accounts.bank.total_borrow_shares)?;
+
+    // --- 2. Determine the Borrower's Outstanding Debt ---
+    let outstanding_debt = current_debt(&ctx.accounts.user_account, &ctx.accounts.bank, &ctx.accounts.mint.key())?;
+    if outstanding_debt == 0 {
+        return err!(ErrorCode::NothingToRepay);
+    }
+
+    // Cap the repayment at the outstanding debt, so the payer only transfers what the borrower owes.
+    let amount = amount.min(outstanding_debt);
+
+    // --- 3. Transfer Tokens from the Payer to the Bank (CPI) ---
+    // The payer also covers any Token-2022 transfer fee, so the whole repayment reaches the vault.
+    let transfer_amount = gross_up_for_transfer_fee(&ctx.accounts.mint.to_account_info(), amount, clock.epoch)?;
+    transfer_to_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.payer_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.payer.to_account_info(),
+        transfer_amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    // --- 4. Update the Borrower's Debt and the Bank's Totals ---
+    let mint_key = ctx.accounts.mint.key();
+    let bank = &mut ctx.accounts.bank;
+    let user = &mut ctx.accounts.user_account;
+    let shares_repaid = record_repayment(bank, user, &mint_key, amount, outstanding_debt)?;
+    ctx.accounts.protocol_stats.record_repayment(bank, amount, now)?;
+
+    user.last_updated = now;
+
+    emit!(RepayEvent {
+        user: ctx.accounts.owner.key(),
+        mint: mint_key,
+        amount,
+        shares: shares_repaid,
+        total_borrows: bank.total_borrows,
+        total_borrow_shares: bank.total_borrow_shares,
+    });
+
+    msg!(
+        "{} repaid {} on behalf of {}, Shares burned: {}",
+        ctx.accounts.payer.key(),
+        amount,
+        ctx.accounts.owner.key(),
+        shares_repaid
+    );
+    Ok(())
+}
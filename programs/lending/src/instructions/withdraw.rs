@@ -1,16 +1,15 @@
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
-use pyth_solana_receiver_sdk::price_update::{self, get_feed_id_from_hex, PriceUpdateV2};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 use crate::state::*; // Assumes your Bank, User, etc., structs are here
 use crate::error::ErrorCode; // Assumes your custom errors are here
-// Define your mint addresses as constants for security and clarity
-use crate::constants::{
-    SOL_USD_FEED_ID, 
-    USDC_USD_FEED_ID, 
-    SOL_MINT_ADDRESS, 
-    USDC_MINT_ADDRESS
-};
+use crate::token_utils::transfer_from_vault;
+use crate::health::{compute_account_health, load_banks, load_price_updates, Prices};
+use crate::interest::accrue_interest;
+use crate::math::{amount_for_shares, require_non_empty, shares_for_amount_ceil};
+use crate::events::WithdrawEvent;
+use crate::token_extensions::gross_up_for_transfer_fee;
 
 
 //================================================================
@@ -22,24 +21,39 @@ pub struct Withdraw<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
+    /// The global protocol config, checked for the pause switch and read for the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the withdrawal.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
     /// The mint of the asset the user wants TO WITHDRAW.
-    #[account(mut)]
+    #[account(constraint = *mint_to_withdraw.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch)]
     pub mint_to_withdraw: InterfaceAccount<'info, Mint>,
 
     /// The bank's state account for the asset being withdrawn. Required to calculate
     /// the correct token amount from the user's shares.
     #[account(
-        mut, 
-        seeds = [mint_to_withdraw.key().as_ref()], 
-        bump
+        mut,
+        seeds = [mint_to_withdraw.key().as_ref()],
+        bump,
+        constraint = bank.decimals == mint_to_withdraw.decimals @ ErrorCode::DecimalsMismatch,
     )]
     pub bank: Account<'info, Bank>,
 
+    /// The bank of another asset the user holds a position in. Its liquidation threshold is used
+    /// to weight the remaining collateral in that asset during the health check.
+    #[account(mut, constraint = other_bank.key() != bank.key() @ ErrorCode::UnsupportedAsset)]
+    pub other_bank: Account<'info, Bank>,
+
     /// The bank's vault (PDA) from which the user's tokens will be paid out.
     #[account(
         mut,
         seeds = [b"treasury", mint_to_withdraw.key().as_ref()],
-        bump
+        bump,
+        constraint = *bank_token_account.to_account_info().owner == token_program.key() @ ErrorCode::TokenProgramMismatch,
     )]
     pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
@@ -53,17 +67,21 @@ pub struct Withdraw<'info> {
     pub user_account: Account<'info, User>,
 
     /// The user's token account (ATA) where the withdrawn tokens will be sent.
-    /// Anchor will create it if it doesn't exist, with the user paying the rent.
+    /// Anchor will create it if it doesn't exist, with the user paying the rent. An existing one must be
+    /// the signer's with no delegate, so nobody else can spend what is paid into it.
     #[account(
         init_if_needed,
         payer = signer,
         associated_token::mint = mint_to_withdraw,
         associated_token::authority = signer,
+        associated_token::token_program = token_program,
+        constraint = user_token_account.owner == signer.key() && user_token_account.delegate.is_none() @ ErrorCode::UnexpectedTokenAccountState,
     )]
     pub user_token_account: InterfaceAccount<'info, TokenAccount>,
 
     /// The Pyth price feed account. This is ESSENTIAL to value all assets
     /// in the user's portfolio for the health check.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
     pub price_update: Account<'info, PriceUpdateV2>,
     
     // Standard required programs
@@ -72,27 +90,147 @@ pub struct Withdraw<'info> {
     pub system_program: Program<'info, System>,
 }
 
+/// How much a withdrawal redeems: an exact number of deposit shares, the shares backing a token amount,
+/// or every share the user holds in the asset.
+#[derive(Clone, Copy)]
+pub(crate) enum WithdrawSize {
+    Shares(u64),
+    Amount(u64),
+    All,
+}
+
 //================================================================
 // Instruction Logic for Processing a Withdrawal
 //================================================================
 pub fn process_withdraw(ctx: Context<Withdraw>, shares_to_withdraw: u64) -> Result<()> {
-    // --- 1. Initial Sanity and Ownership Checks ---
-    if shares_to_withdraw == 0 {
+    withdraw(ctx, WithdrawSize::Shares(shares_to_withdraw))
+}
+
+//================================================================
+// Instruction Logic for Processing a Withdrawal by Token Amount
+//================================================================
+pub fn process_withdraw_amount(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+    withdraw(ctx, WithdrawSize::Amount(amount))
+}
+
+//================================================================
+// Instruction Logic for Withdrawing an Entire Position
+//================================================================
+/// Redeems every deposit share the user holds in `mint_to_withdraw`, so exiting an asset doesn't need
+/// the share count looked up first.
+pub fn process_withdraw_all(ctx: Context<Withdraw>) -> Result<()> {
+    withdraw(ctx, WithdrawSize::All)
+}
+
+fn withdraw(ctx: Context<Withdraw>, size: WithdrawSize) -> Result<()> {
+    // --- 1. Initial Sanity Checks ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+
+    // Bring the bank's totals up to date before any share math. The other bank is accrued too, so the
+    // health check values debt there with the interest owed since it was last touched.
+    let clock = Clock::get()?;
+    accrue_interest(&mut ctx.accounts.bank, clock.unix_timestamp)?;
+    accrue_interest(&mut ctx.accounts.other_bank, clock.unix_timestamp)?;
+
+    // --- 2. Size the Withdrawal and Check the Position Stays Healthy ---
+    let (gross_amount, shares_to_burn, prices) = plan_withdrawal(
+        &ctx.accounts.config,
+        &ctx.accounts.bank,
+        &ctx.accounts.other_bank,
+        &ctx.accounts.user_account,
+        &ctx.accounts.mint_to_withdraw.to_account_info(),
+        &ctx.accounts.price_update,
+        ctx.remaining_accounts,
+        &clock,
+        size,
+    )?;
+
+    // --- 3. Execute Token Transfer (CPI) ---
+    // This code only runs if the health check above has passed.
+    msg!("Health check passed. Proceeding with transfer.");
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint_to_withdraw.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        ctx.bumps.bank_token_account,
+        gross_amount,
+        ctx.accounts.mint_to_withdraw.decimals,
+    )?;
+
+    // --- 4. Update State (Correct Accounting) ---
+    // If the transfer succeeds, we update our records to reflect the withdrawal.
+    record_withdrawal(
+        &mut ctx.accounts.bank,
+        &mut ctx.accounts.user_account,
+        &ctx.accounts.mint_to_withdraw.key(),
+        gross_amount,
+        shares_to_burn,
+    )?;
+    prices.record(&mut ctx.accounts.bank, clock.unix_timestamp);
+    prices.record(&mut ctx.accounts.other_bank, clock.unix_timestamp);
+    ctx.accounts.protocol_stats.record_withdrawal(&ctx.accounts.bank, gross_amount, clock.unix_timestamp)?;
+
+    emit!(WithdrawEvent {
+        user: ctx.accounts.signer.key(),
+        mint: ctx.accounts.mint_to_withdraw.key(),
+        amount: gross_amount,
+        shares: shares_to_burn,
+        total_deposits: ctx.accounts.bank.total_deposits,
+        total_deposit_shares: ctx.accounts.bank.total_deposit_shares,
+    });
+
+    msg!("Withdrawal successful. Amount: {}, Shares redeemed: {}", gross_amount, shares_to_burn);
+    Ok(())
+}
+
+/// Works out how many tokens leave the vault and how many of the user's shares are burned for a
+/// withdrawal of `size` from `bank`, and rejects it if it would leave the position unhealthy.
+/// Returns `(gross_amount, shares_to_burn, prices)`, where `prices` are what the health check used, so
+/// the caller can record them for the price-deviation breaker. `bank` must already be accrued to now.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn plan_withdrawal(
+    config: &Config,
+    bank: &Bank,
+    other_bank: &Bank,
+    user: &User,
+    mint: &AccountInfo,
+    price_update: &PriceUpdateV2,
+    remaining_accounts: &[AccountInfo],
+    clock: &Clock,
+    size: WithdrawSize,
+) -> Result<(u64, u64, Prices)> {
+    let requested = match size {
+        WithdrawSize::Shares(requested) | WithdrawSize::Amount(requested) => requested,
+        WithdrawSize::All => user.position(mint.key).map_or(0, |position| position.deposited_shares),
+    };
+    if requested == 0 {
         return err!(ErrorCode::ZeroAmount);
     }
+    // Nothing can be redeemed from a bank nobody has deposited into.
+    require_non_empty(bank.total_deposits, bank.total_deposit_shares)?;
 
-    let user = &ctx.accounts.user_account;
-    let bank = &ctx.accounts.bank;
+    // A deposit has to stay put for the bank's cooldown, so it can't be used to pass a health check
+    // and pulled straight back out.
+    if bank.deposit_cooldown_seconds > 0 {
+        let last_deposit_ts = user.position(mint.key).map_or(0, |position| position.last_deposit_ts);
+        let unlocks_at = last_deposit_ts
+            .checked_add(i64::try_from(bank.deposit_cooldown_seconds).map_err(|_| ErrorCode::MathOverflow)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(clock.unix_timestamp >= unlocks_at, ErrorCode::WithdrawCooldownActive);
+    }
+
+    // A token amount is converted into the shares backing it, rounding up so the user
+    // can never redeem more than their shares are worth.
+    // Formula: shares = ceil(amount * total_shares_in_bank / total_tokens_in_bank)
+    let shares_to_withdraw = match size {
+        WithdrawSize::Shares(shares) => shares,
+        WithdrawSize::Amount(amount) => shares_for_amount_ceil(amount, bank.total_deposits, bank.total_deposit_shares)?,
+        WithdrawSize::All => requested,
+    };
 
     // Determine which of the user's deposits we are targeting based on the mint.
-    let (user_deposited_shares, user_deposited_amount) = 
-        match ctx.accounts.mint_to_withdraw.key() {
-            key if key == USDC_MINT_ADDRESS.parse().unwrap() => 
-                (user.deposited_usdc_shares, user.deposited_usdc),
-            key if key == SOL_MINT_ADDRESS.parse().unwrap() => 
-                (user.deposited_sol_shares, user.deposited_sol),
-            _ => return err!(ErrorCode::UnsupportedAsset),
-        };
+    let user_deposited_shares = user.position(mint.key).map_or(0, |position| position.deposited_shares);
 
     // Check if the user actually owns enough shares to withdraw.
     if shares_to_withdraw > user_deposited_shares {
@@ -100,20 +238,35 @@ pub fn process_withdraw(ctx: Context<Withdraw>, shares_to_withdraw: u64) -> Resu
         return err!(ErrorCode::InsufficientShares);
     }
     
-    // --- 2. Calculate Token Amount to Withdraw ---
+    // --- Calculate Token Amount to Withdraw ---
     // The user specifies shares, and the protocol calculates the token amount.
     // This is safer than the reverse as it prevents rounding exploits against the protocol.
-    // Formula: amount = (shares_to_withdraw * total_tokens_in_bank) / total_shares_in_bank
-    let amount_to_withdraw = (shares_to_withdraw as u128)
-        .checked_mul(bank.total_deposits as u128).ok_or(ErrorCode::MathOverflow)?
-        .checked_div(bank.total_deposit_shares as u128).ok_or(ErrorCode::MathOverflow)? as u64;
-
-    // Another sanity check. The calculated amount should not exceed what the user's account says they have.
-    if amount_to_withdraw > user_deposited_amount {
-        return err!(ErrorCode::InsufficientFunds);
+    let amount_to_withdraw = amount_for_shares(shares_to_withdraw, bank.total_deposits, bank.total_deposit_shares)?;
+
+    // If the mint charges a Token-2022 transfer fee, send enough extra that the user still nets
+    // `amount_to_withdraw`, and burn the shares backing that extra as well. Rounding the shares up
+    // keeps the fee on the user rather than on the other depositors. Redeeming every share leaves
+    // nothing extra to burn, so there the fee comes out of what the user receives.
+    let gross_amount = match size {
+        WithdrawSize::All => amount_to_withdraw,
+        _ => gross_up_for_transfer_fee(mint, amount_to_withdraw, clock.epoch)?,
+    };
+    let shares_to_burn = if gross_amount == amount_to_withdraw {
+        shares_to_withdraw
+    } else {
+        shares_for_amount_ceil(gross_amount, bank.total_deposits, bank.total_deposit_shares)?
+    };
+    if shares_to_burn > user_deposited_shares {
+        return err!(ErrorCode::InsufficientShares);
+    }
+
+    // An asset that doesn't count as collateral isn't propping up the position, so withdrawing it can't
+    // make the position any less healthy.
+    if !bank.usable_as_collateral {
+        return Ok((gross_amount, shares_to_burn, Prices::default()));
     }
 
-    // --- 3. THE CRITICAL HEALTH CHECK ---
+    // --- THE CRITICAL HEALTH CHECK ---
     // This is the most important security check. We must simulate the withdrawal
     // and verify that the user's remaining collateral is sufficient to cover their
     // outstanding debt. We must prevent a user from withdrawing collateral that
@@ -121,92 +274,62 @@ pub fn process_withdraw(ctx: Context<Withdraw>, shares_to_withdraw: u64) -> Resu
     msg!("Performing health check before allowing withdrawal...");
     
     // A. Get current prices for ALL assets in the user's portfolio (both collateral and debt).
-    let clock = Clock::get()?;
-    let price_update = &ctx.accounts.price_update;
-    let sol_price = price_update.get_price_no_older_than(&clock, 60, &get_feed_id_from_hex(SOL_USD_FEED_ID)?)?;
-    let usdc_price = price_update.get_price_no_older_than(&clock, 60, &get_feed_id_from_hex(USDC_USD_FEED_ID)?)?;
-
-    // B. Calculate the total USD value of all of the user's DEBTS.
-    let total_debt_value = (sol_price.price as u128 * user.borrowed_sol as u128)
-        .checked_add(usdc_price.price as u128 * user.borrowed_usdc as u128)
-        .ok_or(ErrorCode::MathOverflow)?;
-
-    // C. If the user has debt, we must perform the health check.
-    if total_debt_value > 0 {
-        // D. SIMULATE the new collateral state *after* the withdrawal.
-        let (simulated_sol_collateral, simulated_usdc_collateral) = match ctx.accounts.mint_to_withdraw.key() {
-            key if key == USDC_MINT_ADDRESS.parse().unwrap() => 
-                (user.deposited_sol, user_deposited_amount - amount_to_withdraw),
-            key if key == SOL_MINT_ADDRESS.parse().unwrap() => 
-                (user_deposited_amount - amount_to_withdraw, user.deposited_usdc),
-            _ => return err!(ErrorCode::UnsupportedAsset), // Should be unreachable
-        };
-
-        // E. Calculate the total USD value of the user's collateral AFTER the withdrawal.
-        let simulated_total_collateral_value = (sol_price.price as u128 * simulated_sol_collateral as u128)
-            .checked_add(usdc_price.price as u128 * simulated_usdc_collateral as u128)
-            .ok_or(ErrorCode::MathOverflow)?;
-        
-        // F. Apply the liquidation threshold to the simulated collateral value.
-        // This tells us the maximum debt value this collateral can support before being liquidatable.
-        // We assume a single liquidation_threshold for simplicity. A real protocol might have per-asset thresholds.
-        let simulated_weighted_collateral = simulated_total_collateral_value
-            .checked_mul(bank.liquidation_threshold as u128).ok_or(ErrorCode::MathOverflow)?
-            .checked_div(100).ok_or(ErrorCode::MathOverflow)?; // For percentage
-        
-        // G. THE FINAL VERDICT: Is the remaining collateral value sufficient to cover the debt?
-        // If this check fails, the transaction is reverted, protecting the protocol.
-        if simulated_weighted_collateral < total_debt_value {
-            msg!("Withdrawal rejected: would leave position unhealthy and open to liquidation.");
-            msg!("Simulated Collateral Value: {}, Debt Value: {}", simulated_weighted_collateral, total_debt_value);
-            return err!(ErrorCode::PositionUnhealthy);
-        }
-    }
-    
-    // --- 4. Execute Token Transfer (CPI) ---
-    // This code only runs if the health check above has passed.
-    msg!("Health check passed. Proceeding with transfer.");
-    let signer_seeds: &[&[&[u8]]] = &[&[
-        b"treasury", 
-        ctx.accounts.mint_to_withdraw.to_account_info().key.as_ref(), 
-        &[ctx.bumps.bank_token_account]
-    ]];
-    
-    let cpi_accounts = TransferChecked {
-        from: ctx.accounts.bank_token_account.to_account_info(),
-        mint: ctx.accounts.mint_to_withdraw.to_account_info(),
-        to: ctx.accounts.user_token_account.to_account_info(),
-        authority: ctx.accounts.bank_token_account.to_account_info(), // The PDA is the authority
-    };
-    
-    token_interface::transfer_checked(
-        CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts)
-            .with_signer(signer_seeds), 
-        amount_to_withdraw, 
-        ctx.accounts.mint_to_withdraw.decimals
-    )?;
+    // Positions in assets beyond the two named banks are priced with the banks in `remaining_accounts`.
+    let extra_banks = load_banks(remaining_accounts, clock.unix_timestamp)?;
+    let mut banks: Vec<&Bank> = vec![bank, other_bank];
+    banks.extend(extra_banks.iter());
+    let extra_price_updates = load_price_updates(remaining_accounts)?;
+    let mut price_updates: Vec<&PriceUpdateV2> = vec![price_update];
+    price_updates.extend(extra_price_updates.iter());
+    let max_price_age = bank.max_price_age_seconds.min(other_bank.max_price_age_seconds);
+    let prices = Prices::load(&price_updates, remaining_accounts, clock, max_price_age, &banks, &config.quote_feed_id)?;
+    // Collateral can't be pulled out against a price that jumped too far since the last one accepted.
+    prices.check_deviation(&banks, clock.unix_timestamp)?;
 
-    // --- 5. Update State (Correct Accounting) ---
-    // If the transfer succeeds, we update our records to reflect the withdrawal.
-    let bank_mut = &mut ctx.accounts.bank;
-    let user_mut = &mut ctx.accounts.user_account;
-    
-    bank_mut.total_deposits = bank_mut.total_deposits.checked_sub(amount_to_withdraw).ok_or(ErrorCode::MathOverflow)?;
-    bank_mut.total_deposit_shares = bank_mut.total_deposit_shares.checked_sub(shares_to_withdraw).ok_or(ErrorCode::MathOverflow)?;
-    
-    match ctx.accounts.mint_to_withdraw.key() {
-        key if key == USDC_MINT_ADDRESS.parse().unwrap() => {
-            user_mut.deposited_usdc = user_mut.deposited_usdc.checked_sub(amount_to_withdraw).ok_or(ErrorCode::MathOverflow)?;
-            user_mut.deposited_usdc_shares = user_mut.deposited_usdc_shares.checked_sub(shares_to_withdraw).ok_or(ErrorCode::MathOverflow)?;
-        }
-        key if key == SOL_MINT_ADDRESS.parse().unwrap() => {
-            user_mut.deposited_sol = user_mut.deposited_sol.checked_sub(amount_to_withdraw).ok_or(ErrorCode::MathOverflow)?;
-            user_mut.deposited_sol_shares = user_mut.deposited_sol_shares.checked_sub(shares_to_withdraw).ok_or(ErrorCode::MathOverflow)?;
+    // B. SIMULATE the user's portfolio *after* the withdrawal.
+    let mut simulated_user = user.clone();
+    let simulated_position = simulated_user.position_mut(mint.key)?;
+    simulated_position.deposited_shares = user_deposited_shares - shares_to_burn;
+
+    // C. Value the simulated portfolio. The weighted collateral value tells us the maximum
+    // debt value the remaining collateral can support before being liquidatable.
+    let health = compute_account_health(&simulated_user, &prices, &banks)?;
+    msg!("Simulated Total Collateral Value: {}", health.total_collateral_value);
+
+    // D. THE FINAL VERDICT: Is the remaining collateral value sufficient to cover the debt?
+    // If this check fails, the transaction is reverted, protecting the protocol.
+    if !health.is_healthy() {
+        msg!("Withdrawal rejected: would leave position unhealthy and open to liquidation.");
+        msg!("Simulated Collateral Value: {}, Debt Value: {}", health.weighted_collateral_value, health.total_debt_value);
+        // Emptying a position can only fail here because of debt it still backs.
+        if matches!(size, WithdrawSize::All) {
+            return err!(ErrorCode::OutstandingDebt);
         }
-        _ => return err!(ErrorCode::UnsupportedAsset), // Should be unreachable
+        return err!(ErrorCode::PositionUnhealthy);
     }
 
-    msg!("Withdrawal successful. Amount: {}, Shares redeemed: {}", amount_to_withdraw, shares_to_withdraw);
-    Ok(())
+    Ok((gross_amount, shares_to_burn, prices))
 }
 
+/// Removes a paid-out withdrawal from the bank's totals and the user's position in `mint`.
+pub(crate) fn record_withdrawal(
+    bank: &mut Bank,
+    user: &mut User,
+    mint: &Pubkey,
+    gross_amount: u64,
+    shares_to_burn: u64,
+) -> Result<()> {
+    bank.total_deposits = bank.total_deposits.checked_sub(gross_amount).ok_or(ErrorCode::MathOverflow)?;
+    bank.total_deposit_shares = bank.total_deposit_shares.checked_sub(shares_to_burn).ok_or(ErrorCode::MathOverflow)?;
+    
+    let position = user.position_mut(mint)?;
+    position.deposited_shares = position.deposited_shares.checked_sub(shares_to_burn).ok_or(ErrorCode::MathOverflow)?;
+    // Shares carry the interest earned, or the loss from a write-off, so a withdrawal can pay out more or
+    // less than the principal on record. Redeeming the last share clears it either way.
+    position.deposited = if position.deposited_shares == 0 {
+        0
+    } else {
+        position.deposited.saturating_sub(gross_amount)
+    };
+    Ok(())
+}
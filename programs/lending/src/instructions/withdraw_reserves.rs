@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::transfer_from_vault;
+use crate::interest::accrue_interest;
+
+//================================================================
+// Accounts Struct for the WithdrawReserves Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct WithdrawReserves<'info> {
+    /// The protocol admin, the only account allowed to collect the protocol's reserves.
+    pub signer: Signer<'info>,
+
+    /// The global protocol config, whose admin the signer must be.
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.admin == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub config: Account<'info, Config>,
+
+    /// The mint of the asset whose reserves are being withdrawn.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The bank whose accrued reserves are being paid out.
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+        constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    /// The bank's vault (PDA) from which the reserves are paid out.
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Any token account for this mint chosen by the admin to receive the reserves.
+    #[account(mut, token::mint = mint)]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Reserve Withdrawal
+//================================================================
+pub fn process_withdraw_reserves(ctx: Context<WithdrawReserves>, amount: u64) -> Result<()> {
+    if amount == 0 {
+        return err!(ErrorCode::ZeroAmount);
+    }
+
+    // Bring reserves up to date so the admin can collect everything accrued so far.
+    let now = Clock::get()?.unix_timestamp;
+    accrue_interest(&mut ctx.accounts.bank, now)?;
+    require!(amount <= ctx.accounts.bank.total_reserves, ErrorCode::InsufficientReserves);
+
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.destination.to_account_info(),
+        ctx.bumps.bank_token_account,
+        amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    let bank = &mut ctx.accounts.bank;
+    bank.total_reserves = bank.total_reserves.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+
+    msg!("Withdrew {} of reserves. Remaining: {}", amount, bank.total_reserves);
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, CloseAccount, Token};
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+use crate::state::*;
+use crate::error::ErrorCode;
+use crate::token_utils::transfer_from_vault;
+use crate::constants::SOL_MINT_ADDRESS;
+use crate::interest::accrue_interest;
+use crate::events::WithdrawEvent;
+use super::withdraw::{plan_withdrawal, record_withdrawal, WithdrawSize};
+
+//================================================================
+// Accounts Struct for the WithdrawSol Instruction
+//================================================================
+#[derive(Accounts)]
+pub struct WithdrawSol<'info> {
+    /// The user withdrawing SOL collateral. They receive it as native lamports.
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The global protocol config, checked for the pause switch and read for the quote asset.
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    /// Protocol-wide totals, updated with the withdrawal.
+    #[account(mut, seeds = [b"stats"], bump)]
+    pub protocol_stats: Account<'info, ProtocolStats>,
+
+    /// The wrapped SOL mint. Only the SOL bank pays out native SOL.
+    #[account(address = SOL_MINT_ADDRESS @ ErrorCode::UnsupportedAsset)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The SOL bank's state account.
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+        constraint = bank.decimals == mint.decimals @ ErrorCode::DecimalsMismatch,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    /// The bank of another asset the user holds a position in, used in the health check.
+    #[account(mut, constraint = other_bank.key() != bank.key() @ ErrorCode::UnsupportedAsset)]
+    pub other_bank: Account<'info, Bank>,
+
+    /// The SOL bank's vault, from which the wrapped SOL is paid out.
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's state account, which tracks their deposits and shares.
+    #[account(
+        mut,
+        seeds = [signer.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+
+    /// A wrapped SOL account that only lives for this instruction. The withdrawal lands here and the
+    /// account is then closed to the signer, which unwraps it. It is its own authority so that the
+    /// program, not the user, closes it.
+    #[account(
+        init,
+        payer = signer,
+        seeds = [b"unwrap", signer.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = unwrap_account,
+        token::token_program = token_program,
+    )]
+    pub unwrap_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The Pyth price feed account, used to value the user's portfolio in the health check.
+    /// Updates for any other feed the instruction needs are passed in `remaining_accounts`.
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    /// Wrapped SOL only exists under the original SPL Token program.
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+//================================================================
+// Instruction Logic for Processing a Native SOL Withdrawal
+//================================================================
+pub fn process_withdraw_sol(ctx: Context<WithdrawSol>, shares_to_withdraw: u64) -> Result<()> {
+    // --- 1. Initial Sanity Checks ---
+    require!(!ctx.accounts.config.paused, ErrorCode::ProtocolPaused);
+
+    let clock = Clock::get()?;
+    accrue_interest(&mut ctx.accounts.bank, clock.unix_timestamp)?;
+    accrue_interest(&mut ctx.accounts.other_bank, clock.unix_timestamp)?;
+
+    // --- 2. Size the Withdrawal and Check the Position Stays Healthy ---
+    let (gross_amount, shares_to_burn, prices) = plan_withdrawal(
+        &ctx.accounts.config,
+        &ctx.accounts.bank,
+        &ctx.accounts.other_bank,
+        &ctx.accounts.user_account,
+        &ctx.accounts.mint.to_account_info(),
+        &ctx.accounts.price_update,
+        ctx.remaining_accounts,
+        &clock,
+        WithdrawSize::Shares(shares_to_withdraw),
+    )?;
+
+    // --- 3. Pay Out Into the Temporary Wrapped SOL Account ---
+    let mint_key = ctx.accounts.mint.key();
+    transfer_from_vault(
+        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.unwrap_account.to_account_info(),
+        ctx.bumps.bank_token_account,
+        gross_amount,
+        ctx.accounts.mint.decimals,
+    )?;
+
+    // --- 4. Unwrap by Closing It to the Signer ---
+    // Closing a wrapped SOL account sends all of its lamports, the withdrawal plus the rent the signer
+    // just paid, back to the signer as native SOL.
+    let signer_key = ctx.accounts.signer.key();
+    let unwrap_seeds: &[&[&[u8]]] = &[&[b"unwrap", signer_key.as_ref(), &[ctx.bumps.unwrap_account]]];
+    token::close_account(CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.unwrap_account.to_account_info(),
+            destination: ctx.accounts.signer.to_account_info(),
+            authority: ctx.accounts.unwrap_account.to_account_info(),
+        },
+        unwrap_seeds,
+    ))?;
+
+    // --- 5. Update State ---
+    record_withdrawal(
+        &mut ctx.accounts.bank,
+        &mut ctx.accounts.user_account,
+        &mint_key,
+        gross_amount,
+        shares_to_burn,
+    )?;
+    prices.record(&mut ctx.accounts.bank, clock.unix_timestamp);
+    prices.record(&mut ctx.accounts.other_bank, clock.unix_timestamp);
+    ctx.accounts.protocol_stats.record_withdrawal(&ctx.accounts.bank, gross_amount, clock.unix_timestamp)?;
+
+    emit!(WithdrawEvent {
+        user: signer_key,
+        mint: mint_key,
+        amount: gross_amount,
+        shares: shares_to_burn,
+        total_deposits: ctx.accounts.bank.total_deposits,
+        total_deposit_shares: ctx.accounts.bank.total_deposit_shares,
+    });
+
+    msg!("Native SOL withdrawal successful. Amount: {}, Shares redeemed: {}", gross_amount, shares_to_burn);
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use anchor_lang::prelude::*;
+use crate::constants::{BPS_DENOMINATOR, SECONDS_PER_YEAR};
+use crate::error::ErrorCode;
+use crate::state::Bank;
+
+/// Returns the utilization of `bank` (`total_borrows / total_deposits`) in basis points, capped at 100%.
+pub fn utilization(bank: &Bank) -> u64 {
+    if bank.total_deposits == 0 {
+        return 0;
+    }
+    let utilization = (bank.total_borrows as u128) * (BPS_DENOMINATOR as u128) / (bank.total_deposits as u128);
+    utilization.min(BPS_DENOMINATOR as u128) as u64
+}
+
+/// Returns the current borrow APR of `bank` in basis points.
+///
+/// The rate follows a two-slope curve: it rises gently by `slope1` up to `optimal_utilization`, then
+/// steeply by `slope2` above it, pushing borrowers to repay before the bank runs out of liquidity.
+/// The result never exceeds `max_borrow_rate` when one is set, so a misconfigured slope cannot
+/// produce an APR that wipes borrowers out.
+pub fn current_borrow_rate(bank: &Bank) -> u64 {
+    let utilization = utilization(bank) as u128;
+    let optimal = (bank.optimal_utilization as u128).min(BPS_DENOMINATOR as u128);
+    let base = bank.base_rate as u128;
+    let slope1 = bank.slope1 as u128;
+    let slope2 = bank.slope2 as u128;
+
+    let rate = if utilization <= optimal {
+        match optimal {
+            0 => base,
+            _ => base + slope1 * utilization / optimal,
+        }
+    } else {
+        let excess = utilization - optimal;
+        let excess_range = BPS_DENOMINATOR as u128 - optimal;
+        base + slope1 + slope2 * excess / excess_range
+    };
+
+    let rate = u64::try_from(rate).unwrap_or(u64::MAX);
+    match bank.max_borrow_rate {
+        0 => rate,
+        max_rate => rate.min(max_rate),
+    }
+}
+
+/// Returns the current supply APR of `bank` in basis points: what lenders earn once interest is spread
+/// over every deposit and the reserve factor is taken out.
+pub fn current_supply_rate(bank: &Bank) -> u64 {
+    // supply_rate = borrow_rate * utilization * (1 - reserve_factor)
+    let rate = (current_borrow_rate(bank) as u128)
+        * (utilization(bank) as u128)
+        * (BPS_DENOMINATOR as u128 - (bank.reserve_factor_bps as u128).min(BPS_DENOMINATOR as u128))
+        / (BPS_DENOMINATOR as u128 * BPS_DENOMINATOR as u128);
+    rate as u64
+}
+
+/// Accrues borrow interest on `bank` for the time elapsed since `last_updated`, once at least the bank's
+/// `accrual_interval_seconds` has passed.
+///
+/// Called many times in the same second, or within the interval, this is a no-op that leaves every
+/// total, `borrow_index` and `last_updated` untouched, so the skipped time is accrued in full by the
+/// next call that gets through. `bank` may therefore lag up to the interval behind `now`.
+pub fn accrue_interest(bank: &mut Bank, now: i64) -> Result<()> {
+    let elapsed = now.saturating_sub(bank.last_updated);
+    if elapsed <= 0 || (elapsed as u64) < bank.accrual_interval_seconds {
+        return Ok(());
+    }
+    force_accrue_interest(bank, now)
+}
+
+/// Accrues borrow interest on `bank` up to `now` regardless of its accrual interval, for changes that
+/// must not apply to time that has already passed, like a new reserve factor.
+///
+/// Interest grows `total_borrows` without minting new borrow shares, and `borrow_index` by the same
+/// factor, so every borrower's debt grows pro rata. `reserve_factor_bps` of it is set aside in
+/// `total_reserves`; the rest is added to `total_deposits`, which raises the value of each deposit share.
+/// The whole period is charged in one step at the current rate, so the cost doesn't grow with how long
+/// the bank sat idle.
+pub fn force_accrue_interest(bank: &mut Bank, now: i64) -> Result<()> {
+    let elapsed = now.saturating_sub(bank.last_updated);
+    if elapsed <= 0 {
+        return Ok(());
+    }
+
+    // interest = total_borrows * apr_bps * elapsed / (10_000 * seconds_per_year)
+    let interest = (bank.total_borrows as u128)
+        .saturating_mul(current_borrow_rate(bank) as u128)
+        .saturating_mul(elapsed as u128)
+        / (BPS_DENOMINATOR as u128 * SECONDS_PER_YEAR as u128);
+
+    // A bank left idle for a very long time could owe more interest than its totals can hold. Cap the
+    // delta at the room left in the fullest of them rather than failing every later instruction.
+    let headroom = u64::MAX - bank.total_borrows.max(bank.total_deposits).max(bank.total_reserves);
+    let interest = interest.min(headroom as u128) as u64;
+    let reserves = (interest as u128 * bank.reserve_factor_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+
+    // index *= (total_borrows + interest) / total_borrows. A bank nobody owes has nothing to compound.
+    if bank.total_borrows > 0 {
+        bank.borrow_index = bank.borrow_index
+            .checked_mul(bank.total_borrows as u128 + interest as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / bank.total_borrows as u128;
+    }
+
+    bank.total_borrows = bank.total_borrows.checked_add(interest).ok_or(ErrorCode::MathOverflow)?;
+    bank.total_reserves = bank.total_reserves.checked_add(reserves).ok_or(ErrorCode::MathOverflow)?;
+    bank.total_deposits = bank.total_deposits.checked_add(interest - reserves).ok_or(ErrorCode::MathOverflow)?;
+    bank.last_updated = now;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::BORROW_INDEX_SCALE;
+
+    fn bank_with_borrows(total_borrows: u64, base_rate: u64) -> Bank {
+        Bank {
+            total_deposits: total_borrows * 2,
+            total_deposit_shares: total_borrows * 2,
+            total_borrows,
+            total_borrow_shares: total_borrows,
+            base_rate,
+            ..Default::default()
+        }
+    }
+
+    fn kinked_bank(total_deposits: u64, total_borrows: u64) -> Bank {
+        Bank {
+            total_deposits,
+            total_borrows,
+            base_rate: 200,              // 2%
+            slope1: 400,                 // +4% up to the kink
+            slope2: 6_000,               // +60% above the kink
+            optimal_utilization: 8_000,  // 80%
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rate_at_zero_utilization_is_base_rate() {
+        assert_eq!(current_borrow_rate(&kinked_bank(1_000_000, 0)), 200);
+        assert_eq!(current_borrow_rate(&kinked_bank(0, 0)), 200);
+    }
+
+    #[test]
+    fn rate_at_optimal_utilization() {
+        assert_eq!(current_borrow_rate(&kinked_bank(1_000_000, 800_000)), 600);
+        // Halfway to the kink only half of slope1 applies.
+        assert_eq!(current_borrow_rate(&kinked_bank(1_000_000, 400_000)), 400);
+    }
+
+    #[test]
+    fn rate_at_full_utilization() {
+        assert_eq!(current_borrow_rate(&kinked_bank(1_000_000, 1_000_000)), 6_600);
+    }
+
+    #[test]
+    fn rate_curve_bends_at_the_kink() {
+        // 10 points of utilization below the kink cost far less than 10 points above it.
+        let below = current_borrow_rate(&kinked_bank(1_000_000, 800_000)) - current_borrow_rate(&kinked_bank(1_000_000, 700_000));
+        let above = current_borrow_rate(&kinked_bank(1_000_000, 900_000)) - current_borrow_rate(&kinked_bank(1_000_000, 800_000));
+        assert_eq!(below, 50);
+        assert_eq!(above, 3_000);
+    }
+
+    #[test]
+    fn accrues_one_year_of_interest() {
+        let mut bank = bank_with_borrows(1_000_000, 1_000); // 10% APR
+
+        accrue_interest(&mut bank, SECONDS_PER_YEAR as i64).unwrap();
+
+        assert_eq!(bank.total_borrows, 1_100_000);
+        assert_eq!(bank.total_borrow_shares, 1_000_000);
+        assert_eq!(bank.total_deposits, 2_100_000);
+        assert_eq!(bank.total_deposit_shares, 2_000_000);
+        assert_eq!(bank.last_updated, SECONDS_PER_YEAR as i64);
+    }
+
+    #[test]
+    fn borrow_index_compounds_with_total_borrows() {
+        let mut bank = bank_with_borrows(1_000_000, 1_000); // 10% APR
+        bank.borrow_index = BORROW_INDEX_SCALE;
+
+        accrue_interest(&mut bank, SECONDS_PER_YEAR as i64).unwrap();
+        assert_eq!(bank.borrow_index, BORROW_INDEX_SCALE * 11 / 10);
+
+        // The second year's interest is charged on the first year's too.
+        accrue_interest(&mut bank, 2 * SECONDS_PER_YEAR as i64).unwrap();
+        assert_eq!(bank.total_borrows, 1_210_000);
+        assert_eq!(bank.borrow_index, BORROW_INDEX_SCALE * 121 / 100);
+    }
+
+    #[test]
+    fn does_nothing_when_no_time_has_passed() {
+        let mut bank = bank_with_borrows(1_000_000, 1_000);
+        bank.last_updated = 100;
+
+        bank.borrow_index = BORROW_INDEX_SCALE;
+        accrue_interest(&mut bank, 100).unwrap();
+
+        assert_eq!(bank.total_borrows, 1_000_000);
+        assert_eq!(bank.borrow_index, BORROW_INDEX_SCALE);
+        assert_eq!(bank.last_updated, 100);
+    }
+
+    #[test]
+    fn skips_accrual_within_the_interval_without_losing_it() {
+        let mut bank = bank_with_borrows(1_000_000, 1_000); // 10% APR
+        bank.borrow_index = BORROW_INDEX_SCALE;
+        bank.accrual_interval_seconds = 60;
+
+        // 59 seconds in nothing moves, the index included.
+        accrue_interest(&mut bank, 59).unwrap();
+        assert_eq!(bank.total_borrows, 1_000_000);
+        assert_eq!(bank.borrow_index, BORROW_INDEX_SCALE);
+        assert_eq!(bank.last_updated, 0);
+
+        // Once a year has passed the skipped seconds are charged along with the rest.
+        accrue_interest(&mut bank, SECONDS_PER_YEAR as i64).unwrap();
+        assert_eq!(bank.total_borrows, 1_100_000);
+        assert_eq!(bank.last_updated, SECONDS_PER_YEAR as i64);
+
+        // Forcing an accrual ignores the interval.
+        force_accrue_interest(&mut bank, SECONDS_PER_YEAR as i64 + 1).unwrap();
+        assert_eq!(bank.last_updated, SECONDS_PER_YEAR as i64 + 1);
+    }
+
+    #[test]
+    fn reserves_take_their_share_of_interest() {
+        let mut bank = bank_with_borrows(1_000_000, 1_000); // 10% APR
+        bank.reserve_factor_bps = 2_000; // 20%
+
+        accrue_interest(&mut bank, SECONDS_PER_YEAR as i64).unwrap();
+
+        // Borrowers owe the full 100_000 of interest...
+        assert_eq!(bank.total_borrows, 1_100_000);
+        // ...of which 20% goes to reserves and the remaining 80% to lenders.
+        assert_eq!(bank.total_reserves, 20_000);
+        assert_eq!(bank.total_deposits, 2_080_000);
+        assert_eq!(bank.total_deposit_shares, 2_000_000);
+    }
+
+    #[test]
+    fn reserves_grow_in_proportion_to_the_reserve_factor() {
+        let mut low = bank_with_borrows(1_000_000, 1_000);
+        low.reserve_factor_bps = 1_000;
+        let mut high = bank_with_borrows(1_000_000, 1_000);
+        high.reserve_factor_bps = 3_000;
+
+        accrue_interest(&mut low, SECONDS_PER_YEAR as i64).unwrap();
+        accrue_interest(&mut high, SECONDS_PER_YEAR as i64).unwrap();
+
+        assert_eq!(high.total_reserves, low.total_reserves * 3);
+    }
+
+    #[test]
+    fn supply_rate_is_borrow_rate_scaled_by_utilization_and_reserves() {
+        let mut bank = kinked_bank(1_000_000, 800_000);
+        // 6% borrow APR at 80% utilization.
+        assert_eq!(current_supply_rate(&bank), 480);
+
+        bank.reserve_factor_bps = 2_500;
+        assert_eq!(current_supply_rate(&bank), 360);
+        assert_eq!(current_supply_rate(&kinked_bank(1_000_000, 0)), 0);
+    }
+
+    #[test]
+    fn rate_is_clamped_to_the_max_borrow_rate() {
+        let mut bank = kinked_bank(1_000_000, 1_000_000);
+        bank.max_borrow_rate = 6_600;
+        assert_eq!(current_borrow_rate(&bank), 6_600);
+
+        bank.max_borrow_rate = 6_599;
+        assert_eq!(current_borrow_rate(&bank), 6_599);
+        // Below the cap the curve is unchanged.
+        assert_eq!(current_borrow_rate(&kinked_bank(1_000_000, 800_000)), 600);
+    }
+
+    #[test]
+    fn zero_max_borrow_rate_means_uncapped() {
+        let mut bank = kinked_bank(1_000_000, 1_000_000);
+        bank.slope2 = u64::MAX;
+        assert_eq!(current_borrow_rate(&bank), u64::MAX);
+    }
+
+    #[test]
+    fn long_idle_periods_cannot_overflow_the_totals() {
+        let mut bank = bank_with_borrows(u64::MAX / 4, u64::MAX);
+        let headroom = u64::MAX - bank.total_deposits;
+
+        accrue_interest(&mut bank, i64::MAX).unwrap();
+
+        // The deposits, the fuller of the two totals, are filled to the brim.
+        assert_eq!(bank.total_deposits, u64::MAX);
+        assert_eq!(bank.total_borrows, u64::MAX / 4 + headroom);
+        assert_eq!(bank.last_updated, i64::MAX);
+    }
+}
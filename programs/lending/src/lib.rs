@@ -1,43 +1,272 @@
 use anchor_lang::prelude::*;
 use instructions::*;
+use state::CollateralPricer;
 
-mod state;
-mod instructions;
-mod error;
-mod constants;
+pub mod state;
+pub mod instructions;
+pub mod error;
+pub mod constants;
+pub mod math;
+pub mod interest;
+pub mod health;
+pub mod oracle;
+pub mod events;
+pub mod token_extensions;
+pub mod token_utils;
 
 declare_id!("CdZeD33fXsAHfZYS8jdxg4qHgXYJwBQ1Bv6GJyETtLST");
 
+// Anchor 0.31's `#[program]` calls the deprecated `AccountInfo::realloc` in its IDL handler.
+#[allow(deprecated)]
 #[program]
 pub mod lending_protocol {
 
     use super::*;
 
-    pub fn init_bank(ctx: Context<InitBank>, liquidation_threshold: u64, max_ltv: u64) -> Result<()> {
-        process_init_bank(ctx, liquidation_threshold, max_ltv)
+    pub fn initialize(ctx: Context<Initialize>, protocol_fee_bps: u16) -> Result<()> {
+        process_initialize(ctx, protocol_fee_bps)
+    }
+
+    pub fn propose_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        process_propose_admin(ctx, new_admin)
+    }
+
+    pub fn cancel_admin_transfer(ctx: Context<UpdateConfig>) -> Result<()> {
+        process_cancel_admin_transfer(ctx)
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        process_accept_admin(ctx)
+    }
+
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        process_set_paused(ctx, paused)
+    }
+
+    pub fn set_fee_recipient(ctx: Context<UpdateConfig>, fee_recipient: Pubkey) -> Result<()> {
+        process_set_fee_recipient(ctx, fee_recipient)
+    }
+
+    pub fn set_min_liquidation_incentive(ctx: Context<UpdateConfig>, min_liquidation_incentive_usd: u128) -> Result<()> {
+        process_set_min_liquidation_incentive(ctx, min_liquidation_incentive_usd)
+    }
+
+    pub fn set_auction_mode(ctx: Context<UpdateConfig>, auction_mode: bool, auction_duration_seconds: u64) -> Result<()> {
+        process_set_auction_mode(ctx, auction_mode, auction_duration_seconds)
+    }
+
+    pub fn set_quote_asset(ctx: Context<UpdateConfig>, quote_feed_id: Option<String>) -> Result<()> {
+        process_set_quote_asset(ctx, quote_feed_id)
+    }
+
+    pub fn set_max_borrowed_assets(ctx: Context<UpdateConfig>, max_borrowed_assets: u8) -> Result<()> {
+        process_set_max_borrowed_assets(ctx, max_borrowed_assets)
+    }
+
+    pub fn init_bank(ctx: Context<InitBank>, feed_id: String, params: InitBankParams) -> Result<()> {
+        process_init_bank(ctx, feed_id, params)
+    }
+
+    pub fn add_supported_asset(ctx: Context<AddSupportedAsset>, feed_id: String, params: InitBankParams) -> Result<()> {
+        process_add_supported_asset(ctx, feed_id, params)
+    }
+
+    pub fn set_max_price_age(ctx: Context<UpdateBank>, max_price_age_seconds: u64) -> Result<()> {
+        process_set_max_price_age(ctx, max_price_age_seconds)
+    }
+
+    pub fn set_risk_params(ctx: Context<UpdateBank>, max_ltv: u64, liquidation_threshold: u64) -> Result<()> {
+        process_set_risk_params(ctx, max_ltv, liquidation_threshold)
+    }
+
+    pub fn set_caps(ctx: Context<UpdateBank>, supply_cap: u64, borrow_cap: u64) -> Result<()> {
+        process_set_caps(ctx, supply_cap, borrow_cap)
+    }
+
+    pub fn set_accrual_interval(ctx: Context<UpdateBank>, accrual_interval_seconds: u64) -> Result<()> {
+        process_set_accrual_interval(ctx, accrual_interval_seconds)
+    }
+
+    pub fn set_min_borrow_amount(ctx: Context<UpdateBank>, min_borrow_amount: u64) -> Result<()> {
+        process_set_min_borrow_amount(ctx, min_borrow_amount)
+    }
+
+    pub fn set_safety_buffer(ctx: Context<UpdateBank>, safety_buffer_bps: u16) -> Result<()> {
+        process_set_safety_buffer(ctx, safety_buffer_bps)
+    }
+
+    pub fn set_collateral_pricer(ctx: Context<UpdateBank>, collateral_pricer: CollateralPricer) -> Result<()> {
+        process_set_collateral_pricer(ctx, collateral_pricer)
+    }
+
+    pub fn set_reserve_factor(ctx: Context<UpdateBank>, reserve_factor_bps: u16) -> Result<()> {
+        process_set_reserve_factor(ctx, reserve_factor_bps)
+    }
+
+    pub fn withdraw_reserves(ctx: Context<WithdrawReserves>, amount: u64) -> Result<()> {
+        process_withdraw_reserves(ctx, amount)
+    }
+
+    pub fn collect_fees(ctx: Context<CollectFees>) -> Result<()> {
+        process_collect_fees(ctx)
+    }
+
+    pub fn emergency_withdraw(ctx: Context<EmergencyWithdraw>, amount: u64) -> Result<()> {
+        process_emergency_withdraw(ctx, amount)
+    }
+
+    pub fn set_flash_fee(ctx: Context<UpdateBank>, flash_fee_bps: u16) -> Result<()> {
+        process_set_flash_fee(ctx, flash_fee_bps)
+    }
+
+    pub fn set_deposit_cooldown(ctx: Context<UpdateBank>, deposit_cooldown_seconds: u64) -> Result<()> {
+        process_set_deposit_cooldown(ctx, deposit_cooldown_seconds)
+    }
+
+    pub fn set_origination_fee(ctx: Context<UpdateBank>, origination_fee_bps: u16) -> Result<()> {
+        process_set_origination_fee(ctx, origination_fee_bps)
+    }
+
+    pub fn set_secondary_feed(ctx: Context<UpdateBank>, secondary_feed: Pubkey, max_oracle_deviation_bps: u16) -> Result<()> {
+        process_set_secondary_feed(ctx, secondary_feed, max_oracle_deviation_bps)
+    }
+
+    pub fn set_price_deviation_limit(ctx: Context<UpdateBank>, max_price_deviation_bps: u16, window_seconds: u64) -> Result<()> {
+        process_set_price_deviation_limit(ctx, max_price_deviation_bps, window_seconds)
+    }
+
+    pub fn set_dynamic_liquidation_bonus(
+        ctx: Context<UpdateBank>,
+        dynamic_bonus: bool,
+        min_liquidation_bonus: u64,
+        max_liquidation_bonus: u64,
+    ) -> Result<()> {
+        process_set_dynamic_liquidation_bonus(ctx, dynamic_bonus, min_liquidation_bonus, max_liquidation_bonus)
+    }
+
+    pub fn set_borrowing_enabled(ctx: Context<UpdateBank>, borrowing_enabled: bool) -> Result<()> {
+        process_set_borrowing_enabled(ctx, borrowing_enabled)
+    }
+
+    pub fn set_usable_as_collateral(ctx: Context<UpdateBank>, usable_as_collateral: bool) -> Result<()> {
+        process_set_usable_as_collateral(ctx, usable_as_collateral)
+    }
+
+    pub fn set_peg_deviation(ctx: Context<UpdateBank>, peg_deviation_bps: u16) -> Result<()> {
+        process_set_peg_deviation(ctx, peg_deviation_bps)
+    }
+
+    pub fn set_max_liquidation_value(ctx: Context<UpdateBank>, max_liquidation_value_usd: u128) -> Result<()> {
+        process_set_max_liquidation_value(ctx, max_liquidation_value_usd)
     }
 
     pub fn init_user(ctx: Context<InitUser>, usdc_address: Pubkey) -> Result<()> {
         process_init_user(ctx, usdc_address)
     }
 
+    pub fn close_user(ctx: Context<CloseUser>) -> Result<()> {
+        process_close_user(ctx)
+    }
+
+    pub fn migrate_user(ctx: Context<MigrateUser>) -> Result<()> {
+        process_migrate_user(ctx)
+    }
+
     pub fn deposit (ctx: Context<Deposit>, amount: u64) -> Result<()> {
         process_deposit(ctx, amount)
     }
 
+    pub fn deposit_sol(ctx: Context<DepositSol>, amount: u64) -> Result<()> {
+        process_deposit_sol(ctx, amount)
+    }
+
+    pub fn deposit_multi(ctx: Context<DepositMulti>, amounts: Vec<u64>) -> Result<()> {
+        process_deposit_multi(ctx, amounts)
+    }
+
     pub fn withdraw (ctx: Context<Withdraw>, amount: u64) -> Result<()> {
         process_withdraw(ctx, amount)
     }
 
-    pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
-        process_borrow(ctx, amount)
+    pub fn withdraw_amount(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        process_withdraw_amount(ctx, amount)
+    }
+
+    pub fn withdraw_all(ctx: Context<Withdraw>) -> Result<()> {
+        process_withdraw_all(ctx)
+    }
+
+    pub fn withdraw_sol(ctx: Context<WithdrawSol>, shares: u64) -> Result<()> {
+        process_withdraw_sol(ctx, shares)
+    }
+
+    pub fn borrow(ctx: Context<Borrow>, amount: u64, isolated_mint: Option<Pubkey>) -> Result<()> {
+        process_borrow(ctx, amount, isolated_mint)
     }
 
     pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
         process_repay(ctx, amount)
     }
 
-    pub fn liquidate(ctx: Context<Liquidate>) -> Result<()> {
-        process_liquidate(ctx)
+    pub fn repay_on_behalf(ctx: Context<RepayOnBehalf>, amount: u64) -> Result<()> {
+        process_repay_on_behalf(ctx, amount)
+    }
+
+    pub fn repay_and_withdraw(ctx: Context<RepayAndWithdraw>, repay_amount: u64, withdraw_shares: u64) -> Result<()> {
+        process_repay_and_withdraw(ctx, repay_amount, withdraw_shares)
+    }
+
+    pub fn liquidate(ctx: Context<Liquidate>, repay_amount: u64) -> Result<()> {
+        process_liquidate(ctx, repay_amount)
+    }
+
+    pub fn liquidate_all(ctx: Context<LiquidateAll>) -> Result<()> {
+        process_liquidate_all(ctx)
+    }
+
+    pub fn deposit_and_borrow(
+        ctx: Context<DepositAndBorrow>,
+        deposit_amount: u64,
+        borrow_amount: u64,
+        isolated_mint: Option<Pubkey>,
+    ) -> Result<()> {
+        process_deposit_and_borrow(ctx, deposit_amount, borrow_amount, isolated_mint)
+    }
+
+    pub fn flash_borrow(ctx: Context<FlashBorrow>, amount: u64) -> Result<()> {
+        process_flash_borrow(ctx, amount)
+    }
+
+    pub fn flash_repay(ctx: Context<FlashRepay>) -> Result<()> {
+        process_flash_repay(ctx)
+    }
+
+    pub fn get_health(ctx: Context<GetHealth>) -> Result<HealthView> {
+        process_get_health(ctx)
+    }
+
+    pub fn is_liquidatable(ctx: Context<IsLiquidatable>) -> Result<LiquidationEligibility> {
+        process_is_liquidatable(ctx)
+    }
+
+    pub fn preview_liquidation(ctx: Context<PreviewLiquidation>, repay_amount: u64) -> Result<LiquidationAmounts> {
+        process_preview_liquidation(ctx, repay_amount)
+    }
+
+    pub fn refresh_auction(ctx: Context<RefreshAuction>) -> Result<()> {
+        process_refresh_auction(ctx)
+    }
+
+    pub fn get_max_borrow(ctx: Context<GetMaxBorrow>, mint_to_borrow: Pubkey) -> Result<u64> {
+        process_get_max_borrow(ctx, mint_to_borrow)
+    }
+
+    pub fn get_bank_rates(ctx: Context<GetBankRates>) -> Result<BankRatesView> {
+        process_get_bank_rates(ctx)
+    }
+
+    #[cfg(feature = "debug")]
+    pub fn reconcile_bank(ctx: Context<ReconcileBank>) -> Result<i64> {
+        process_reconcile_bank(ctx)
     }
 }
@@ -0,0 +1,455 @@
+use anchor_lang::prelude::*;
+use crate::error::ErrorCode;
+
+/// Rescales a Pyth price from its native exponent to `target_expo`.
+///
+/// Pyth reports prices as `price * 10^expo` (e.g. SOL at $150 is `15_000_000_000` with expo `-8`).
+/// Normalizing every feed to the same exponent lets values from different feeds be compared and summed.
+/// A malfunctioning feed can report zero or a negative price, which is rejected rather than valuing an
+/// asset at nothing.
+pub fn normalize_price(price: i128, expo: i32, target_expo: i32) -> Result<u128> {
+    require!(price > 0, ErrorCode::InvalidOraclePrice);
+    let price = u128::try_from(price).map_err(|_| ErrorCode::PriceConversionOverflow)?;
+    let shift = target_expo.checked_sub(expo).ok_or(ErrorCode::PriceConversionOverflow)?;
+
+    if shift >= 0 {
+        // The feed is more precise than the target scale, so drop the extra digits.
+        let divisor = 10u128.checked_pow(shift as u32).ok_or(ErrorCode::PriceConversionOverflow)?;
+        Ok(price / divisor)
+    } else {
+        // The feed is less precise than the target scale, so pad with zeros.
+        let multiplier = 10u128.checked_pow(shift.unsigned_abs()).ok_or(ErrorCode::PriceConversionOverflow)?;
+        Ok(price.checked_mul(multiplier).ok_or(ErrorCode::PriceConversionOverflow)?)
+    }
+}
+
+/// Returns the USD value of `amount` native units of a token with `decimals` decimals.
+///
+/// `price` must already be normalized to `USD_EXPONENT`, so the result is on that same fixed USD scale
+/// regardless of how many decimals the token uses (9 for SOL, 6 for USDC). A value too large for a u128
+/// fails with `ValueOverflow`.
+pub fn token_value_usd(price: u128, amount: u64, decimals: u8) -> Result<u128> {
+    let scale = 10u128.checked_pow(decimals as u32).ok_or(ErrorCode::ValueOverflow)?;
+    Ok(price.checked_mul(amount as u128).ok_or(ErrorCode::ValueOverflow)? / scale)
+}
+
+/// Returns how many native units of a token with `decimals` decimals are worth `value` USD.
+///
+/// The inverse of `token_value_usd`: `value` and `price` must both be on the `USD_EXPONENT` scale, so the
+/// feed's own exponent has already been handled by `normalize_price`. Rounds down. A zero price fails
+/// with `DivideByZero`, and an amount that doesn't fit in a u64 with `PriceConversionOverflow`.
+pub fn usd_value_to_native(value: u128, price: u128, decimals: u8) -> Result<u64> {
+    require!(price > 0, ErrorCode::DivideByZero);
+    let scale = 10u128.checked_pow(decimals as u32).ok_or(ErrorCode::PriceConversionOverflow)?;
+    let native = value.checked_mul(scale).ok_or(ErrorCode::PriceConversionOverflow)? / price;
+    Ok(u64::try_from(native).map_err(|_| ErrorCode::PriceConversionOverflow)?)
+}
+
+/// Same as `usd_value_to_native`, but rounds up, for amounts that must be worth at least `value`.
+pub fn usd_value_to_native_ceil(value: u128, price: u128, decimals: u8) -> Result<u64> {
+    require!(price > 0, ErrorCode::DivideByZero);
+    let scale = 10u128.checked_pow(decimals as u32).ok_or(ErrorCode::PriceConversionOverflow)?;
+    let native = value.checked_mul(scale).ok_or(ErrorCode::PriceConversionOverflow)?.div_ceil(price);
+    Ok(u64::try_from(native).map_err(|_| ErrorCode::PriceConversionOverflow)?)
+}
+
+/// Returns what a debt of `principal` taken on at `snapshot_index` has grown to at `current_index`,
+/// rounded up so a borrower always owes at least what the index says.
+pub fn debt_at_index(principal: u64, snapshot_index: u128, current_index: u128) -> Result<u64> {
+    require!(snapshot_index > 0, ErrorCode::DivideByZero);
+    let debt = (principal as u128)
+        .checked_mul(current_index)
+        .ok_or(ErrorCode::MathOverflow)?
+        .div_ceil(snapshot_index);
+    Ok(u64::try_from(debt).map_err(|_| ErrorCode::MathOverflow)?)
+}
+
+/// Sums collateral values, weighting each by its own asset's percentage (e.g. its liquidation threshold).
+///
+/// Takes `(value, percentage)` pairs where percentages are whole numbers (80 = 80%).
+pub fn weighted_sum(weighted_values: &[(u128, u64)]) -> Result<u128> {
+    weighted_values.iter().try_fold(0u128, |sum, &(value, percentage)| {
+        let weighted = value
+            .checked_mul(percentage as u128)
+            .ok_or(ErrorCode::ValueOverflow)?
+            / 100;
+        Ok(sum.checked_add(weighted).ok_or(ErrorCode::ValueOverflow)?)
+    })
+}
+
+/// Returns the integer square root of `value`, rounded down.
+pub fn isqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    // Newton's method from an overestimate, which decreases until it reaches the root.
+    let mut root = 1u128 << (128 - value.leading_zeros()).div_ceil(2);
+    loop {
+        let next = (root + value / root) / 2;
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
+/// Returns `value * numerator / denominator`, rounded down.
+///
+/// The product is taken in u128 so it cannot overflow; only a zero denominator (`DivideByZero`) or a
+/// result that does not fit back into a u64 (`MathOverflow`) is an error.
+pub fn mul_div_floor(value: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    mul_div(value, numerator, denominator, false, ErrorCode::MathOverflow)
+}
+
+/// Returns `value * numerator / denominator`, rounded up.
+pub fn mul_div_ceil(value: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    mul_div(value, numerator, denominator, true, ErrorCode::MathOverflow)
+}
+
+/// `value * numerator / denominator`, rounded up if `round_up`, failing with `overflow` if the result
+/// does not fit in a u64, so each kind of conversion can report its own error.
+fn mul_div(value: u64, numerator: u64, denominator: u64, round_up: bool, overflow: ErrorCode) -> Result<u64> {
+    require!(denominator > 0, ErrorCode::DivideByZero);
+    // A u64 times a u64 always fits in a u128.
+    let product = (value as u128) * (numerator as u128);
+    let result = if round_up {
+        product.div_ceil(denominator as u128)
+    } else {
+        product / denominator as u128
+    };
+    u64::try_from(result).map_err(|_| error!(overflow))
+}
+
+// Every conversion between tokens and shares rounds in the protocol's favour, so no sequence of
+// operations lets a user take out more than they put in:
+// - deposit, tokens to shares minted: floor (`shares_for_amount`)
+// - withdraw, shares to tokens paid out: floor (`amount_for_shares`)
+// - withdraw, tokens to shares burned: ceil (`shares_for_amount_ceil`, via `withdraw::plan_withdrawal`)
+// - borrow, tokens to debt shares minted: ceil (`borrow_shares_for_amount`)
+// - repay, tokens to debt shares burned: floor (`repay::burn_debt_shares`, shared by repayments and liquidations)
+// - borrow index growth to tokens owed: ceil (`debt_at_index`, via `health::current_debt`), or debt shares
+//   to tokens owed for a position without an index snapshot: ceil (`amount_for_shares_ceil`)
+// - liquidation, seized tokens to deposit shares burned: ceil (`shares_for_amount_ceil`, via `liquidate`
+//   and `liquidate_all`)
+//
+// A conversion whose result doesn't fit in a u64 fails with `ShareConversionOverflow`.
+
+// Every bank holds two pools, deposits and borrows, and both treat an empty pool the same way:
+// - minting into an empty pool, the first deposit or first borrow, is 1:1 (`shares_for_amount`,
+//   `borrow_shares_for_amount`)
+// - burning or redeeming shares of an empty pool is impossible, so it fails with `BankEmpty`
+//   (`require_non_empty`) instead of dividing by zero
+
+/// Fails with `BankEmpty` unless a pool holds both tokens and the shares backing them.
+pub fn require_non_empty(total_amount: u64, total_shares: u64) -> Result<()> {
+    require!(total_amount > 0 && total_shares > 0, ErrorCode::BankEmpty);
+    Ok(())
+}
+
+/// Returns the deposit shares minted for adding `amount` to a pool of `total_amount` tokens backed by
+/// `total_shares` shares, rounded down so the pool never mints more than the deposit is worth.
+///
+/// An empty pool mints shares 1:1.
+pub fn shares_for_amount(amount: u64, total_amount: u64, total_shares: u64) -> Result<u64> {
+    if total_amount == 0 || total_shares == 0 {
+        return Ok(amount);
+    }
+    mul_div(amount, total_shares, total_amount, false, ErrorCode::ShareConversionOverflow)
+}
+
+/// Returns the shares that must be burned to take `amount` out of a pool of `total_amount` tokens backed
+/// by `total_shares` shares, rounded up so the shares burned always cover the tokens removed. The pool
+/// must not be empty (see `require_non_empty`).
+pub fn shares_for_amount_ceil(amount: u64, total_amount: u64, total_shares: u64) -> Result<u64> {
+    mul_div(amount, total_shares, total_amount, true, ErrorCode::ShareConversionOverflow)
+}
+
+/// Returns the debt shares minted for borrowing `amount` from a pool of `total_amount` borrowed tokens
+/// backed by `total_shares` shares, rounded up so a borrower always owes at least what they took out.
+///
+/// An empty pool mints shares 1:1.
+pub fn borrow_shares_for_amount(amount: u64, total_amount: u64, total_shares: u64) -> Result<u64> {
+    if total_amount == 0 || total_shares == 0 {
+        return Ok(amount);
+    }
+    mul_div(amount, total_shares, total_amount, true, ErrorCode::ShareConversionOverflow)
+}
+
+/// Returns the tokens that `shares` redeem from a pool of `total_amount` tokens backed by `total_shares`
+/// shares, rounded down so the pool never pays out more than the shares are worth.
+pub fn amount_for_shares(shares: u64, total_amount: u64, total_shares: u64) -> Result<u64> {
+    mul_div(shares, total_amount, total_shares, false, ErrorCode::ShareConversionOverflow)
+}
+
+/// Returns the tokens `shares` stand for in a pool of `total_amount` tokens backed by `total_shares`
+/// shares, rounded up, for debt shares whose holder must owe at least their share of the pool.
+pub fn amount_for_shares_ceil(shares: u64, total_amount: u64, total_shares: u64) -> Result<u64> {
+    mul_div(shares, total_amount, total_shares, true, ErrorCode::ShareConversionOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{USD_EXPONENT, SOL_DECIMALS, USDC_DECIMALS};
+
+    #[test]
+    fn normalizes_sol_price() {
+        // $150.12345678 with expo -8 -> $150.123456 at 6 decimals
+        assert_eq!(normalize_price(15_012_345_678, -8, USD_EXPONENT).unwrap(), 150_123_456);
+    }
+
+    #[test]
+    fn normalizes_usdc_price() {
+        // $0.99990000 with expo -8 -> $0.999900 at 6 decimals
+        assert_eq!(normalize_price(99_990_000, -8, USD_EXPONENT).unwrap(), 999_900);
+    }
+
+    #[test]
+    fn scales_up_less_precise_feeds() {
+        assert_eq!(normalize_price(150, 0, USD_EXPONENT).unwrap(), 150_000_000);
+        assert_eq!(normalize_price(15_000, -2, -8).unwrap(), 15_000_000_000);
+    }
+
+    #[test]
+    fn rejects_huge_shifts() {
+        assert!(normalize_price(1, 40, -8).is_err());
+        assert!(normalize_price(i64::MAX.into(), 30, 0).is_err());
+    }
+
+    #[test]
+    fn rejects_zero_and_negative_prices() {
+        assert_eq!(normalize_price(-1, -8, USD_EXPONENT).unwrap_err(), error!(ErrorCode::InvalidOraclePrice));
+        assert_eq!(normalize_price(i64::MIN.into(), -8, USD_EXPONENT).unwrap_err(), error!(ErrorCode::InvalidOraclePrice));
+        assert_eq!(normalize_price(0, -8, USD_EXPONENT).unwrap_err(), error!(ErrorCode::InvalidOraclePrice));
+    }
+
+    #[test]
+    fn values_sol_and_usdc_on_the_same_scale() {
+        let sol_price = normalize_price(150_00000000, -8, USD_EXPONENT).unwrap();
+        let usdc_price = normalize_price(1_00000000, -8, USD_EXPONENT).unwrap();
+
+        // 1 SOL = 10^9 lamports, 1 USDC = 10^6 micro-USDC
+        let one_sol = token_value_usd(sol_price, 1_000_000_000, SOL_DECIMALS).unwrap();
+        let one_usdc = token_value_usd(usdc_price, 1_000_000, USDC_DECIMALS).unwrap();
+
+        assert_eq!(one_sol, 150_000_000);
+        assert_eq!(one_usdc, 1_000_000);
+        assert_eq!(one_sol / one_usdc, 150);
+    }
+
+    #[test]
+    fn weights_each_asset_by_its_own_threshold() {
+        let sol_value = 1_000_000_000; // $1,000
+        let usdc_value = 500_000_000; // $500
+
+        // SOL at an 80% threshold, USDC at 90%
+        let weighted = weighted_sum(&[(sol_value, 80), (usdc_value, 90)]).unwrap();
+
+        assert_eq!(weighted, 800_000_000 + 450_000_000);
+        // A single blended threshold would have given a different answer.
+        assert_ne!(weighted, (sol_value + usdc_value) * 80 / 100);
+    }
+
+    #[test]
+    fn converts_usd_value_back_to_native_units() {
+        // $472.50 of SOL at $90 is 5.25 SOL
+        assert_eq!(usd_value_to_native(472_500_000, 90_000_000, SOL_DECIMALS).unwrap(), 5_250_000_000);
+        // $450 of USDC at $1 is 450 USDC
+        assert_eq!(usd_value_to_native(450_000_000, 1_000_000, USDC_DECIMALS).unwrap(), 450_000_000);
+    }
+
+    #[test]
+    fn native_conversion_round_trips_token_value() {
+        let price = 150_123_456;
+        let value = token_value_usd(price, 3_000_000_000, SOL_DECIMALS).unwrap();
+        assert_eq!(usd_value_to_native(value, price, SOL_DECIMALS).unwrap(), 3_000_000_000);
+    }
+
+    #[test]
+    fn rejects_zero_price_conversion() {
+        assert_eq!(usd_value_to_native(1_000_000, 0, USDC_DECIMALS).unwrap_err(), error!(ErrorCode::DivideByZero));
+        assert_eq!(usd_value_to_native_ceil(1_000_000, 0, USDC_DECIMALS).unwrap_err(), error!(ErrorCode::DivideByZero));
+    }
+
+    #[test]
+    fn price_conversions_report_their_own_overflows() {
+        // $10^15 of SOL at a millionth of a dollar is far more lamports than a u64 holds.
+        assert_eq!(
+            usd_value_to_native(1_000_000_000_000_000_000_000, 1, SOL_DECIMALS).unwrap_err(),
+            error!(ErrorCode::PriceConversionOverflow)
+        );
+        assert_eq!(normalize_price(1, 40, -8).unwrap_err(), error!(ErrorCode::PriceConversionOverflow));
+        assert_eq!(token_value_usd(u128::MAX, 2, SOL_DECIMALS).unwrap_err(), error!(ErrorCode::ValueOverflow));
+    }
+
+    #[test]
+    fn ceil_conversion_is_always_worth_at_least_the_value() {
+        // $15 of SOL at $90 is 0.1666... SOL, which rounds down to less than $15.
+        assert_eq!(usd_value_to_native(15_000_000, 90_000_000, SOL_DECIMALS).unwrap(), 166_666_666);
+        assert_eq!(usd_value_to_native_ceil(15_000_000, 90_000_000, SOL_DECIMALS).unwrap(), 166_666_667);
+        assert!(token_value_usd(90_000_000, 166_666_667, SOL_DECIMALS).unwrap() >= 15_000_000);
+        // An exact conversion is unchanged.
+        assert_eq!(usd_value_to_native_ceil(472_500_000, 90_000_000, SOL_DECIMALS).unwrap(), 5_250_000_000);
+    }
+
+    #[test]
+    fn debt_at_index_grows_with_the_index_and_rounds_up() {
+        let one = 1_000_000_000_000_000_000u128;
+        assert_eq!(debt_at_index(1_000, one, one).unwrap(), 1_000);
+        assert_eq!(debt_at_index(1_000, one, one * 11 / 10).unwrap(), 1_100);
+        // Borrowed at 1.1 and now at 1.21, the debt has grown by 10%, not 21%.
+        assert_eq!(debt_at_index(1_000, one * 11 / 10, one * 121 / 100).unwrap(), 1_100);
+        // Any fraction of a unit is owed in full.
+        assert_eq!(debt_at_index(1_000, 3 * one, 4 * one).unwrap(), 1_334);
+        assert_eq!(debt_at_index(1_000, 0, one).unwrap_err(), error!(ErrorCode::DivideByZero));
+    }
+
+    #[test]
+    fn first_deposit_mints_shares_one_to_one() {
+        assert_eq!(shares_for_amount(1_000, 0, 0).unwrap(), 1_000);
+        assert_eq!(borrow_shares_for_amount(1_000, 0, 0).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn empty_pools_are_reported_as_bank_empty() {
+        assert!(require_non_empty(1, 1).is_ok());
+        assert_eq!(require_non_empty(0, 0).unwrap_err(), error!(ErrorCode::BankEmpty));
+        assert_eq!(require_non_empty(1_000, 0).unwrap_err(), error!(ErrorCode::BankEmpty));
+        assert_eq!(require_non_empty(0, 1_000).unwrap_err(), error!(ErrorCode::BankEmpty));
+    }
+
+    #[test]
+    fn borrow_shares_round_up() {
+        // 3 tokens owed back 2 shares: borrowing 2 tokens mints 1.33 shares, rounded up to 2.
+        assert_eq!(borrow_shares_for_amount(2, 3, 2).unwrap(), 2);
+        assert_eq!(borrow_shares_for_amount(3, 3, 2).unwrap(), 2);
+    }
+
+    #[test]
+    fn share_conversions_round_down() {
+        // 3 tokens back 2 shares: 2 tokens are worth 1.33 shares, 1 share is worth 1.5 tokens.
+        assert_eq!(shares_for_amount(2, 3, 2).unwrap(), 1);
+        assert_eq!(amount_for_shares(1, 3, 2).unwrap(), 1);
+    }
+
+    #[test]
+    fn tiny_deposits_mint_no_shares_at_extreme_rates() {
+        // Each share is worth 10^12 tokens, so anything smaller mints nothing.
+        let total_amount = 1_000_000_000_000_000_000;
+        let total_shares = 1_000_000;
+        assert_eq!(shares_for_amount(999_999_999_999, total_amount, total_shares).unwrap(), 0);
+        assert_eq!(shares_for_amount(1_000_000_000_000, total_amount, total_shares).unwrap(), 1);
+    }
+
+    #[test]
+    fn cheap_shares_do_not_overflow() {
+        // One token backs 10^12 shares.
+        assert_eq!(shares_for_amount(1_000_000, 1, 1_000_000_000_000).unwrap(), 1_000_000_000_000_000_000);
+        assert_eq!(
+            shares_for_amount(u64::MAX, 1, 1_000_000_000_000).unwrap_err(),
+            error!(ErrorCode::ShareConversionOverflow)
+        );
+        assert_eq!(amount_for_shares(1_000_000_000_000, 1, 1_000_000_000_000).unwrap(), 1);
+    }
+
+    #[test]
+    fn ceil_share_conversions_cover_the_amount() {
+        // 3 tokens back 2 shares: taking out 2 tokens burns 1.33 shares, rounded up to 2, and 1 debt
+        // share owes 1.5 tokens, rounded up to 2.
+        assert_eq!(shares_for_amount_ceil(2, 3, 2).unwrap(), 2);
+        assert_eq!(amount_for_shares_ceil(1, 3, 2).unwrap(), 2);
+        // An empty pool has nothing to convert against.
+        assert_eq!(shares_for_amount_ceil(1, 0, 0).unwrap_err(), error!(ErrorCode::DivideByZero));
+        assert_eq!(amount_for_shares_ceil(u64::MAX, 2, 1).unwrap_err(), error!(ErrorCode::ShareConversionOverflow));
+    }
+
+    #[test]
+    fn isqrt_rounds_down() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(1), 1);
+        assert_eq!(isqrt(15), 3);
+        assert_eq!(isqrt(16), 4);
+        assert_eq!(isqrt(22_500_000_000_000_000), 150_000_000);
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+    }
+
+    #[test]
+    fn mul_div_rounds_in_the_requested_direction() {
+        assert_eq!(mul_div_floor(10, 2, 3).unwrap(), 6);
+        assert_eq!(mul_div_ceil(10, 2, 3).unwrap(), 7);
+        // Exact results are the same either way.
+        assert_eq!(mul_div_floor(9, 2, 3).unwrap(), 6);
+        assert_eq!(mul_div_ceil(9, 2, 3).unwrap(), 6);
+        assert_eq!(mul_div_ceil(0, 2, 3).unwrap(), 0);
+    }
+
+    #[test]
+    fn mul_div_rejects_zero_denominators_and_overflow() {
+        assert_eq!(mul_div_floor(1, 1, 0).unwrap_err(), error!(ErrorCode::DivideByZero));
+        assert_eq!(mul_div_ceil(1, 1, 0).unwrap_err(), error!(ErrorCode::DivideByZero));
+        assert_eq!(mul_div_floor(u64::MAX, 2, 1).unwrap_err(), error!(ErrorCode::MathOverflow));
+        assert_eq!(mul_div_ceil(u64::MAX, 2, 1).unwrap_err(), error!(ErrorCode::MathOverflow));
+        // The intermediate product may exceed a u64 as long as the result fits.
+        assert_eq!(mul_div_floor(u64::MAX, u64::MAX, u64::MAX).unwrap(), u64::MAX);
+        assert_eq!(mul_div_ceil(u64::MAX, u64::MAX - 1, u64::MAX).unwrap(), u64::MAX - 1);
+    }
+
+    /// A small deterministic generator, so the round-trip tests cover many pool states without
+    /// pulling in a property-testing crate.
+    fn pseudo_random(seed: &mut u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed
+    }
+
+    #[test]
+    fn deposit_then_withdraw_never_returns_more_than_deposited() {
+        let mut seed = 0x9E37_79B9_7F4A_7C15;
+        for _ in 0..10_000 {
+            let total_amount = pseudo_random(&mut seed) % 1_000_000_000_000 + 1;
+            let total_shares = pseudo_random(&mut seed) % 1_000_000_000_000 + 1;
+            let amount = pseudo_random(&mut seed) % 1_000_000_000;
+
+            let shares = shares_for_amount(amount, total_amount, total_shares).unwrap();
+            let redeemed = amount_for_shares(
+                shares,
+                total_amount + amount,
+                total_shares + shares,
+            )
+            .unwrap();
+            assert!(redeemed <= amount, "deposited {amount}, redeemed {redeemed}");
+        }
+    }
+
+    #[test]
+    fn borrow_then_repay_never_owes_less_than_borrowed() {
+        let mut seed = 0xD1B5_4A32_D192_ED03;
+        for _ in 0..10_000 {
+            let total_borrows = pseudo_random(&mut seed) % 1_000_000_000_000 + 1;
+            let total_borrow_shares = pseudo_random(&mut seed) % 1_000_000_000_000 + 1;
+            let amount = pseudo_random(&mut seed) % 1_000_000_000 + 1;
+
+            let shares = mul_div_ceil(amount, total_borrow_shares, total_borrows).unwrap();
+            let owed = mul_div_ceil(shares, total_borrows + amount, total_borrow_shares + shares).unwrap();
+            assert!(owed >= amount, "borrowed {amount}, owes {owed}");
+
+            // Repaying exactly what was borrowed never clears more shares than were minted for it.
+            let cleared = mul_div_floor(amount, total_borrow_shares + shares, total_borrows + amount).unwrap();
+            assert!(cleared <= shares, "minted {shares}, repay cleared {cleared}");
+        }
+    }
+
+    #[test]
+    fn withdrawing_an_amount_burns_enough_shares_to_cover_it() {
+        let mut seed = 0x2545_F491_4F6C_DD1D;
+        for _ in 0..10_000 {
+            let total_amount = pseudo_random(&mut seed) % 1_000_000_000_000 + 1;
+            let total_shares = pseudo_random(&mut seed) % 1_000_000_000_000 + 1;
+            let amount = pseudo_random(&mut seed) % total_amount + 1;
+
+            let shares = mul_div_ceil(amount, total_shares, total_amount).unwrap();
+            assert!(amount_for_shares(shares, total_amount, total_shares).unwrap() >= amount);
+        }
+    }
+}
@@ -0,0 +1,665 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use pyth_solana_receiver_sdk::price_update::{Price, PriceUpdateV2};
+use crate::state::{Bank, CollateralPricer};
+use crate::error::ErrorCode;
+use crate::constants::{
+    BPS_DENOMINATOR, LP_MAX_TWAP_DEVIATION_BPS, LP_MIN_TWAP_WINDOW_SECONDS, MAX_PRICE_CONFIDENCE_BPS, RAYDIUM_CPMM_PROGRAM_ID,
+    SWITCHBOARD_PROGRAM_ID, USD_EXPONENT,
+};
+use crate::health::fetch_price;
+use crate::math::{isqrt, normalize_price, token_value_usd};
+
+/// Where a bank's price was read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriceSource {
+    /// The bank's Pyth feed, always tried first.
+    Pyth,
+    /// The bank's Switchboard aggregator, used when the Pyth price is stale or too uncertain.
+    Switchboard,
+}
+
+/// A price normalized to `USD_EXPONENT`, along with the oracle it came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub price: u128,
+    pub source: PriceSource,
+}
+
+// Byte offsets into a Switchboard V2 `AggregatorAccountData`, a packed zero-copy account, of the fields
+// of its `latest_confirmed_round` that are read here.
+const SWITCHBOARD_NUM_SUCCESS_OFFSET: usize = 341;
+const SWITCHBOARD_ROUND_OPEN_TIMESTAMP_OFFSET: usize = 358;
+const SWITCHBOARD_RESULT_MANTISSA_OFFSET: usize = 366;
+const SWITCHBOARD_RESULT_SCALE_OFFSET: usize = 382;
+const SWITCHBOARD_MIN_LEN: usize = 386;
+
+/// The latest confirmed result of a Switchboard V2 aggregator, `mantissa * 10^-scale`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwitchboardPrice {
+    pub mantissa: i128,
+    pub scale: u32,
+    /// When the round that produced the result was opened.
+    pub timestamp: i64,
+}
+
+impl SwitchboardPrice {
+    /// Reads the latest confirmed round of the aggregator in `info`, which must be owned by the
+    /// Switchboard program.
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require_keys_eq!(*info.owner, SWITCHBOARD_PROGRAM_ID, ErrorCode::InvalidSecondaryFeed);
+        let data = info.try_borrow_data()?;
+        require!(data.len() >= SWITCHBOARD_MIN_LEN, ErrorCode::InvalidSecondaryFeed);
+        require!(
+            data[..8] == hash(b"account:AggregatorAccountData").to_bytes()[..8],
+            ErrorCode::InvalidSecondaryFeed
+        );
+
+        let num_success = u32::from_le_bytes(read(&data, SWITCHBOARD_NUM_SUCCESS_OFFSET));
+        // A round no oracle answered has no result.
+        require!(num_success > 0, ErrorCode::StalePriceFeed);
+        Ok(Self {
+            mantissa: i128::from_le_bytes(read(&data, SWITCHBOARD_RESULT_MANTISSA_OFFSET)),
+            scale: u32::from_le_bytes(read(&data, SWITCHBOARD_RESULT_SCALE_OFFSET)),
+            timestamp: i64::from_le_bytes(read(&data, SWITCHBOARD_ROUND_OPEN_TIMESTAMP_OFFSET)),
+        })
+    }
+
+    /// Returns the result normalized to `USD_EXPONENT`, rejecting it if it is older than `max_age`.
+    pub fn price_no_older_than(&self, clock: &Clock, max_age: u64) -> Result<u128> {
+        let age = clock.unix_timestamp.saturating_sub(self.timestamp);
+        require!(u64::try_from(age).is_ok_and(|age| age <= max_age), ErrorCode::StalePriceFeed);
+        let expo = i32::try_from(self.scale).map_err(|_| ErrorCode::MathOverflow)?;
+        normalize_price(self.mantissa, -expo, USD_EXPONENT)
+    }
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    data[offset..offset + N].try_into().unwrap()
+}
+
+// Byte offsets into a Raydium CPMM `PoolState`, a packed zero-copy account, of the fields read here.
+const RAYDIUM_POOL_TOKEN_0_VAULT_OFFSET: usize = 72;
+const RAYDIUM_POOL_TOKEN_1_VAULT_OFFSET: usize = 104;
+const RAYDIUM_POOL_LP_MINT_OFFSET: usize = 136;
+const RAYDIUM_POOL_OBSERVATION_KEY_OFFSET: usize = 296;
+const RAYDIUM_POOL_MINT_0_DECIMALS_OFFSET: usize = 331;
+const RAYDIUM_POOL_MINT_1_DECIMALS_OFFSET: usize = 332;
+const RAYDIUM_POOL_PROTOCOL_FEES_TOKEN_0_OFFSET: usize = 341;
+const RAYDIUM_POOL_PROTOCOL_FEES_TOKEN_1_OFFSET: usize = 349;
+const RAYDIUM_POOL_FUND_FEES_TOKEN_0_OFFSET: usize = 357;
+const RAYDIUM_POOL_FUND_FEES_TOKEN_1_OFFSET: usize = 365;
+const RAYDIUM_POOL_LEN: usize = 637;
+
+// Byte offsets into a Raydium CPMM `ObservationState`, the pool's ring of price observations.
+const RAYDIUM_OBSERVATION_INDEX_OFFSET: usize = 9;
+const RAYDIUM_OBSERVATION_POOL_ID_OFFSET: usize = 11;
+const RAYDIUM_OBSERVATIONS_OFFSET: usize = 43;
+const RAYDIUM_OBSERVATION_SIZE: usize = 40; // block_timestamp: u64, then two u128 cumulative prices
+const RAYDIUM_OBSERVATION_NUM: usize = 100;
+const RAYDIUM_OBSERVATION_STATE_LEN: usize = 4_075;
+
+/// The fields of a Raydium CPMM pool an `LpToken` bank is priced from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RaydiumPool {
+    pub token_0_vault: Pubkey,
+    pub token_1_vault: Pubkey,
+    pub lp_mint: Pubkey,
+    /// The pool's `ObservationState`, which records its time-weighted price.
+    pub observation_key: Pubkey,
+    pub mint_0_decimals: u8,
+    pub mint_1_decimals: u8,
+    /// Protocol and fund fees sitting in the token 0 vault, which belong to Raydium rather than the pool.
+    pub fees_token_0: u64,
+    /// Protocol and fund fees sitting in the token 1 vault.
+    pub fees_token_1: u64,
+}
+
+impl RaydiumPool {
+    /// Reads the pool state in `info`, which must be owned by the Raydium CPMM program.
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require_keys_eq!(*info.owner, RAYDIUM_CPMM_PROGRAM_ID, ErrorCode::InvalidLpPool);
+        let data = info.try_borrow_data()?;
+        require!(data.len() >= RAYDIUM_POOL_LEN, ErrorCode::InvalidLpPool);
+        require!(data[..8] == hash(b"account:PoolState").to_bytes()[..8], ErrorCode::InvalidLpPool);
+
+        let fees = |protocol_offset: usize, fund_offset: usize| {
+            u64::from_le_bytes(read(&data, protocol_offset)).saturating_add(u64::from_le_bytes(read(&data, fund_offset)))
+        };
+        Ok(Self {
+            token_0_vault: Pubkey::new_from_array(read(&data, RAYDIUM_POOL_TOKEN_0_VAULT_OFFSET)),
+            token_1_vault: Pubkey::new_from_array(read(&data, RAYDIUM_POOL_TOKEN_1_VAULT_OFFSET)),
+            lp_mint: Pubkey::new_from_array(read(&data, RAYDIUM_POOL_LP_MINT_OFFSET)),
+            observation_key: Pubkey::new_from_array(read(&data, RAYDIUM_POOL_OBSERVATION_KEY_OFFSET)),
+            mint_0_decimals: data[RAYDIUM_POOL_MINT_0_DECIMALS_OFFSET],
+            mint_1_decimals: data[RAYDIUM_POOL_MINT_1_DECIMALS_OFFSET],
+            fees_token_0: fees(RAYDIUM_POOL_PROTOCOL_FEES_TOKEN_0_OFFSET, RAYDIUM_POOL_FUND_FEES_TOKEN_0_OFFSET),
+            fees_token_1: fees(RAYDIUM_POOL_PROTOCOL_FEES_TOKEN_1_OFFSET, RAYDIUM_POOL_FUND_FEES_TOKEN_1_OFFSET),
+        })
+    }
+}
+
+/// Returns the time-weighted price of token 0 in token 1 of `pool_key`'s pool, as a Q32 ratio of native
+/// units, from its `ObservationState` in `info`.
+///
+/// Raydium appends an observation of the running price sum at most every 15 seconds, on swaps. The
+/// average is taken between the latest one and the newest that is at least `LP_MIN_TWAP_WINDOW_SECONDS`
+/// older, so a swap can only move it by its share of the window.
+pub fn time_weighted_price_x32(info: &AccountInfo, pool_key: &Pubkey) -> Result<u128> {
+    require_keys_eq!(*info.owner, RAYDIUM_CPMM_PROGRAM_ID, ErrorCode::InvalidLpPool);
+    let data = info.try_borrow_data()?;
+    require!(data.len() >= RAYDIUM_OBSERVATION_STATE_LEN, ErrorCode::InvalidLpPool);
+    require!(data[..8] == hash(b"account:ObservationState").to_bytes()[..8], ErrorCode::InvalidLpPool);
+    require_keys_eq!(Pubkey::new_from_array(read(&data, RAYDIUM_OBSERVATION_POOL_ID_OFFSET)), *pool_key, ErrorCode::InvalidLpPool);
+
+    let observation = |index: usize| -> (i64, u128) {
+        let offset = RAYDIUM_OBSERVATIONS_OFFSET + index * RAYDIUM_OBSERVATION_SIZE;
+        (u64::from_le_bytes(read(&data, offset)) as i64, u128::from_le_bytes(read(&data, offset + 8)))
+    };
+    let latest_index = u16::from_le_bytes(read(&data, RAYDIUM_OBSERVATION_INDEX_OFFSET)) as usize;
+    require!(latest_index < RAYDIUM_OBSERVATION_NUM, ErrorCode::InvalidLpPool);
+    let (latest_ts, latest_cumulative) = observation(latest_index);
+
+    // Walk back through the ring until the window is long enough. Slots never written read 0.
+    for step in 1..RAYDIUM_OBSERVATION_NUM {
+        let (ts, cumulative) = observation((latest_index + RAYDIUM_OBSERVATION_NUM - step) % RAYDIUM_OBSERVATION_NUM);
+        if ts == 0 || ts >= latest_ts {
+            break;
+        }
+        let window = latest_ts - ts;
+        if window >= LP_MIN_TWAP_WINDOW_SECONDS {
+            // The running sums wrap on overflow, so their difference does too.
+            return Ok(latest_cumulative.wrapping_sub(cumulative) / window as u128);
+        }
+    }
+    err!(ErrorCode::LpWindowTooShort)
+}
+
+/// Returns the price of one whole LP token of `bank`, an `LpToken` bank, on the `USD_EXPONENT` scale,
+/// from its Raydium CPMM pool's reserves and the Pyth prices of its two reserve assets. The pool, its
+/// observation account, both vaults and the LP mint must all be among `accounts`.
+///
+/// The pool is valued at the fair reserves for the feeds' prices rather than at its actual reserves:
+/// a constant-product pool holds equal value on both sides, so it is worth `2 * sqrt(value_a * value_b)`.
+/// Skewing the reserves with a swap leaves their product, and so this value, unchanged, while their
+/// plain sum would move with the skew. A pool whose spot price has strayed more than
+/// `LP_MAX_TWAP_DEVIATION_BPS` from its time-weighted price is refused as it is being moved.
+pub fn fetch_lp_price(
+    bank: &Bank,
+    reserve_a_update: &PriceUpdateV2,
+    reserve_b_update: &PriceUpdateV2,
+    accounts: &[AccountInfo],
+    max_age: u64,
+    clock: &Clock,
+) -> Result<u128> {
+    let CollateralPricer::LpToken { reserve_a_feed, reserve_b_feed, pool: pool_key } = bank.collateral_pricer else {
+        return err!(ErrorCode::InvalidLpPool);
+    };
+    let account = |key: &Pubkey| -> Result<&AccountInfo> {
+        accounts.iter().find(|info| info.key == key).ok_or_else(|| {
+            msg!("Account {} of LP pool {} was not passed", key, pool_key);
+            error!(ErrorCode::InvalidLpPool)
+        })
+    };
+    let pool = RaydiumPool::load(account(&pool_key)?)?;
+    require_keys_eq!(pool.lp_mint, bank.mint_address, ErrorCode::InvalidLpPool);
+
+    // Fees the vaults hold for Raydium aren't the pool's.
+    let reserve_a = token_account_amount(account(&pool.token_0_vault)?)?.saturating_sub(pool.fees_token_0);
+    let reserve_b = token_account_amount(account(&pool.token_1_vault)?)?.saturating_sub(pool.fees_token_1);
+    require!(reserve_a > 0 && reserve_b > 0, ErrorCode::InvalidLpPool);
+    let spot_price_x32 = ((reserve_b as u128) << 32) / reserve_a as u128;
+    let twap_x32 = time_weighted_price_x32(account(&pool.observation_key)?, &pool_key)?;
+    let deviation_bps = deviation_bps(twap_x32, spot_price_x32)?;
+    if deviation_bps > LP_MAX_TWAP_DEVIATION_BPS as u128 {
+        msg!("LP pool {} is {} bps from its time-weighted price", pool_key, deviation_bps);
+        return err!(ErrorCode::LpPriceDeviation);
+    }
+
+    let price_a = fetch_price(reserve_a_update, &reserve_a_feed, max_age, clock)?;
+    let price_b = fetch_price(reserve_b_update, &reserve_b_feed, max_age, clock)?;
+    let price_a = normalize_price(price_a.price.into(), price_a.exponent, USD_EXPONENT)?;
+    let price_b = normalize_price(price_b.price.into(), price_b.exponent, USD_EXPONENT)?;
+
+    let value_a = token_value_usd(price_a, reserve_a, pool.mint_0_decimals)?;
+    let value_b = token_value_usd(price_b, reserve_b, pool.mint_1_decimals)?;
+    let pool_value = isqrt(value_a.checked_mul(value_b).ok_or(ErrorCode::ValueOverflow)?)
+        .checked_mul(2)
+        .ok_or(ErrorCode::ValueOverflow)?;
+
+    // The supply comes from the LP mint itself, which only the pool can mint from.
+    let lp_supply = token_mint_supply(account(&bank.mint_address)?)?;
+    require!(lp_supply > 0, ErrorCode::DivideByZero);
+    let scale = 10u128.checked_pow(bank.decimals as u32).ok_or(ErrorCode::ValueOverflow)?;
+    Ok(pool_value.checked_mul(scale).ok_or(ErrorCode::ValueOverflow)? / lp_supply as u128)
+}
+
+/// The balance of the SPL or Token-2022 token account in `info`.
+fn token_account_amount(info: &AccountInfo) -> Result<u64> {
+    require!(is_token_program(info.owner), ErrorCode::InvalidLpPool);
+    let data = info.try_borrow_data()?;
+    Ok(TokenAccount::try_deserialize(&mut &data[..]).map_err(|_| error!(ErrorCode::InvalidLpPool))?.amount)
+}
+
+/// The supply of the SPL or Token-2022 mint in `info`.
+fn token_mint_supply(info: &AccountInfo) -> Result<u64> {
+    require!(is_token_program(info.owner), ErrorCode::InvalidLpPool);
+    let data = info.try_borrow_data()?;
+    Ok(Mint::try_deserialize(&mut &data[..]).map_err(|_| error!(ErrorCode::InvalidLpPool))?.supply)
+}
+
+fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == anchor_spl::token::ID || *program_id == anchor_spl::token_2022::ID
+}
+
+/// Returns the price of `bank`'s asset from its Pyth feed, falling back to its Switchboard aggregator
+/// when the Pyth price is stale, invalid or its confidence interval is too wide.
+///
+/// The fallback is only taken when `secondary` is the aggregator set on the bank, and only if its price
+/// is itself fresh and within the bank's `max_oracle_deviation_bps` of the last price Pyth reported.
+/// Without a usable fallback, the Pyth price or its error is returned as is.
+pub fn fetch_price_with_fallback(
+    primary: &PriceUpdateV2,
+    secondary: Option<&AccountInfo>,
+    bank: &Bank,
+    max_age: u64,
+    clock: &Clock,
+) -> Result<OraclePrice> {
+    let secondary = secondary.filter(|info| bank.secondary_feed != Pubkey::default() && *info.key == bank.secondary_feed);
+    let from_pyth = |price: Price| -> Result<OraclePrice> {
+        Ok(OraclePrice {
+            price: normalize_price(price.price.into(), price.exponent, USD_EXPONENT)?,
+            source: PriceSource::Pyth,
+        })
+    };
+
+    let primary_price = fetch_price(primary, &bank.price_feed_id, max_age, clock);
+    // An update for another asset says nothing about this one, not even as the fallback's reference, so
+    // its `WrongPriceFeed` is returned as is.
+    if primary.price_message.feed_id != bank.price_feed_id {
+        return primary_price.and_then(from_pyth);
+    }
+    let secondary = match (primary_price, secondary) {
+        (primary_price, None) => return primary_price.and_then(from_pyth),
+        (Ok(price), Some(_)) if !too_uncertain(&price)? => return from_pyth(price),
+        (_, Some(secondary)) => secondary,
+    };
+
+    // The last price Pyth reported, however old, is the reference the fallback has to agree with. If
+    // there is none the sources can't be cross-checked, so the fallback isn't trusted either.
+    let message = &primary.price_message;
+    require!(message.price > 0, ErrorCode::InvalidOraclePrice);
+    let reference = normalize_price(message.price.into(), message.exponent, USD_EXPONENT)?;
+    let fallback = SwitchboardPrice::load(secondary)?.price_no_older_than(clock, max_age)?;
+
+    let deviation_bps = deviation_bps(reference, fallback)?;
+    if deviation_bps > bank.max_oracle_deviation_bps as u128 {
+        msg!("Oracles disagree: Pyth {}, Switchboard {}, {} bps apart", reference, fallback, deviation_bps);
+        return err!(ErrorCode::OracleDeviation);
+    }
+
+    msg!("Pyth price unusable, using Switchboard price {}", fallback);
+    Ok(OraclePrice { price: fallback, source: PriceSource::Switchboard })
+}
+
+/// Rejects `price` for `bank`'s asset if it has moved more than the bank's `max_price_deviation_bps` from
+/// the last price recorded within its `price_deviation_window_seconds`.
+///
+/// A sudden jump is more likely a manipulated or broken update than a real move, so borrows and
+/// withdrawals wait until the window has passed. Repayments never price anything and are unaffected.
+pub fn check_price_deviation(bank: &Bank, price: u128, now: i64) -> Result<()> {
+    if bank.max_price_deviation_bps == 0 || bank.last_price == 0 {
+        return Ok(());
+    }
+    let elapsed = now.saturating_sub(bank.last_price_ts);
+    if u64::try_from(elapsed).is_ok_and(|elapsed| elapsed > bank.price_deviation_window_seconds) {
+        return Ok(());
+    }
+
+    let deviation_bps = deviation_bps(bank.last_price, price)?;
+    if deviation_bps > bank.max_price_deviation_bps as u128 {
+        msg!("Price of {} moved {} bps from {} within the breaker window", bank.mint_address, deviation_bps, bank.last_price);
+        return err!(ErrorCode::PriceDeviationTooHigh);
+    }
+    Ok(())
+}
+
+/// How far `price` is from `reference`, in basis points of `reference`.
+fn deviation_bps(reference: u128, price: u128) -> Result<u128> {
+    Ok(reference
+        .abs_diff(price)
+        .checked_mul(BPS_DENOMINATOR as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        .checked_div(reference)
+        .ok_or(ErrorCode::InvalidOraclePrice)?)
+}
+
+/// Whether a Pyth price's confidence interval is wider than `MAX_PRICE_CONFIDENCE_BPS` of the price.
+fn too_uncertain(price: &Price) -> Result<bool> {
+    let confidence_bps = (price.conf as u128)
+        .checked_mul(BPS_DENOMINATOR as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / price.price.unsigned_abs() as u128;
+    Ok(confidence_bps > MAX_PRICE_CONFIDENCE_BPS as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::tests::price_update;
+
+    const FEED_ID: [u8; 32] = [7u8; 32];
+    const NOW: i64 = 1_000;
+
+    /// The bytes of a Switchboard V2 aggregator whose latest confirmed round returned `mantissa * 10^-scale`.
+    fn aggregator(mantissa: i128, scale: u32, timestamp: i64) -> Vec<u8> {
+        let mut data = vec![0u8; SWITCHBOARD_MIN_LEN];
+        data[..8].copy_from_slice(&hash(b"account:AggregatorAccountData").to_bytes()[..8]);
+        data[SWITCHBOARD_NUM_SUCCESS_OFFSET..][..4].copy_from_slice(&1u32.to_le_bytes());
+        data[SWITCHBOARD_ROUND_OPEN_TIMESTAMP_OFFSET..][..8].copy_from_slice(&timestamp.to_le_bytes());
+        data[SWITCHBOARD_RESULT_MANTISSA_OFFSET..][..16].copy_from_slice(&mantissa.to_le_bytes());
+        data[SWITCHBOARD_RESULT_SCALE_OFFSET..][..4].copy_from_slice(&scale.to_le_bytes());
+        data
+    }
+
+    fn bank(secondary_feed: Pubkey) -> Bank {
+        Bank { price_feed_id: FEED_ID, secondary_feed, max_oracle_deviation_bps: 100, ..Default::default() }
+    }
+
+    fn clock() -> Clock {
+        Clock { unix_timestamp: NOW, ..Default::default() }
+    }
+
+    /// Runs `check` against an `AccountInfo` holding `data` and owned by `owner`.
+    fn with_account<T>(key: Pubkey, owner: Pubkey, mut data: Vec<u8>, check: impl FnOnce(&AccountInfo) -> T) -> T {
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        check(&info)
+    }
+
+    #[test]
+    fn reads_the_latest_confirmed_round() {
+        let data = aggregator(151_250_000_000, 9, 990);
+        let price = with_account(Pubkey::new_unique(), SWITCHBOARD_PROGRAM_ID, data, |info| SwitchboardPrice::load(info).unwrap());
+
+        assert_eq!(price, SwitchboardPrice { mantissa: 151_250_000_000, scale: 9, timestamp: 990 });
+        assert_eq!(price.price_no_older_than(&clock(), 60).unwrap(), 151_250_000);
+        assert_eq!(price.price_no_older_than(&clock(), 5).unwrap_err(), error!(ErrorCode::StalePriceFeed));
+    }
+
+    #[test]
+    fn rejects_accounts_that_are_not_aggregators() {
+        let key = Pubkey::new_unique();
+        let wrong_owner = with_account(key, crate::ID, aggregator(1, 0, NOW), |info| SwitchboardPrice::load(info).unwrap_err());
+        assert_eq!(wrong_owner, error!(ErrorCode::InvalidSecondaryFeed));
+
+        let mut data = aggregator(1, 0, NOW);
+        data[0] ^= 1;
+        let wrong_account = with_account(key, SWITCHBOARD_PROGRAM_ID, data, |info| SwitchboardPrice::load(info).unwrap_err());
+        assert_eq!(wrong_account, error!(ErrorCode::InvalidSecondaryFeed));
+
+        let mut data = aggregator(1, 0, NOW);
+        data[SWITCHBOARD_NUM_SUCCESS_OFFSET] = 0;
+        let unanswered = with_account(key, SWITCHBOARD_PROGRAM_ID, data, |info| SwitchboardPrice::load(info).unwrap_err());
+        assert_eq!(unanswered, error!(ErrorCode::StalePriceFeed));
+    }
+
+    #[test]
+    fn uses_pyth_while_its_price_is_fresh_and_certain() {
+        let key = Pubkey::new_unique();
+        let primary = price_update(FEED_ID, 150_00000000, -8, NOW);
+        let price = with_account(key, SWITCHBOARD_PROGRAM_ID, aggregator(151_000_000_000, 9, NOW), |info| {
+            fetch_price_with_fallback(&primary, Some(info), &bank(key), 60, &clock()).unwrap()
+        });
+        assert_eq!(price, OraclePrice { price: 150_000_000, source: PriceSource::Pyth });
+    }
+
+    #[test]
+    fn falls_back_to_switchboard_within_the_deviation_bound() {
+        let key = Pubkey::new_unique();
+        let stale = price_update(FEED_ID, 150_00000000, -8, NOW - 300);
+        let mut uncertain = price_update(FEED_ID, 150_00000000, -8, NOW);
+        uncertain.price_message.conf = 5_00000000; // $5 either way is over 2% of $150
+
+        for primary in [stale, uncertain] {
+            // $151 is 66 bps from Pyth's last $150, inside the bank's 100 bps.
+            let price = with_account(key, SWITCHBOARD_PROGRAM_ID, aggregator(151_000_000_000, 9, NOW), |info| {
+                fetch_price_with_fallback(&primary, Some(info), &bank(key), 60, &clock()).unwrap()
+            });
+            assert_eq!(price, OraclePrice { price: 151_000_000, source: PriceSource::Switchboard });
+        }
+    }
+
+    #[test]
+    fn rejects_a_fallback_that_disagrees_or_is_stale() {
+        let key = Pubkey::new_unique();
+        let stale = price_update(FEED_ID, 150_00000000, -8, NOW - 300);
+
+        // $152 is 133 bps from $150.
+        let disagrees = with_account(key, SWITCHBOARD_PROGRAM_ID, aggregator(152_000_000_000, 9, NOW), |info| {
+            fetch_price_with_fallback(&stale, Some(info), &bank(key), 60, &clock()).unwrap_err()
+        });
+        assert_eq!(disagrees, error!(ErrorCode::OracleDeviation));
+
+        let also_stale = with_account(key, SWITCHBOARD_PROGRAM_ID, aggregator(150_000_000_000, 9, NOW - 300), |info| {
+            fetch_price_with_fallback(&stale, Some(info), &bank(key), 60, &clock()).unwrap_err()
+        });
+        assert_eq!(also_stale, error!(ErrorCode::StalePriceFeed));
+    }
+
+    #[test]
+    fn never_falls_back_from_an_update_for_another_feed() {
+        let key = Pubkey::new_unique();
+        let other_feed = price_update([3u8; 32], 150_00000000, -8, NOW);
+        let err = with_account(key, SWITCHBOARD_PROGRAM_ID, aggregator(150_000_000_000, 9, NOW), |info| {
+            fetch_price_with_fallback(&other_feed, Some(info), &bank(key), 60, &clock()).unwrap_err()
+        });
+        assert_eq!(err, error!(ErrorCode::WrongPriceFeed));
+    }
+
+    #[test]
+    fn ignores_an_aggregator_the_bank_does_not_name() {
+        let stale = price_update(FEED_ID, 150_00000000, -8, NOW - 300);
+        let other = with_account(Pubkey::new_unique(), SWITCHBOARD_PROGRAM_ID, aggregator(150_000_000_000, 9, NOW), |info| {
+            fetch_price_with_fallback(&stale, Some(info), &bank(Pubkey::new_unique()), 60, &clock()).unwrap_err()
+        });
+        assert_eq!(other, error!(ErrorCode::StalePriceFeed));
+        assert_eq!(
+            fetch_price_with_fallback(&stale, None, &bank(Pubkey::default()), 60, &clock()).unwrap_err(),
+            error!(ErrorCode::StalePriceFeed)
+        );
+    }
+
+    #[test]
+    fn breaker_rejects_a_jump_within_the_window() {
+        let mut bank = Bank { max_price_deviation_bps: 1_000, price_deviation_window_seconds: 3_600, ..Default::default() };
+        bank.record_price(150_000_000, NOW - 60);
+
+        assert!(check_price_deviation(&bank, 160_000_000, NOW).is_ok());
+        assert!(check_price_deviation(&bank, 135_000_000, NOW).is_ok());
+        assert_eq!(check_price_deviation(&bank, 195_000_000, NOW).unwrap_err(), error!(ErrorCode::PriceDeviationTooHigh));
+        assert_eq!(check_price_deviation(&bank, 100_000_000, NOW).unwrap_err(), error!(ErrorCode::PriceDeviationTooHigh));
+    }
+
+    #[test]
+    fn breaker_is_off_when_disabled_unset_or_outside_the_window() {
+        let mut bank = Bank { price_deviation_window_seconds: 3_600, ..Default::default() };
+        bank.record_price(150_000_000, NOW - 60);
+        assert!(check_price_deviation(&bank, 300_000_000, NOW).is_ok());
+
+        bank.max_price_deviation_bps = 1_000;
+        assert!(check_price_deviation(&bank, 300_000_000, NOW + 3_540).is_err());
+        assert!(check_price_deviation(&bank, 300_000_000, NOW + 3_541).is_ok());
+
+        bank.record_price(0, NOW);
+        assert!(check_price_deviation(&bank, 300_000_000, NOW).is_ok());
+    }
+
+    const SOL_FEED: [u8; 32] = [1u8; 32];
+    const USDC_FEED: [u8; 32] = [2u8; 32];
+    const WINDOW: i64 = 600;
+    /// SOL's price in USDC as Raydium records it, a Q32 ratio of native units, with 1,000 SOL against 150,000 USDC.
+    const BALANCED_PRICE_X32: u128 = (150_000_000_000u128 << 32) / 1_000_000_000_000;
+    /// USDC fees the pool owes Raydium, which sit in its token 1 vault on top of the reserves.
+    const USDC_FEES: u64 = 500_000_000;
+
+    /// The accounts of a mock SOL/USDC Raydium CPMM pool whose LP token has 6 decimals.
+    struct LpPool {
+        pool: Pubkey,
+        observation: Pubkey,
+        vault_a: Pubkey,
+        vault_b: Pubkey,
+        lp_mint: Pubkey,
+    }
+
+    impl LpPool {
+        fn new() -> Self {
+            let key = Pubkey::new_unique;
+            Self { pool: key(), observation: key(), vault_a: key(), vault_b: key(), lp_mint: key() }
+        }
+
+        fn bank(&self) -> Bank {
+            Bank {
+                mint_address: self.lp_mint,
+                decimals: 6,
+                collateral_pricer: CollateralPricer::LpToken { reserve_a_feed: SOL_FEED, reserve_b_feed: USDC_FEED, pool: self.pool },
+                ..Default::default()
+            }
+        }
+
+        /// The pool holding `reserve_a` lamports and `reserve_b` micro-USDC, whose price averaged
+        /// `BALANCED_PRICE_X32` over a `window`-second window ending 10 seconds ago, with 1,000 LP tokens out.
+        fn accounts(&self, reserve_a: u64, reserve_b: u64, window: i64) -> Vec<(Pubkey, Pubkey, Vec<u8>)> {
+            let mut pool = vec![0u8; RAYDIUM_POOL_LEN];
+            pool[..8].copy_from_slice(&hash(b"account:PoolState").to_bytes()[..8]);
+            pool[RAYDIUM_POOL_TOKEN_0_VAULT_OFFSET..][..32].copy_from_slice(self.vault_a.as_ref());
+            pool[RAYDIUM_POOL_TOKEN_1_VAULT_OFFSET..][..32].copy_from_slice(self.vault_b.as_ref());
+            pool[RAYDIUM_POOL_LP_MINT_OFFSET..][..32].copy_from_slice(self.lp_mint.as_ref());
+            pool[RAYDIUM_POOL_OBSERVATION_KEY_OFFSET..][..32].copy_from_slice(self.observation.as_ref());
+            pool[RAYDIUM_POOL_MINT_0_DECIMALS_OFFSET] = 9;
+            pool[RAYDIUM_POOL_MINT_1_DECIMALS_OFFSET] = 6;
+            pool[RAYDIUM_POOL_FUND_FEES_TOKEN_1_OFFSET..][..8].copy_from_slice(&USDC_FEES.to_le_bytes());
+
+            // A running sum close enough to overflow that the window's end has wrapped past it.
+            let window_start_sum = u128::MAX - 5_000;
+            let mut observation = vec![0u8; RAYDIUM_OBSERVATION_STATE_LEN];
+            observation[..8].copy_from_slice(&hash(b"account:ObservationState").to_bytes()[..8]);
+            observation[RAYDIUM_OBSERVATION_INDEX_OFFSET..][..2].copy_from_slice(&1u16.to_le_bytes());
+            observation[RAYDIUM_OBSERVATION_POOL_ID_OFFSET..][..32].copy_from_slice(self.pool.as_ref());
+            for (index, (ts, sum)) in [
+                (NOW - 10 - window, window_start_sum),
+                (NOW - 10, window_start_sum.wrapping_add(BALANCED_PRICE_X32 * window as u128)),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let offset = RAYDIUM_OBSERVATIONS_OFFSET + index * RAYDIUM_OBSERVATION_SIZE;
+                observation[offset..][..8].copy_from_slice(&(ts as u64).to_le_bytes());
+                observation[offset + 8..][..16].copy_from_slice(&sum.to_le_bytes());
+            }
+
+            vec![
+                (self.pool, RAYDIUM_CPMM_PROGRAM_ID, pool),
+                (self.observation, RAYDIUM_CPMM_PROGRAM_ID, observation),
+                (self.vault_a, anchor_spl::token::ID, token_account(reserve_a)),
+                (self.vault_b, anchor_spl::token::ID, token_account(reserve_b + USDC_FEES)),
+                (self.lp_mint, anchor_spl::token::ID, mint(1_000_000_000, 6)),
+            ]
+        }
+    }
+
+    /// The bytes of an SPL token account holding `amount`.
+    fn token_account(amount: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 165];
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        data[108] = 1; // AccountState::Initialized
+        data
+    }
+
+    /// The bytes of an initialized SPL mint with `supply` out.
+    fn mint(supply: u64, decimals: u8) -> Vec<u8> {
+        let mut data = vec![0u8; 82];
+        data[36..44].copy_from_slice(&supply.to_le_bytes());
+        data[44] = decimals;
+        data[45] = 1; // is_initialized
+        data
+    }
+
+    /// Runs `check` against an `AccountInfo` for each `(key, owner, data)`.
+    fn with_accounts<T>(mut accounts: Vec<(Pubkey, Pubkey, Vec<u8>)>, check: impl FnOnce(&[AccountInfo]) -> T) -> T {
+        let mut lamports = vec![0u64; accounts.len()];
+        let infos: Vec<AccountInfo> = accounts
+            .iter_mut()
+            .zip(lamports.iter_mut())
+            .map(|((key, owner, data), lamports)| AccountInfo::new(key, false, false, lamports, data, owner, false, 0))
+            .collect();
+        check(&infos)
+    }
+
+    /// Prices the mock LP token of `bank` from `accounts`, with SOL at $150 and USDC at $1.
+    fn lp_price(bank: &Bank, accounts: Vec<(Pubkey, Pubkey, Vec<u8>)>) -> Result<u128> {
+        let sol = price_update(SOL_FEED, 150_00000000, -8, NOW);
+        let usdc = price_update(USDC_FEED, 1_00000000, -8, NOW);
+        with_accounts(accounts, |infos| fetch_lp_price(bank, &sol, &usdc, infos, 60, &clock()))
+    }
+
+    #[test]
+    fn prices_an_lp_token_from_its_reserve_feeds_and_pool() {
+        let pool = LpPool::new();
+        let bank = pool.bank();
+
+        // 1,000 SOL and 150,000 USDC are $300,000 of reserves, or $300 for each of the 1,000 LP tokens. The
+        // fees in the USDC vault aren't counted.
+        let balanced = pool.accounts(1_000_000_000_000, 150_000_000_000, WINDOW);
+        assert_eq!(lp_price(&bank, balanced).unwrap(), 300_000_000);
+
+        // A 1% swap moves the reserves to 1,010 SOL and 148,514.85 USDC, whose plain sum would rise to
+        // $300,015. Their product, and so the fair value of the pool, is unchanged up to rounding.
+        let swapped = pool.accounts(1_010_000_000_000, 148_514_851_485, WINDOW);
+        assert_eq!(lp_price(&bank, swapped).unwrap(), 299_999_999);
+    }
+
+    #[test]
+    fn lp_prices_go_through_prices_load() {
+        let pool = LpPool::new();
+        let bank = pool.bank();
+        let sol = price_update(SOL_FEED, 150_00000000, -8, NOW);
+        let usdc = price_update(USDC_FEED, 1_00000000, -8, NOW);
+
+        // Updates for the reserve feeds are the bank's own, rather than for some other asset.
+        let prices = with_accounts(pool.accounts(1_000_000_000_000, 150_000_000_000, WINDOW), |infos| {
+            crate::health::Prices::load(&[&sol, &usdc], infos, &clock(), 60, &[&bank], &[0; 32])
+        })
+        .unwrap();
+        assert_eq!(prices.for_mint(&pool.lp_mint).unwrap(), 300_000_000);
+    }
+
+    #[test]
+    fn rejects_a_moved_pool_a_short_window_or_the_wrong_accounts() {
+        let pool = LpPool::new();
+        let bank = pool.bank();
+        let (sol, usdc) = (1_000_000_000_000, 150_000_000_000);
+
+        // Swapped to 2,000 SOL and 75,000 USDC in the same transaction, far from the pool's average price.
+        let moved = pool.accounts(2_000_000_000_000, 75_000_000_000, WINDOW);
+        assert_eq!(lp_price(&bank, moved).unwrap_err(), error!(ErrorCode::LpPriceDeviation));
+
+        // A window a single swap could make up most of.
+        let short = pool.accounts(sol, usdc, 30);
+        assert_eq!(lp_price(&bank, short).unwrap_err(), error!(ErrorCode::LpWindowTooShort));
+
+        // A pool account some other program wrote, or a pool for a different LP token.
+        let mut forged = pool.accounts(sol, usdc, WINDOW);
+        forged[0].1 = Pubkey::new_unique();
+        assert_eq!(lp_price(&bank, forged).unwrap_err(), error!(ErrorCode::InvalidLpPool));
+        let mut foreign = pool.accounts(sol, usdc, WINDOW);
+        foreign[0].2[RAYDIUM_POOL_LP_MINT_OFFSET..][..32].copy_from_slice(Pubkey::new_unique().as_ref());
+        assert_eq!(lp_price(&bank, foreign).unwrap_err(), error!(ErrorCode::InvalidLpPool));
+
+        // The LP supply is read from the mint, so it has to be passed.
+        let mut without_mint = pool.accounts(sol, usdc, WINDOW);
+        without_mint.pop();
+        assert_eq!(lp_price(&bank, without_mint).unwrap_err(), error!(ErrorCode::InvalidLpPool));
+    }
+}
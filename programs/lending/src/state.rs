@@ -1,20 +1,71 @@
 use anchor_lang::prelude::*;
+use crate::constants::MAX_POSITIONS;
+use crate::error::ErrorCode;
+use crate::math::token_value_usd;
 
 #[account]
 #[derive(InitSpace)]
+pub struct Config {
+    /// Authority allowed to change protocol-wide settings
+    pub admin: Pubkey,
+    /// When true, state-changing instructions are halted
+    pub paused: bool,
+    /// Protocol fee in basis points
+    pub protocol_fee_bps: u16,
+    /// Wallet whose token accounts receive the fees swept by `collect_fees`
+    pub fee_recipient: Pubkey,
+    /// Number of users created so far, handed out as each new user's `position_nonce`
+    pub user_count: u64,
+    /// Admin proposed by `propose_admin`, who takes over once they sign `accept_admin`
+    pub pending_admin: Option<Pubkey>,
+    /// Least a liquidation's bonus is worth, on the `USD_EXPONENT` scale, as far as the collateral allows. 0 means no floor
+    pub min_liquidation_incentive_usd: u128,
+    /// Ramp the liquidation bonus up from when a position becomes liquidatable instead of offering it in full at once
+    pub auction_mode: bool,
+    /// Seconds an auction takes to ramp from each bank's `min_liquidation_bonus` to its `max_liquidation_bonus`
+    pub auction_duration_seconds: u64,
+    /// Pyth feed id of the asset health is valued in, read as its USD price. All zeros values health in USD
+    pub quote_feed_id: [u8; 32],
+    /// Most distinct assets one user may owe at once, bounding how many positions a health check prices. 0 means unlimited
+    pub max_borrowed_assets: u8,
+}
+
+/// Protocol-wide running totals, so analytics can read aggregate TVL and debt without scanning every bank.
+///
+/// Each deposit, withdrawal, borrow and repayment is valued at its bank's `last_price` as it happens and is
+/// never revalued, so the USD totals drift from the true ones as prices move, miss interest accrued since,
+/// and count a bank's tokens as worthless until it has recorded a price. Treat them as an approximation.
+#[account]
+#[derive(InitSpace, Default)]
+pub struct ProtocolStats {
+    /// Value of deposits less withdrawals, on the `USD_EXPONENT` scale
+    pub total_value_locked_usd: u128,
+    /// Value of borrows less repayments, on the `USD_EXPONENT` scale
+    pub total_debt_usd: u128,
+    /// Value of all debt ever repaid by liquidators, on the `USD_EXPONENT` scale
+    pub cumulative_liquidation_volume_usd: u128,
+    /// Timestamp of the last change to any total
+    pub last_updated: i64,
+}
+
+#[account]
+#[derive(InitSpace, Default)]
 pub struct Bank {
-    /// Authority to make changes to Bank State
+    /// The admin that created the bank. Changes to it are authorized by the config's current admin
+    /// instead, so they follow an admin transfer.
     pub authority: Pubkey,
     /// Mint address of the asset 
     pub mint_address: Pubkey,
+    /// Decimals of the asset's mint, checked against the mint passed alongside the bank
+    pub decimals: u8,
     /// Current number of tokens in the bank
     pub total_deposits: u64,
     /// Current number of deposit shares in the bank
     pub total_deposit_shares: u64,
-    // Current number of borrowed tokens in the bank
-    pub total_borrowed: u64,
+    /// Current number of borrowed tokens in the bank
+    pub total_borrows: u64,
     /// Current number of borrowed shares in the bank
-    pub total_borrowed_shares: u64,
+    pub total_borrow_shares: u64,
     /// LTV at which the loan is defined as under collateralized and can be liquidated 
     pub liquidation_threshold: u64,
     /// Bonus percentage of collateral that can be liquidated
@@ -23,37 +74,265 @@ pub struct Bank {
     pub liquidation_close_factor: u64,
     /// Max percentage of collateral that can be borrowed
     pub max_ltv: u64,
-    /// Last updated timestamp
+    /// Last updated timestamp, also the point interest was last accrued to
     pub last_updated: i64,
-    pub interest_rate: u64,
+    /// Borrow APR at 0% utilization, in basis points
+    pub base_rate: u64,
+    /// APR added between 0% and optimal utilization, in basis points
+    pub slope1: u64,
+    /// APR added between optimal and 100% utilization, in basis points
+    pub slope2: u64,
+    /// Utilization at which the rate curve kinks, in basis points
+    pub optimal_utilization: u64,
+    /// Ceiling on the borrow APR whatever the utilization, in basis points. 0 means uncapped
+    pub max_borrow_rate: u64,
+    /// Pyth price feed id of the asset, read when valuing positions in it
+    pub price_feed_id: [u8; 32],
+    /// Maximum age in seconds of an oracle price accepted for this asset
+    pub max_price_age_seconds: u64,
+    /// Switchboard aggregator read when the Pyth price is unusable. The default pubkey means none
+    pub secondary_feed: Pubkey,
+    /// How far the Switchboard price may be from Pyth's last price before it's rejected, in basis points
+    pub max_oracle_deviation_bps: u16,
+    /// Seconds a deposit must sit in the bank before it can be withdrawn. 0 means no cooldown
+    pub deposit_cooldown_seconds: u64,
+    /// Maximum total deposits this bank accepts, in native units. 0 means unlimited
+    pub supply_cap: u64,
+    /// Maximum total borrows this bank lends out, in native units. 0 means unlimited
+    pub borrow_cap: u64,
+    /// Share of accrued interest kept by the protocol instead of paid to lenders, in basis points
+    pub reserve_factor_bps: u16,
+    /// Interest set aside for the protocol and not yet withdrawn, in native units
+    pub total_reserves: u64,
+    /// Fee charged on flash loans, in basis points
+    pub flash_fee_bps: u16,
+    /// Fee added to the debt of every new borrow and kept as reserves, in basis points
+    pub origination_fee_bps: u16,
+    /// Principal of the flash loan currently in flight, 0 when none is
+    pub flash_loan_principal: u64,
+    /// Fee owed on the flash loan currently in flight
+    pub flash_loan_fee: u64,
+    /// Debt written off because its borrowers ran out of collateral, in native units
+    pub bad_debt: u64,
+    /// Most debt in this asset one liquidation may repay, on the `USD_EXPONENT` scale. 0 means unlimited
+    pub max_liquidation_value_usd: u128,
+    /// Last price accepted for this asset by a borrow or withdrawal, on the `USD_EXPONENT` scale
+    pub last_price: u128,
+    /// Timestamp `last_price` was recorded at
+    pub last_price_ts: i64,
+    /// Largest move from `last_price` a borrow or withdrawal accepts within the window, in basis points. 0 disables the breaker
+    pub max_price_deviation_bps: u16,
+    /// Seconds after `last_price_ts` during which the price-deviation breaker applies
+    pub price_deviation_window_seconds: u64,
+    /// Scale the liquidation bonus with how unhealthy the position is, instead of using `liquidation_bonus`
+    pub dynamic_bonus: bool,
+    /// Bonus percentage paid on a position just below a health factor of 1.0, when `dynamic_bonus` is set
+    pub min_liquidation_bonus: u64,
+    /// Bonus percentage paid once the health factor falls to `DYNAMIC_BONUS_FLOOR_HEALTH_BPS`, when `dynamic_bonus` is set
+    pub max_liquidation_bonus: u64,
+    /// Whether the asset may be borrowed. A deposit-only asset still counts as collateral
+    pub borrowing_enabled: bool,
+    /// Whether deposits of the asset count towards borrowing power and the liquidation threshold
+    pub usable_as_collateral: bool,
+    /// How far a USD stablecoin's price may drift from $1 before it is valued conservatively, in basis points. 0 means the asset isn't pegged
+    pub peg_deviation_bps: u16,
+    /// Smallest debt a user may hold in this asset, in native units, so no position is too small to liquidate. 0 means no minimum
+    pub min_borrow_amount: u64,
+    /// Growth of one unit of debt since the bank opened, on the `BORROW_INDEX_SCALE`. Starts at the scale itself
+    pub borrow_index: u128,
+    /// Least time between two interest accruals, so instructions in quick succession skip the work. 0 accrues every second
+    pub accrual_interval_seconds: u64,
+    /// How far below the liquidation threshold a borrow of this asset must leave the position, in basis points of its weighted collateral. 0 allows borrowing up to max LTV
+    pub safety_buffer_bps: u16,
+    /// How the asset is priced. An LP token is valued from its pool's reserves instead of a feed of its own
+    pub collateral_pricer: CollateralPricer,
+}
+
+/// How a bank's asset is priced when positions in it are valued.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CollateralPricer {
+    /// Priced from the bank's own `price_feed_id`, falling back to its Switchboard aggregator.
+    #[default]
+    DirectFeed,
+    /// The LP token of a Raydium CPMM pool, priced from the pool's reserves and the feeds of the two
+    /// assets they are held in, once its spot price agrees with its time-weighted price. See
+    /// `oracle::fetch_lp_price`.
+    LpToken {
+        /// Pyth feed id of the pool's token 0
+        reserve_a_feed: [u8; 32],
+        /// Pyth feed id of the pool's token 1
+        reserve_b_feed: [u8; 32],
+        /// The Raydium CPMM pool state account the LP token is minted by
+        pool: Pubkey,
+    },
+}
+
+impl Bank {
+    /// Whether a price update for `feed_id` is one this bank's asset is priced from.
+    pub fn is_priced_by(&self, feed_id: &[u8; 32]) -> bool {
+        match self.collateral_pricer {
+            CollateralPricer::DirectFeed => self.price_feed_id == *feed_id,
+            CollateralPricer::LpToken { reserve_a_feed, reserve_b_feed, .. } => {
+                reserve_a_feed == *feed_id || reserve_b_feed == *feed_id
+            }
+        }
+    }
+
+    /// Tokens not lent out, i.e. the most that can be borrowed right now.
+    pub fn available_liquidity(&self) -> u64 {
+        self.total_deposits.saturating_sub(self.total_borrows)
+    }
+
+    /// Remembers `price` as the reference the price-deviation breaker compares later prices against.
+    pub fn record_price(&mut self, price: u128, now: i64) {
+        self.last_price = price;
+        self.last_price_ts = now;
+    }
 }
 
 // Challenge: How would you update the user state to save "all_deposited_assets" and "all_borrowed_assets" to accommodate for several asset listings?  
+/// A user's deposit and borrow in a single asset.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, InitSpace, PartialEq, Eq)]
+pub struct Position {
+    /// Mint address of the asset
+    pub mint: Pubkey,
+    /// Principal the user has deposited in this asset's bank. Bookkeeping only: the deposit is worth
+    /// what `deposited_shares` redeem for, see `health::current_deposit`.
+    pub deposited: u64,
+    /// User's deposited shares in this asset's bank
+    pub deposited_shares: u64,
+    /// What the user owed this asset's bank when their debt last changed. What they owe now is this
+    /// grown by the bank's `borrow_index` since the position's `borrow_index` snapshot; see `health::current_debt`
+    pub borrowed: u64,
+    /// User's borrowed shares in this asset's bank, which keep the bank's `total_borrow_shares` in step
+    /// and value debt taken on before the borrow index existed
+    pub borrowed_shares: u64,
+    /// Timestamp of the user's latest deposit into this asset's bank, which starts its withdrawal cooldown
+    pub last_deposit_ts: i64,
+    /// The bank's `borrow_index` when `borrowed` was last set. Positions from before the index read 0
+    pub borrow_index: u128,
+}
+
+impl Position {
+    /// Records that the user owes `debt` as of the bank's current `borrow_index`.
+    pub fn set_debt(&mut self, debt: u64, borrow_index: u128) {
+        self.borrowed = debt;
+        self.borrow_index = borrow_index;
+    }
+}
+
 #[account]
-#[derive(InitSpace)]
+#[derive(InitSpace, Default)]
 pub struct User {
     /// Pubkey of the user's wallet 
     pub owner: Pubkey,
-    /// User's deposited tokens in the SOL bank
-    pub deposited_sol: u64,
-    /// User's deposited shares in the SOL bank 
-    pub deposited_sol_shares: u64,
-    /// User's borrowed tokens in the SOL bank
-    pub borrowed_sol: u64,
-    /// User's borrowed shares in the SOL bank
-    pub borrowed_sol_shares: u64, 
-    /// User's deposited tokens in the USDC bank
-    pub deposited_usdc: u64,
-    /// User's deposited shares in the USDC bank 
-    pub deposited_usdc_shares: u64, 
-    /// User's borrowed tokens in the USDC bank
-    pub borrowed_usdc: u64,
-    /// User's borrowed shares in the USDC bank
-    pub borrowed_usdc_shares: u64, 
+    /// One entry per asset the user has deposited or borrowed
+    #[max_len(MAX_POSITIONS)]
+    pub positions: Vec<Position>,
     /// USDC mint address
     pub usdc_address: Pubkey,
     /// Current health factor of the user
     pub health_factor: u64,
     /// Last updated timestamp
     pub last_updated: i64,
+    /// Whether the user's debt is backed only by `isolated_mint` instead of their whole portfolio
+    pub isolated: bool,
+    /// The only collateral asset counted while `isolated` is set
+    pub isolated_mint: Pubkey,
+    /// Slot the user account was created in
+    pub created_at: u64,
+    /// Order the user was created in, starting at 1. Users created before nonces existed read 0
+    pub position_nonce: u64,
+    /// Layout version the account was last written with, see `USER_VERSION`. Accounts from before versioning read 0
+    pub version: u8,
+    /// When the position was first seen liquidatable with `auction_mode` on, which starts its bonus ramp. 0 while no auction is running
+    pub auction_start_ts: i64,
+    /// Number of distinct assets the user currently owes, checked against the config's `max_borrowed_assets`
+    pub borrowed_asset_count: u8,
+}
+
+impl User {
+    /// Returns the user's position in `mint`, if they have one.
+    pub fn position(&self, mint: &Pubkey) -> Option<&Position> {
+        self.positions.iter().find(|position| position.mint == *mint)
+    }
+
+    /// Returns the user's existing position in `mint`.
+    pub fn position_mut(&mut self, mint: &Pubkey) -> Result<&mut Position> {
+        self.positions
+            .iter_mut()
+            .find(|position| position.mint == *mint)
+            .ok_or(error!(ErrorCode::PositionNotFound))
+    }
+
+    /// Returns the user's position in `mint`, opening an empty one if they don't have one yet.
+    pub fn position_or_insert(&mut self, mint: &Pubkey) -> Result<&mut Position> {
+        let index = match self.positions.iter().position(|position| position.mint == *mint) {
+            Some(index) => index,
+            None => {
+                require!(self.positions.len() < MAX_POSITIONS, ErrorCode::TooManyPositions);
+                self.positions.push(Position { mint: *mint, ..Default::default() });
+                self.positions.len() - 1
+            }
+        };
+        Ok(&mut self.positions[index])
+    }
+
+    /// Whether the user owes anything in any asset.
+    pub fn has_debt(&self) -> bool {
+        self.positions.iter().any(|position| position.borrowed_shares > 0)
+    }
+
+    /// Counts the assets the user owes from their positions, which `borrowed_asset_count` tracks as
+    /// debts are opened and cleared.
+    pub fn count_borrowed_assets(&self) -> u8 {
+        self.positions.iter().filter(|position| position.borrowed_shares > 0).count() as u8
+    }
+}
+
+impl ProtocolStats {
+    /// Adds `amount` of `bank`'s asset deposited into its vault.
+    pub fn record_deposit(&mut self, bank: &Bank, amount: u64, now: i64) -> Result<()> {
+        let value = token_value_usd(bank.last_price, amount, bank.decimals)?;
+        self.total_value_locked_usd = self.total_value_locked_usd.checked_add(value).ok_or(ErrorCode::ValueOverflow)?;
+        self.last_updated = now;
+        Ok(())
+    }
+
+    /// Removes `amount` of `bank`'s asset paid out of its vault. Saturates at zero, since the
+    /// deposit may have been counted at a lower price.
+    pub fn record_withdrawal(&mut self, bank: &Bank, amount: u64, now: i64) -> Result<()> {
+        let value = token_value_usd(bank.last_price, amount, bank.decimals)?;
+        self.total_value_locked_usd = self.total_value_locked_usd.saturating_sub(value);
+        self.last_updated = now;
+        Ok(())
+    }
+
+    /// Adds `debt` of `bank`'s asset newly owed.
+    pub fn record_borrow(&mut self, bank: &Bank, debt: u64, now: i64) -> Result<()> {
+        let value = token_value_usd(bank.last_price, debt, bank.decimals)?;
+        self.total_debt_usd = self.total_debt_usd.checked_add(value).ok_or(ErrorCode::ValueOverflow)?;
+        self.last_updated = now;
+        Ok(())
+    }
+
+    /// Removes `amount` of `bank`'s asset repaid. Saturates at zero, since repayments include interest
+    /// the borrow never counted.
+    pub fn record_repayment(&mut self, bank: &Bank, amount: u64, now: i64) -> Result<()> {
+        let value = token_value_usd(bank.last_price, amount, bank.decimals)?;
+        self.total_debt_usd = self.total_debt_usd.saturating_sub(value);
+        self.last_updated = now;
+        Ok(())
+    }
+
+    /// Adds a liquidation that repaid `amount` of `bank`'s asset. The repayment and the seized collateral
+    /// are recorded separately.
+    pub fn record_liquidation(&mut self, bank: &Bank, amount: u64, now: i64) -> Result<()> {
+        let value = token_value_usd(bank.last_price, amount, bank.decimals)?;
+        self.cumulative_liquidation_volume_usd = self.cumulative_liquidation_volume_usd
+            .checked_add(value)
+            .ok_or(ErrorCode::ValueOverflow)?;
+        self.last_updated = now;
+        Ok(())
+    }
 }
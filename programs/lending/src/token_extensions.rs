@@ -0,0 +1,107 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+    state::Mint,
+};
+use anchor_spl::token_interface;
+use crate::error::ErrorCode;
+
+/// Token-2022 mint extensions a bank may be created for. Anything else, such as a permanent delegate,
+/// a default frozen account state or a transfer hook, could let a third party freeze or move the
+/// vault's funds.
+pub const ALLOWED_MINT_EXTENSIONS: &[ExtensionType] = &[
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+];
+
+/// Rejects mints carrying any Token-2022 extension outside `ALLOWED_MINT_EXTENSIONS`.
+pub fn assert_safe_mint(mint: &InterfaceAccount<token_interface::Mint>) -> Result<()> {
+    let info = mint.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+    let data = info.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    check_extensions(&mint.get_extension_types()?)
+}
+
+fn check_extensions(extensions: &[ExtensionType]) -> Result<()> {
+    for extension in extensions {
+        if !ALLOWED_MINT_EXTENSIONS.contains(extension) {
+            msg!("Unsupported mint extension: {:?}", extension);
+            return err!(ErrorCode::UnsafeMintExtension);
+        }
+    }
+    Ok(())
+}
+
+/// Returns how much must leave a vault so that `net` arrives after `mint`'s transfer fee in `epoch`.
+///
+/// Legacy SPL mints and Token-2022 mints without the transfer-fee extension charge nothing, so `net`
+/// comes back unchanged.
+pub fn gross_up_for_transfer_fee(mint: &AccountInfo, net: u64, epoch: u64) -> Result<u64> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(net);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    match mint.get_extension::<TransferFeeConfig>() {
+        Ok(config) => gross_up(config, net, epoch),
+        Err(_) => Ok(net),
+    }
+}
+
+fn gross_up(config: &TransferFeeConfig, net: u64, epoch: u64) -> Result<u64> {
+    let fee = config.calculate_inverse_epoch_fee(epoch, net).ok_or(ErrorCode::MathOverflow)?;
+    Ok(net.checked_add(fee).ok_or(ErrorCode::MathOverflow)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spl_token_2022::extension::transfer_fee::TransferFee;
+
+    fn config(basis_points: u16, maximum_fee: u64) -> TransferFeeConfig {
+        let fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: maximum_fee.into(),
+            transfer_fee_basis_points: basis_points.into(),
+        };
+        TransferFeeConfig { older_transfer_fee: fee, newer_transfer_fee: fee, ..Default::default() }
+    }
+
+    #[test]
+    fn gross_amount_nets_the_requested_amount_after_the_fee() {
+        let config = config(100, u64::MAX); // 1%
+        let gross = gross_up(&config, 990_000, 0).unwrap();
+
+        assert_eq!(gross, 1_000_000);
+        assert_eq!(gross - config.calculate_epoch_fee(0, gross).unwrap(), 990_000);
+    }
+
+    #[test]
+    fn fee_is_capped_at_the_maximum() {
+        let config = config(100, 500);
+        assert_eq!(gross_up(&config, 10_000_000, 0).unwrap(), 10_000_500);
+    }
+
+    #[test]
+    fn no_fee_leaves_the_amount_unchanged() {
+        assert_eq!(gross_up(&config(0, 0), 1_000_000, 0).unwrap(), 1_000_000);
+    }
+
+    #[test]
+    fn allows_plain_and_transfer_fee_mints() {
+        assert!(check_extensions(&[]).is_ok());
+        assert!(check_extensions(&[ExtensionType::TransferFeeConfig, ExtensionType::MetadataPointer]).is_ok());
+    }
+
+    #[test]
+    fn rejects_extensions_that_can_freeze_or_move_funds() {
+        assert!(check_extensions(&[ExtensionType::PermanentDelegate]).is_err());
+        assert!(check_extensions(&[ExtensionType::DefaultAccountState]).is_err());
+        assert!(check_extensions(&[ExtensionType::TransferFeeConfig, ExtensionType::TransferHook]).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, TransferChecked};
+use crate::error::ErrorCode;
+
+/// Anchor only checks a `token_program` is one of the known token programs, so it is also pinned to
+/// the program that owns `mint`, and a transfer is never handed to a program other than the one
+/// holding the tokens.
+fn require_mint_owner(token_program: &AccountInfo, mint: &AccountInfo) -> Result<()> {
+    require_keys_eq!(token_program.key(), *mint.owner, ErrorCode::TokenProgramMismatch);
+    Ok(())
+}
+
+/// Pays `amount` of `mint` out of the bank's vault to `to`, with the vault PDA (bump `vault_bump`)
+/// signing the transfer. Every payout from a vault goes through here, so its seeds are only ever
+/// assembled in one place.
+pub(crate) fn transfer_from_vault<'info>(
+    token_program: AccountInfo<'info>,
+    vault: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    vault_bump: u8,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    require_mint_owner(&token_program, &mint)?;
+
+    let mint_key = mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", mint_key.as_ref(), &[vault_bump]]];
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program,
+            TransferChecked {
+                from: vault.clone(),
+                mint,
+                to,
+                authority: vault,
+            },
+        ).with_signer(signer_seeds),
+        amount,
+        decimals,
+    )
+}
+
+/// Moves `amount` of `mint` from `from` into the bank's vault, signed by `authority`, the owner of
+/// `from`. With a Token-2022 transfer fee the vault receives less than `amount`.
+pub(crate) fn transfer_to_vault<'info>(
+    token_program: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    vault: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    require_mint_owner(&token_program, &mint)?;
+
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program,
+            TransferChecked {
+                from,
+                mint,
+                to: vault,
+                authority,
+            },
+        ),
+        amount,
+        decimals,
+    )
+}
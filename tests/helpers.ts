@@ -0,0 +1,517 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN, EventParser } from "@coral-xyz/anchor";
+import { Keypair, PublicKey, SystemProgram, Transaction } from "@solana/web3.js";
+import {
+  ACCOUNT_SIZE,
+  AccountLayout,
+  ExtensionType,
+  MINT_SIZE,
+  MintLayout,
+  NATIVE_MINT,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccountIdempotentInstruction,
+  createInitializeMintInstruction,
+  createInitializePermanentDelegateInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createMintToInstruction,
+  getAssociatedTokenAddressSync,
+  getMintLen,
+} from "@solana/spl-token";
+import { BankrunProvider, startAnchor } from "anchor-bankrun";
+import { Clock, ProgramTestContext } from "solana-bankrun";
+import { createHash } from "crypto";
+import { Lending } from "../target/types/lending";
+import IDL from "../target/idl/lending.json";
+
+export const PYTH_RECEIVER_PROGRAM_ID = new PublicKey(
+  "rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ"
+);
+
+export const SWITCHBOARD_PROGRAM_ID = new PublicKey(
+  "SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f"
+);
+
+export const SOL_MINT = NATIVE_MINT;
+export const USDC_MINT = new PublicKey(
+  "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+);
+
+export const SOL_USD_FEED_ID =
+  "0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+export const USDC_USD_FEED_ID =
+  "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
+
+export const LAMPORTS_PER_SOL = 1_000_000_000;
+export const USDC_UNIT = 1_000_000;
+// Mirrors `MIN_INITIAL_DEPOSIT` and `DEAD_SHARES` in constants.rs.
+export const MIN_INITIAL_DEPOSIT = 100_000;
+export const DEAD_SHARES = 1_000;
+
+export type TestEnv = {
+  context: ProgramTestContext;
+  provider: BankrunProvider;
+  program: Program<Lending>;
+  payer: Keypair;
+};
+
+/// Boots a fresh bankrun instance with the lending program and both supported mints.
+export async function setupEnv(): Promise<TestEnv> {
+  const context = await startAnchor("", [], []);
+  const provider = new BankrunProvider(context);
+  anchor.setProvider(provider);
+  const program = new Program<Lending>(IDL as Lending, provider);
+  const payer = context.payer;
+
+  setMint(context, SOL_MINT, 9, payer.publicKey);
+  setMint(context, USDC_MINT, 6, payer.publicKey);
+
+  return { context, provider, program, payer };
+}
+
+export function setMint(
+  context: ProgramTestContext,
+  address: PublicKey,
+  decimals: number,
+  authority: PublicKey
+) {
+  const data = Buffer.alloc(MINT_SIZE);
+  MintLayout.encode(
+    {
+      mintAuthorityOption: 1,
+      mintAuthority: authority,
+      supply: BigInt(0),
+      decimals,
+      isInitialized: true,
+      freezeAuthorityOption: 0,
+      freezeAuthority: PublicKey.default,
+    },
+    data
+  );
+  context.setAccount(address, {
+    lamports: 1_000_000_000,
+    data,
+    owner: TOKEN_PROGRAM_ID,
+    executable: false,
+  });
+}
+
+/// Writes a funded token account at the owner's ATA. Native-mint accounts are backed by lamports.
+export function setTokenAccount(
+  context: ProgramTestContext,
+  mint: PublicKey,
+  owner: PublicKey,
+  amount: number | bigint,
+  delegate?: PublicKey
+): PublicKey {
+  const address = getAssociatedTokenAddressSync(mint, owner, true);
+  const rent = BigInt(2_039_280);
+  const isNative = mint.equals(NATIVE_MINT);
+  const data = Buffer.alloc(ACCOUNT_SIZE);
+  AccountLayout.encode(
+    {
+      mint,
+      owner,
+      amount: BigInt(amount),
+      delegateOption: delegate ? 1 : 0,
+      delegate: delegate ?? PublicKey.default,
+      state: 1,
+      isNativeOption: isNative ? 1 : 0,
+      isNative: isNative ? rent : BigInt(0),
+      delegatedAmount: delegate ? BigInt(amount) : BigInt(0),
+      closeAuthorityOption: 0,
+      closeAuthority: PublicKey.default,
+    },
+    data
+  );
+  context.setAccount(address, {
+    lamports: Number(rent + (isNative ? BigInt(amount) : BigInt(0))),
+    data,
+    owner: TOKEN_PROGRAM_ID,
+    executable: false,
+  });
+  return address;
+}
+
+/// Writes a fully-verified Pyth `PriceUpdateV2` account for the given feed.
+export function setPriceUpdate(
+  context: ProgramTestContext,
+  address: PublicKey,
+  feedIdHex: string,
+  price: number | bigint,
+  exponent: number,
+  publishTime: number | bigint
+) {
+  const discriminator = createHash("sha256")
+    .update("account:PriceUpdateV2")
+    .digest()
+    .subarray(0, 8);
+  const data = Buffer.alloc(134);
+  let offset = 0;
+  discriminator.copy(data, offset);
+  offset += 8;
+  PublicKey.default.toBuffer().copy(data, offset); // write_authority
+  offset += 32;
+  data.writeUInt8(1, offset); // VerificationLevel::Full
+  offset += 1;
+  Buffer.from(feedIdHex.replace(/^0x/, ""), "hex").copy(data, offset);
+  offset += 32;
+  data.writeBigInt64LE(BigInt(price), offset); // price
+  offset += 8;
+  data.writeBigUInt64LE(BigInt(0), offset); // conf
+  offset += 8;
+  data.writeInt32LE(exponent, offset);
+  offset += 4;
+  data.writeBigInt64LE(BigInt(publishTime), offset); // publish_time
+  offset += 8;
+  data.writeBigInt64LE(BigInt(publishTime), offset); // prev_publish_time
+  offset += 8;
+  data.writeBigInt64LE(BigInt(price), offset); // ema_price
+  offset += 8;
+  data.writeBigUInt64LE(BigInt(0), offset); // ema_conf
+  offset += 8;
+  data.writeBigUInt64LE(BigInt(0), offset); // posted_slot
+  context.setAccount(address, {
+    lamports: 1_000_000_000,
+    data,
+    owner: PYTH_RECEIVER_PROGRAM_ID,
+    executable: false,
+  });
+}
+
+/// Writes a Switchboard V2 aggregator whose latest confirmed round returned `mantissa * 10^-scale`.
+/// Only the fields the program reads are filled in; the offsets mirror oracle.rs.
+export function setSwitchboardFeed(
+  context: ProgramTestContext,
+  address: PublicKey,
+  mantissa: bigint,
+  scale: number,
+  roundOpenTimestamp: number | bigint
+) {
+  const data = Buffer.alloc(386);
+  createHash("sha256")
+    .update("account:AggregatorAccountData")
+    .digest()
+    .subarray(0, 8)
+    .copy(data, 0);
+  data.writeUInt32LE(1, 341); // latest_confirmed_round.num_success
+  data.writeBigInt64LE(BigInt(roundOpenTimestamp), 358); // latest_confirmed_round.round_open_timestamp
+  data.writeBigInt64LE(BigInt.asIntN(64, mantissa), 366); // result.mantissa, low half
+  data.writeBigInt64LE(mantissa >> BigInt(64), 374); // result.mantissa, high half
+  data.writeUInt32LE(scale, 382); // result.scale
+  context.setAccount(address, {
+    lamports: 1_000_000_000,
+    data,
+    owner: SWITCHBOARD_PROGRAM_ID,
+    executable: false,
+  });
+}
+
+/// Remaining accounts passing extra price updates to an instruction, for feeds not in its `priceUpdate`.
+export function priceUpdateAccounts(...updates: PublicKey[]) {
+  return updates.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }));
+}
+
+export async function now(context: ProgramTestContext): Promise<bigint> {
+  const clock = await context.banksClient.getClock();
+  return clock.unixTimestamp;
+}
+
+export async function warpForward(
+  context: ProgramTestContext,
+  seconds: number
+) {
+  const clock = await context.banksClient.getClock();
+  context.setClock(
+    new Clock(
+      clock.slot + BigInt(1),
+      clock.epochStartTimestamp,
+      clock.epoch,
+      clock.leaderScheduleEpoch,
+      clock.unixTimestamp + BigInt(seconds)
+    )
+  );
+}
+
+export function fundedKeypair(context: ProgramTestContext): Keypair {
+  const keypair = Keypair.generate();
+  context.setAccount(keypair.publicKey, {
+    lamports: 100 * LAMPORTS_PER_SOL,
+    data: Buffer.alloc(0),
+    owner: SystemProgram.programId,
+    executable: false,
+  });
+  return keypair;
+}
+
+export function findConfig(program: Program<Lending>) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  )[0];
+}
+
+export function findProtocolStats(program: Program<Lending>) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("stats")],
+    program.programId
+  )[0];
+}
+
+export function findUserAccount(program: Program<Lending>, owner: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [owner.toBuffer()],
+    program.programId
+  )[0];
+}
+
+export function findBank(program: Program<Lending>, mint: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [mint.toBuffer()],
+    program.programId
+  )[0];
+}
+
+export function findTreasury(program: Program<Lending>, mint: PublicKey) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury"), mint.toBuffer()],
+    program.programId
+  )[0];
+}
+
+export async function initConfig(env: TestEnv, protocolFeeBps = 0) {
+  await env.program.methods
+    .initialize(protocolFeeBps)
+    .accounts({ signer: env.payer.publicKey })
+    .rpc();
+}
+
+export async function setPaused(env: TestEnv, paused: boolean) {
+  await env.program.methods
+    .setPaused(paused)
+    .accounts({ signer: env.payer.publicKey })
+    .rpc();
+}
+
+export type BankParams = {
+  liquidationThreshold: BN;
+  liquidationBonus: BN;
+  liquidationCloseFactor: BN;
+  maxLtv: BN;
+  baseRate: BN;
+  slope1: BN;
+  slope2: BN;
+  optimalUtilization: BN;
+  maxBorrowRate: BN;
+};
+
+export function bankParams(overrides: Partial<BankParams> = {}): BankParams {
+  return {
+    liquidationThreshold: new BN(80),
+    liquidationBonus: new BN(5),
+    liquidationCloseFactor: new BN(50),
+    maxLtv: new BN(75),
+    baseRate: new BN(200),
+    slope1: new BN(400),
+    slope2: new BN(6_000),
+    optimalUtilization: new BN(8_000),
+    maxBorrowRate: new BN(0),
+    ...overrides,
+  };
+}
+
+/// Feed id a test bank for `mint` is priced by when none is given: SOL's for SOL, USDC's for anything else.
+export function defaultFeedId(mint: PublicKey): string {
+  return mint.equals(SOL_MINT) ? SOL_USD_FEED_ID : USDC_USD_FEED_ID;
+}
+
+export async function initBank(
+  env: TestEnv,
+  mint: PublicKey,
+  overrides: Partial<BankParams> = {},
+  tokenProgram: PublicKey = TOKEN_PROGRAM_ID,
+  feedId: string = defaultFeedId(mint)
+) {
+  await env.program.methods
+    .initBank(feedId, bankParams(overrides))
+    .accounts({
+      signer: env.payer.publicKey,
+      mint,
+      tokenProgram,
+    })
+    .rpc();
+}
+
+export async function setCaps(
+  env: TestEnv,
+  mint: PublicKey,
+  supplyCap: number,
+  borrowCap: number
+) {
+  await env.program.methods
+    .setCaps(new BN(supplyCap), new BN(borrowCap))
+    .accounts({ signer: env.payer.publicKey, mint })
+    .rpc();
+}
+
+/// Overwrites fields of `mint`'s bank in place, for states no sequence of instructions reaches directly.
+export async function overwriteBank(env: TestEnv, mint: PublicKey, fields: Record<string, unknown>) {
+  const address = findBank(env.program, mint);
+  const account = await env.context.banksClient.getAccount(address);
+  const bank = env.program.coder.accounts.decode("bank", Buffer.from(account.data));
+  const data = await env.program.coder.accounts.encode("bank", { ...bank, ...fields });
+  env.context.setAccount(address, {
+    lamports: account.lamports,
+    data,
+    owner: account.owner,
+    executable: false,
+  });
+}
+
+export async function initUser(env: TestEnv, user: Keypair) {
+  await env.program.methods
+    .initUser(USDC_MINT)
+    .accounts({ signer: user.publicKey })
+    .signers([user])
+    .rpc();
+}
+
+/// Creates a user with a funded ATA for `mint` and deposits `amount` into its bank.
+export async function depositFor(
+  env: TestEnv,
+  user: Keypair,
+  mint: PublicKey,
+  amount: number
+) {
+  setTokenAccount(env.context, mint, user.publicKey, amount);
+  await env.program.methods
+    .deposit(new BN(amount))
+    .accounts({
+      signer: user.publicKey,
+      mint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .signers([user])
+    .rpc();
+}
+
+/// Makes the first deposit into `mint`'s bank from a throwaway user, so the users under test get
+/// shares 1:1 instead of paying for the bank's dead shares.
+export async function seedBank(env: TestEnv, mint: PublicKey) {
+  const seeder = fundedKeypair(env.context);
+  await initUser(env, seeder);
+  await depositFor(env, seeder, mint, MIN_INITIAL_DEPOSIT);
+}
+
+/// Whether the program was built with the `debug` feature, which adds `reconcile_bank`.
+export function hasReconcileBank(env: TestEnv): boolean {
+  return "reconcileBank" in env.program.methods;
+}
+
+/// Returns how far `mint`'s vault balance is from what its bank's totals say it should hold.
+/// Only callable when `hasReconcileBank` is true.
+export async function reconcileBank(env: TestEnv, mint: PublicKey): Promise<number> {
+  // Untyped because the generated IDL types only include `reconcile_bank` in a `debug` build.
+  const methods = env.program.methods as any;
+  const discrepancy = await methods.reconcileBank().accounts({ mint }).view();
+  return discrepancy.toNumber();
+}
+
+/// Sends `tx` directly through the banks client and returns the program events it emitted.
+export async function sendAndParseEvents(
+  env: TestEnv,
+  tx: Transaction,
+  signers: Keypair[] = []
+) {
+  const [blockhash] = await env.context.banksClient.getLatestBlockhash();
+  tx.recentBlockhash = blockhash;
+  tx.feePayer = env.payer.publicKey;
+  tx.sign(env.payer, ...signers);
+  const meta = await env.context.banksClient.processTransaction(tx);
+  const parser = new EventParser(env.program.programId, env.program.coder);
+  return [...parser.parseLogs(meta.logMessages)];
+}
+
+/// Returns `account`'s position in `mint`, or an empty one if it has never touched that asset.
+export function positionOf(
+  account: {
+    positions: { mint: PublicKey; deposited: BN; depositedShares: BN; borrowed: BN; borrowedShares: BN; borrowIndex: BN }[];
+  },
+  mint: PublicKey
+) {
+  const empty = new BN(0);
+  return (
+    account.positions.find((position) => position.mint.equals(mint)) ?? {
+      mint,
+      deposited: empty,
+      depositedShares: empty,
+      borrowed: empty,
+      borrowedShares: empty,
+      borrowIndex: empty,
+    }
+  );
+}
+
+/// Creates a Token-2022 mint that charges `feeBps` on every transfer, and mints `amount` of it to
+/// `owner`'s associated token account.
+export async function createTransferFeeMint(
+  env: TestEnv,
+  owner: PublicKey,
+  decimals: number,
+  feeBps: number,
+  amount: number
+): Promise<PublicKey> {
+  const mint = Keypair.generate();
+  const payer = env.payer.publicKey;
+  const space = getMintLen([ExtensionType.TransferFeeConfig]);
+  const rent = await env.context.banksClient.getRent();
+  const ata = getAssociatedTokenAddressSync(mint.publicKey, owner, false, TOKEN_2022_PROGRAM_ID);
+
+  const tx = new Transaction().add(
+    SystemProgram.createAccount({
+      fromPubkey: payer,
+      newAccountPubkey: mint.publicKey,
+      space,
+      lamports: Number(rent.minimumBalance(BigInt(space))),
+      programId: TOKEN_2022_PROGRAM_ID,
+    }),
+    createInitializeTransferFeeConfigInstruction(
+      mint.publicKey,
+      payer,
+      payer,
+      feeBps,
+      BigInt(Number.MAX_SAFE_INTEGER),
+      TOKEN_2022_PROGRAM_ID
+    ),
+    createInitializeMintInstruction(mint.publicKey, decimals, payer, null, TOKEN_2022_PROGRAM_ID),
+    createAssociatedTokenAccountIdempotentInstruction(payer, ata, owner, mint.publicKey, TOKEN_2022_PROGRAM_ID),
+    createMintToInstruction(mint.publicKey, ata, payer, amount, [], TOKEN_2022_PROGRAM_ID)
+  );
+  await sendAndParseEvents(env, tx, [mint]);
+  return mint.publicKey;
+}
+
+/// Creates a Token-2022 mint whose permanent delegate (the payer) can move any holder's tokens.
+export async function createPermanentDelegateMint(
+  env: TestEnv,
+  decimals: number
+): Promise<PublicKey> {
+  const mint = Keypair.generate();
+  const payer = env.payer.publicKey;
+  const space = getMintLen([ExtensionType.PermanentDelegate]);
+  const rent = await env.context.banksClient.getRent();
+
+  const tx = new Transaction().add(
+    SystemProgram.createAccount({
+      fromPubkey: payer,
+      newAccountPubkey: mint.publicKey,
+      space,
+      lamports: Number(rent.minimumBalance(BigInt(space))),
+      programId: TOKEN_2022_PROGRAM_ID,
+    }),
+    createInitializePermanentDelegateInstruction(mint.publicKey, payer, TOKEN_2022_PROGRAM_ID),
+    createInitializeMintInstruction(mint.publicKey, decimals, payer, null, TOKEN_2022_PROGRAM_ID)
+  );
+  await sendAndParseEvents(env, tx, [mint]);
+  return mint.publicKey;
+}